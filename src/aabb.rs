@@ -0,0 +1,126 @@
+use ray::Ray;
+use vector::Vector3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+impl Aabb {
+    pub fn new(min: Vector3, max: Vector3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    // An inverted box: merging anything into it yields that thing's box
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vector3::new(f64::MAX, f64::MAX, f64::MAX),
+            max: Vector3::new(-f64::MAX, -f64::MAX, -f64::MAX),
+        }
+    }
+
+    pub fn min(&self) -> Vector3 {
+        self.min
+    }
+
+    pub fn max(&self) -> Vector3 {
+        self.max
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let origin: Vector3 = ray.origin();
+        let direction: Vector3 = ray.direction();
+        let mut t_min: f64 = t_min;
+        let mut t_max: f64 = t_max;
+        for axis in 0..3 {
+            let inv_d: f64 = 1.0 / direction.axis(axis);
+            let mut t0: f64 = (self.min.axis(axis) - origin.axis(axis)) * inv_d;
+            let mut t1: f64 = (self.max.axis(axis) - origin.axis(axis)) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // Written so that a NaN slab (origin on the plane, zero direction)
+            // leaves the interval untouched instead of rejecting the ray
+            if t0 > t_min {
+                t_min = t0;
+            }
+            if t1 < t_max {
+                t_max = t1;
+            }
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn surrounding_box(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vector3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Vector3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn include_point(&self, p: &Vector3) -> Aabb {
+        self.surrounding_box(&Aabb::new(*p, *p))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn centroid(&self) -> Vector3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let d: Vector3 = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let d: Vector3 = self.max - self.min;
+        if d.x >= d.y && d.x >= d.z {
+            0
+        } else if d.y >= d.z {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+#[test]
+fn test_aabb_hit() {
+    let bbox: Aabb = Aabb::new(Vector3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0));
+    let towards: Ray = Ray::new(Vector3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    let away: Ray = Ray::new(Vector3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, 1.0));
+    let beside: Ray = Ray::new(Vector3::new(2.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(bbox.hit(&towards, 0.001, f64::MAX));
+    assert!(!bbox.hit(&towards, 0.001, 3.0));
+    assert!(!bbox.hit(&away, 0.001, f64::MAX));
+    assert!(!bbox.hit(&beside, 0.001, f64::MAX));
+}
+
+#[test]
+fn test_aabb_surrounding() {
+    let a: Aabb = Aabb::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 1.0));
+    let b: Aabb = Aabb::new(Vector3::new(-1.0, 0.5, 0.0), Vector3::new(0.5, 2.0, 1.0));
+    let c: Aabb = a.surrounding_box(&b);
+    assert_eq!(c.min(), Vector3::new(-1.0, 0.0, 0.0));
+    assert_eq!(c.max(), Vector3::new(1.0, 2.0, 1.0));
+    assert_eq!(c.surface_area(), 2.0 * (2.0 * 2.0 + 2.0 * 1.0 + 1.0 * 2.0));
+    assert_eq!(Aabb::empty().surrounding_box(&a), a);
+}
@@ -0,0 +1,45 @@
+use environment::Environment;
+use vector::Vector3;
+
+// What a ray sees when it leaves the scene without hitting anything
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Background {
+    // Blend between the two colors on the y component of the direction
+    Gradient { bottom: Vector3, top: Vector3 },
+    Solid(Vector3),
+}
+
+impl Background {
+    pub fn sky() -> Background {
+        Background::Gradient {
+            bottom: Vector3::new(1.0, 1.0, 1.0),
+            top: Vector3::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+impl Environment for Background {
+    fn radiance(&self, direction: &Vector3) -> Vector3 {
+        match *self {
+            Background::Gradient { bottom, top } => {
+                let unit_direction = direction.unit_vector();
+                let t = 0.5 * (unit_direction.y + 1.0);
+                (1.0 - t) * bottom + t * top
+            }
+            Background::Solid(color) => color,
+        }
+    }
+}
+
+impl Default for Background {
+    fn default() -> Background {
+        Background::sky()
+    }
+}
+
+#[test]
+fn test_background() {
+    let up: Vector3 = Vector3::new(0.0, 2.0, 0.0);
+    assert_eq!(Background::sky().radiance(&up), Vector3::new(0.5, 0.7, 1.0));
+    assert_eq!(Background::Solid(Vector3::new(0.0, 0.0, 0.0)).radiance(&up), Vector3::new(0.0, 0.0, 0.0));
+}
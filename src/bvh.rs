@@ -0,0 +1,364 @@
+use aabb::Aabb;
+use hitable::{HitRecord, Hittable, SharedHittable};
+use hitable_list::HittableList;
+use ray::Ray;
+use vector::Vector3;
+
+// Number of buckets the centroid range is split into when evaluating the SAH
+const SAH_BUCKETS: usize = 16;
+// Relative cost of visiting a node compared to intersecting a primitive
+const TRAVERSAL_COST: f64 = 0.125;
+const MAX_LEAF_SIZE: usize = 4;
+
+struct Primitive {
+    // Position in the source list, used to break ties the same way the list does
+    index: usize,
+    object: SharedHittable,
+    bbox: Aabb,
+    centroid: Vector3,
+}
+
+enum BvhContents {
+    Leaf(Vec<(usize, SharedHittable)>),
+    Branch {
+        axis: usize,
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+    },
+}
+
+pub struct BvhNode {
+    bbox: Aabb,
+    contents: BvhContents,
+    // Objects without a bounding box (only ever filled in on the root)
+    unbounded: Vec<(usize, SharedHittable)>,
+}
+
+impl BvhNode {
+    pub fn new(list: HittableList) -> BvhNode {
+        let mut primitives: Vec<Primitive> = Vec::new();
+        let mut unbounded: Vec<(usize, SharedHittable)> = Vec::new();
+        for (index, object) in list.list.into_iter().enumerate() {
+            let mut bbox: Aabb = Aabb::empty();
+            if object.bounding_box(&mut bbox) {
+                primitives.push(Primitive {
+                    index,
+                    object,
+                    bbox,
+                    centroid: bbox.centroid(),
+                });
+            } else {
+                unbounded.push((index, object));
+            }
+        }
+        let mut root: BvhNode = BvhNode::build(primitives);
+        root.unbounded = unbounded;
+        root
+    }
+
+    fn leaf(bbox: Aabb, primitives: Vec<Primitive>) -> BvhNode {
+        BvhNode {
+            bbox,
+            contents: BvhContents::Leaf(primitives.into_iter().map(|p| (p.index, p.object)).collect()),
+            unbounded: Vec::new(),
+        }
+    }
+
+    fn build(primitives: Vec<Primitive>) -> BvhNode {
+        let bbox: Aabb = primitives
+            .iter()
+            .fold(Aabb::empty(), |acc, p| acc.surrounding_box(&p.bbox));
+        if primitives.len() <= 1 {
+            return BvhNode::leaf(bbox, primitives);
+        }
+
+        let centroid_box: Aabb = primitives
+            .iter()
+            .fold(Aabb::empty(), |acc, p| acc.include_point(&p.centroid));
+        let axis: usize = centroid_box.longest_axis();
+        let lo: f64 = centroid_box.min().axis(axis);
+        let extent: f64 = centroid_box.max().axis(axis) - lo;
+
+        if extent <= 0.0 {
+            // Every centroid coincides, no plane can separate them
+            if primitives.len() <= MAX_LEAF_SIZE {
+                return BvhNode::leaf(bbox, primitives);
+            }
+            let mut left: Vec<Primitive> = primitives;
+            let right: Vec<Primitive> = left.split_off(left.len() / 2);
+            return BvhNode::branch(bbox, axis, left, right);
+        }
+
+        let bucket_of = |p: &Primitive| -> usize {
+            let b: usize = (SAH_BUCKETS as f64 * (p.centroid.axis(axis) - lo) / extent) as usize;
+            b.min(SAH_BUCKETS - 1)
+        };
+
+        let mut counts: [usize; SAH_BUCKETS] = [0; SAH_BUCKETS];
+        let mut bounds: [Aabb; SAH_BUCKETS] = [Aabb::empty(); SAH_BUCKETS];
+        for p in &primitives {
+            let b: usize = bucket_of(p);
+            counts[b] += 1;
+            bounds[b] = bounds[b].surrounding_box(&p.bbox);
+        }
+
+        // Cost of splitting after each bucket, relative to intersecting every primitive
+        let mut best_split: usize = 0;
+        let mut best_cost: f64 = f64::MAX;
+        for split in 0..SAH_BUCKETS - 1 {
+            let mut left_box: Aabb = Aabb::empty();
+            let mut right_box: Aabb = Aabb::empty();
+            let mut left_count: usize = 0;
+            let mut right_count: usize = 0;
+            for b in 0..=split {
+                left_box = left_box.surrounding_box(&bounds[b]);
+                left_count += counts[b];
+            }
+            for b in split + 1..SAH_BUCKETS {
+                right_box = right_box.surrounding_box(&bounds[b]);
+                right_count += counts[b];
+            }
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+            let cost: f64 = TRAVERSAL_COST
+                + (left_box.surface_area() * left_count as f64
+                    + right_box.surface_area() * right_count as f64)
+                    / bbox.surface_area();
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = split;
+            }
+        }
+
+        if primitives.len() <= MAX_LEAF_SIZE && best_cost >= primitives.len() as f64 {
+            return BvhNode::leaf(bbox, primitives);
+        }
+
+        // partition keeps the source order on both sides
+        let (left, right): (Vec<Primitive>, Vec<Primitive>) =
+            primitives.into_iter().partition(|p| bucket_of(p) <= best_split);
+        BvhNode::branch(bbox, axis, left, right)
+    }
+
+    fn branch(bbox: Aabb, axis: usize, left: Vec<Primitive>, right: Vec<Primitive>) -> BvhNode {
+        BvhNode {
+            bbox,
+            contents: BvhContents::Branch {
+                axis,
+                left: Box::new(BvhNode::build(left)),
+                right: Box::new(BvhNode::build(right)),
+            },
+            unbounded: Vec::new(),
+        }
+    }
+
+    // Keeps the hit with the smallest t, and among equal t the one that came
+    // first in the source list, so the result matches a linear HittableList.
+    //
+    // With surfaces_only set objects are tested with hit_surface, which lets
+    // the ray pass through participating media.
+    fn hit_closest(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        closest: &mut f64,
+        best_index: &mut Option<usize>,
+        rec: &mut HitRecord,
+        surfaces_only: bool,
+    ) {
+        if !self.bbox.hit(ray, t_min, *closest) {
+            return;
+        }
+        match self.contents {
+            BvhContents::Leaf(ref objects) => {
+                hit_objects(objects, ray, t_min, closest, best_index, rec, surfaces_only);
+            }
+            BvhContents::Branch {
+                axis,
+                ref left,
+                ref right,
+            } => {
+                if ray.direction().axis(axis) < 0.0 {
+                    right.hit_closest(ray, t_min, closest, best_index, rec, surfaces_only);
+                    left.hit_closest(ray, t_min, closest, best_index, rec, surfaces_only);
+                } else {
+                    left.hit_closest(ray, t_min, closest, best_index, rec, surfaces_only);
+                    right.hit_closest(ray, t_min, closest, best_index, rec, surfaces_only);
+                }
+            }
+        }
+    }
+
+    // Sum of pdf_value over the objects a ray from o along v can reach, for
+    // objects whose densities already include the chance of picking them
+    pub fn pdf_sum(&self, o: &Vector3, v: &Vector3) -> f64 {
+        let ray: Ray = Ray::new(*o, *v);
+        let unbounded: f64 = self.unbounded.iter().map(|(_, object)| object.pdf_value(o, v)).sum();
+        unbounded + self.node_pdf_sum(&ray)
+    }
+
+    fn node_pdf_sum(&self, ray: &Ray) -> f64 {
+        if !self.bbox.hit(ray, 0.001, f64::MAX) {
+            return 0.0;
+        }
+        match self.contents {
+            BvhContents::Leaf(ref objects) => objects
+                .iter()
+                .map(|(_, object)| object.pdf_value(&ray.origin(), &ray.direction()))
+                .sum(),
+            BvhContents::Branch { ref left, ref right, .. } => left.node_pdf_sum(ray) + right.node_pdf_sum(ray),
+        }
+    }
+
+    fn node_transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        if !self.bbox.hit(ray, t_min, t_max) {
+            return 1.0;
+        }
+        match self.contents {
+            BvhContents::Leaf(ref objects) => objects
+                .iter()
+                .map(|(_, object)| object.transmittance(ray, t_min, t_max))
+                .product(),
+            BvhContents::Branch { ref left, ref right, .. } => {
+                left.node_transmittance(ray, t_min, t_max) * right.node_transmittance(ray, t_min, t_max)
+            }
+        }
+    }
+}
+
+fn hit_objects(
+    objects: &[(usize, SharedHittable)],
+    ray: &Ray,
+    t_min: f64,
+    closest: &mut f64,
+    best_index: &mut Option<usize>,
+    rec: &mut HitRecord,
+    surfaces_only: bool,
+) {
+    for &(index, ref object) in objects {
+        let limit: f64 = match *best_index {
+            Some(best) if index < best => closest.next_up(),
+            _ => *closest,
+        };
+        let mut temp_rec: HitRecord = HitRecord::new();
+        let hit: bool = if surfaces_only {
+            object.hit_surface(ray, t_min, limit, &mut temp_rec)
+        } else {
+            object.hit(ray, t_min, limit, &mut temp_rec)
+        };
+        if hit {
+            *closest = temp_rec.t;
+            *best_index = Some(index);
+            *rec = temp_rec;
+        }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut closest: f64 = t_max;
+        let mut best_index: Option<usize> = None;
+        hit_objects(&self.unbounded, ray, t_min, &mut closest, &mut best_index, rec, false);
+        self.hit_closest(ray, t_min, &mut closest, &mut best_index, rec, false);
+        best_index.is_some()
+    }
+
+    fn hit_surface(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut closest: f64 = t_max;
+        let mut best_index: Option<usize> = None;
+        hit_objects(&self.unbounded, ray, t_min, &mut closest, &mut best_index, rec, true);
+        self.hit_closest(ray, t_min, &mut closest, &mut best_index, rec, true);
+        best_index.is_some()
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let unbounded: f64 = self
+            .unbounded
+            .iter()
+            .map(|(_, object)| object.transmittance(ray, t_min, t_max))
+            .product();
+        unbounded * self.node_transmittance(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        if self.bbox.is_empty() || !self.unbounded.is_empty() {
+            return false;
+        }
+        *output_box = self.bbox;
+        true
+    }
+}
+
+#[cfg(test)]
+fn random_world(count: usize, seed: u64) -> HittableList {
+    use lambertian::Lambertian;
+    use material::Material;
+    use rand::Rng;
+    use random;
+    use sphere::Sphere;
+
+    let mut rng = random::generator(seed);
+    let mut list: HittableList = HittableList::new();
+    for _ in 0..count {
+        let center: Vector3 = Vector3::new(
+            10.0 * rng.gen::<f64>() - 5.0,
+            10.0 * rng.gen::<f64>() - 5.0,
+            10.0 * rng.gen::<f64>() - 5.0,
+        );
+        let albedo: Vector3 = Vector3::new(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>());
+        let sphere: Sphere = Sphere::new(center, 0.8 * rng.gen::<f64>() + 0.05, Material::Lambertian(Lambertian::new(albedo)));
+        // Exact duplicates exercise the tie breaking between equal hits
+        if rng.gen::<f64>() < 0.1 {
+            list.add(Sphere::new(center, 0.5, Material::Lambertian(Lambertian::new(albedo * 0.5))));
+            list.add(Sphere::new(center, 0.5, Material::Lambertian(Lambertian::new(albedo))));
+        }
+        list.add(sphere);
+    }
+    list
+}
+
+#[test]
+fn test_bvh_matches_list() {
+    use rand::Rng;
+    use random;
+
+    // Fixed seeds so a failing scene and ray can be reproduced
+    let list: HittableList = random_world(300, 1);
+    let bvh: BvhNode = BvhNode::new(list.clone());
+    let mut rng = random::generator(2);
+    for _ in 0..5000 {
+        let origin: Vector3 = Vector3::new(
+            16.0 * rng.gen::<f64>() - 8.0,
+            16.0 * rng.gen::<f64>() - 8.0,
+            16.0 * rng.gen::<f64>() - 8.0,
+        );
+        let direction: Vector3 = Vector3::new(
+            2.0 * rng.gen::<f64>() - 1.0,
+            2.0 * rng.gen::<f64>() - 1.0,
+            2.0 * rng.gen::<f64>() - 1.0,
+        );
+        let ray: Ray = Ray::new(origin, direction);
+        let mut list_rec: HitRecord = HitRecord::new();
+        let mut bvh_rec: HitRecord = HitRecord::new();
+        let list_hit: bool = list.hit(&ray, 0.001, f64::MAX, &mut list_rec);
+        let bvh_hit: bool = bvh.hit(&ray, 0.001, f64::MAX, &mut bvh_rec);
+        assert_eq!(list_hit, bvh_hit);
+        if list_hit {
+            assert_eq!(list_rec.t.to_bits(), bvh_rec.t.to_bits());
+            assert_eq!(list_rec.p, bvh_rec.p);
+            assert_eq!(list_rec.normal, bvh_rec.normal);
+            assert_eq!(format!("{:?}", list_rec.material), format!("{:?}", bvh_rec.material));
+        }
+    }
+}
+
+#[test]
+fn test_bvh_empty() {
+    let bvh: BvhNode = BvhNode::new(HittableList::new());
+    let ray: Ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+    let mut rec: HitRecord = HitRecord::new();
+    let mut bbox: Aabb = Aabb::empty();
+    assert!(!bvh.hit(&ray, 0.001, f64::MAX, &mut rec));
+    assert!(!bvh.bounding_box(&mut bbox));
+}
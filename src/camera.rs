@@ -1,6 +1,7 @@
 extern crate rand;
 
 use rand::Rng;
+use random;
 use ray::Ray;
 use std::f64::consts::PI;
 use vector::Vector3;
@@ -11,9 +12,12 @@ pub struct Camera {
     vertical: Vector3,
     origin: Vector3,
     lens_radius: f64,
+    half_height: f64,
     u: Vector3,
     v: Vector3,
-    w: Vector3,
+    // Shutter interval, rays get a uniformly random time within it
+    time0: f64,
+    time1: f64,
 }
 
 impl Camera {
@@ -44,27 +48,46 @@ impl Camera {
         let vertical: Vector3 = 2.0 * half_height * focus_dist * v;
 
         Camera {
-            lower_left_corner: lower_left_corner,
-            horizontal: horizontal,
-            vertical: vertical,
-            origin: origin,
-            lens_radius: lens_radius,
-            u: u,
-            v: v,
-            w: w,
+            lower_left_corner,
+            horizontal,
+            vertical,
+            origin,
+            lens_radius,
+            half_height,
+            u,
+            v,
+            time0: 0.0,
+            time1: 0.0,
         }
     }
 
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Camera {
+        self.time0 = open;
+        self.time1 = close;
+        self
+    }
+
+    pub fn shutter(&self) -> (f64, f64) {
+        (self.time0, self.time1)
+    }
+
+    // Angle covered by one pixel when the image has this many rows
+    pub fn pixel_spread(&self, rows: usize) -> f64 {
+        2.0 * self.half_height.atan() / rows as f64
+    }
+
     pub fn get_ray(&self, u: f64, v: f64) -> Ray {
         let rd: Vector3 = self.lens_radius*random_in_unit_disk();
         let offset: Vector3 = self.u * rd.x() + self.v * rd.y();
+        let time: f64 = self.time0 + random::random_f64() * (self.time1 - self.time0);
         Ray::new(self.origin + offset,
                  self.lower_left_corner + u*self.horizontal + v*self.vertical - self.origin - offset)
+            .with_time(time)
     }
 }
 
 fn random_in_unit_disk() -> Vector3 {
-    let mut rng = rand::thread_rng();
+    let mut rng = random::thread_rng();
     let mut p: Vector3;
     while {
         p = 2.0 * Vector3::new(rng.gen::<f64>(), rng.gen::<f64>(), 0.0)
@@ -73,3 +96,22 @@ fn random_in_unit_disk() -> Vector3 {
     } {}
     p
 }
+
+#[test]
+fn test_shutter() {
+    let camera: Camera = Camera::new(
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, -1.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        90.0,
+        2.0,
+        0.0,
+        1.0,
+    );
+    assert_eq!(camera.get_ray(0.5, 0.5).time(), 0.0);
+    let camera: Camera = camera.with_shutter(1.0, 1.5);
+    for _ in 0..100 {
+        let time: f64 = camera.get_ray(0.5, 0.5).time();
+        assert!((1.0..=1.5).contains(&time));
+    }
+}
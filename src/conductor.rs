@@ -0,0 +1,192 @@
+use hitable::HitRecord;
+use material::{ScatterRecord, Scatterable};
+use microfacet::{incident_frame, reflect_about, Ggx};
+use onb::Onb;
+use random;
+use ray::Ray;
+use vector::Vector3;
+
+// Metal with a GGX microfacet BRDF and the Fresnel reflectance of a complex
+// index of refraction eta + ik, given per color channel
+#[derive(Clone, Copy, Debug)]
+pub struct Conductor {
+    eta: Vector3,
+    k: Vector3,
+    distribution: Ggx,
+}
+
+impl Conductor {
+    pub fn new(eta: Vector3, k: Vector3, roughness: f64) -> Conductor {
+        Conductor::anisotropic(eta, k, roughness, roughness)
+    }
+
+    // Roughness along the tangent and across it, see microfacet::shading_frame
+    pub fn anisotropic(eta: Vector3, k: Vector3, roughness_u: f64, roughness_v: f64) -> Conductor {
+        Conductor {
+            eta,
+            k,
+            distribution: Ggx::from_roughness(roughness_u.clamp(0.0, 1.0), roughness_v.clamp(0.0, 1.0)),
+        }
+    }
+
+    // Measured metals, RGB fits of the spectral data
+    pub fn gold(roughness: f64) -> Conductor {
+        Conductor::new(Vector3::new(0.143119, 0.374957, 1.44248), Vector3::new(3.98316, 2.38572, 1.60322), roughness)
+    }
+
+    pub fn copper(roughness: f64) -> Conductor {
+        Conductor::new(Vector3::new(0.200438, 0.924033, 1.10221), Vector3::new(3.91295, 2.45285, 2.14219), roughness)
+    }
+
+    pub fn aluminium(roughness: f64) -> Conductor {
+        Conductor::new(Vector3::new(1.65746, 0.880369, 0.521229), Vector3::new(9.22387, 6.26952, 4.837), roughness)
+    }
+
+    pub fn silver(roughness: f64) -> Conductor {
+        Conductor::new(Vector3::new(0.155265, 0.116723, 0.138342), Vector3::new(4.82835, 3.12225, 2.14696), roughness)
+    }
+
+    // Optical constants of a preset by name, for scene files
+    pub fn preset(name: &str) -> Option<(Vector3, Vector3)> {
+        let conductor: Conductor = match name {
+            "gold" => Conductor::gold(0.0),
+            "copper" => Conductor::copper(0.0),
+            "aluminium" => Conductor::aluminium(0.0),
+            "silver" => Conductor::silver(0.0),
+            _ => return None,
+        };
+        Some((conductor.eta, conductor.k))
+    }
+
+    pub fn fresnel(&self, cos_theta: f64) -> Vector3 {
+        Vector3::new(
+            fresnel_conductor(cos_theta, self.eta.x(), self.k.x()),
+            fresnel_conductor(cos_theta, self.eta.y(), self.k.y()),
+            fresnel_conductor(cos_theta, self.eta.z(), self.k.z()),
+        )
+    }
+
+    // BRDF times the cosine of wi, both directions in the local shading frame
+    pub fn eval_local(&self, wo: &Vector3, wi: &Vector3) -> Vector3 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Vector3::new(0.0, 0.0, 0.0);
+        }
+        let m: Vector3 = (*wo + *wi).unit_vector();
+        let d: f64 = self.distribution.d(&m);
+        let g: f64 = self.distribution.g(wo, wi);
+        self.fresnel(wo.dot(&m)) * (d * g / (4.0 * wo.z()))
+    }
+
+    // Solid angle density of sampling wi from wo
+    pub fn pdf_local(&self, wo: &Vector3, wi: &Vector3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let m: Vector3 = (*wo + *wi).unit_vector();
+        self.distribution.pdf_visible(wo, &m) / (4.0 * wo.dot(&m))
+    }
+
+    // A reflected direction and its weight eval / pdf, None if it points into the surface
+    pub fn sample_local(&self, wo: &Vector3, u1: f64, u2: f64) -> Option<(Vector3, Vector3)> {
+        let m: Vector3 = self.distribution.sample_visible(wo, u1, u2);
+        let wi: Vector3 = reflect_about(wo, &m);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        let weight: f64 = self.distribution.g(wo, &wi) / self.distribution.g1(wo);
+        Some((wi, self.fresnel(wo.dot(&m)) * weight))
+    }
+}
+
+// Unpolarised reflectance of a conductor for light arriving at cos_theta
+pub fn fresnel_conductor(cos_theta: f64, eta: f64, k: f64) -> f64 {
+    let cos2: f64 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2: f64 = 1.0 - cos2;
+    let t0: f64 = eta * eta - k * k - sin2;
+    let a2_plus_b2: f64 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let a: f64 = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t1: f64 = a2_plus_b2 + cos2;
+    let t2: f64 = 2.0 * cos2.sqrt() * a;
+    let rs: f64 = (t1 - t2) / (t1 + t2);
+    let t3: f64 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4: f64 = t2 * sin2;
+    let rp: f64 = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rp + rs)
+}
+
+impl Scatterable for Conductor {
+    // Two sided, the side the ray arrives from is the outside
+    fn sample(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let (frame, _): (Onb, bool) = incident_frame(&ray_in.direction(), &rec.normal());
+        let wo: Vector3 = frame.to_local(&-ray_in.direction().unit_vector());
+        let (wi, weight): (Vector3, Vector3) = self.sample_local(&wo, random::random_f64(), random::random_f64())?;
+        let scattered: Ray = Ray::new(rec.p(), frame.local_vector(&wi)).with_time(ray_in.time());
+        Some(ScatterRecord::new(scattered, weight, self.pdf_local(&wo, &wi)))
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vector3) -> Vector3 {
+        let (frame, _): (Onb, bool) = incident_frame(&ray_in.direction(), &rec.normal());
+        let wo: Vector3 = frame.to_local(&-ray_in.direction().unit_vector());
+        self.eval_local(&wo, &frame.to_local(&direction.unit_vector()))
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f64 {
+        let (frame, _): (Onb, bool) = incident_frame(&ray_in.direction(), &rec.normal());
+        let wo: Vector3 = frame.to_local(&-ray_in.direction().unit_vector());
+        self.pdf_local(&wo, &frame.to_local(&direction.unit_vector()))
+    }
+}
+
+#[test]
+fn test_fresnel_conductor() {
+    // At normal incidence the exact expression reduces to ((n-1)^2+k^2)/((n+1)^2+k^2)
+    let (eta, k): (f64, f64) = (0.2, 3.9);
+    let expected: f64 = ((eta - 1.0).powi(2) + k * k) / ((eta + 1.0).powi(2) + k * k);
+    assert!((fresnel_conductor(1.0, eta, k) - expected).abs() < 1e-12);
+    assert!((fresnel_conductor(0.0, eta, k) - 1.0).abs() < 1e-12);
+    // Without absorption it matches the dielectric reflectance
+    assert!((fresnel_conductor(1.0, 1.5, 0.0) - 0.04).abs() < 1e-12);
+
+    let gold: Vector3 = Conductor::gold(0.0).fresnel(1.0);
+    assert!(gold.x() > gold.y() && gold.y() > gold.z());
+    assert!(Conductor::preset("silver").is_some() && Conductor::preset("tin").is_none());
+}
+
+#[test]
+fn test_conductor_sampling() {
+    use std::f64::consts::PI;
+
+    // A perfect reflector (F = 1 at all angles) isolates the microfacet terms
+    let mirror: Conductor = Conductor::anisotropic(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1e6, 1e6, 1e6), 0.4, 0.7);
+    let wo: Vector3 = Vector3::new(0.3, 0.4, 0.6).unit_vector();
+    random::seed(5);
+    let samples: usize = 50000;
+    let mut albedo: f64 = 0.0;
+    let mut valid: usize = 0;
+    for _ in 0..samples {
+        if let Some((wi, weight)) = mirror.sample_local(&wo, random::random_f64(), random::random_f64()) {
+            // The weight is exactly eval / pdf
+            let ratio: Vector3 = mirror.eval_local(&wo, &wi) / mirror.pdf_local(&wo, &wi);
+            assert!((ratio - weight).length() < 1e-6 * weight.length().max(1.0));
+            albedo += weight.x() / samples as f64;
+            valid += 1;
+        }
+    }
+    // Single scattering loses energy to shadowing, a fair amount at this
+    // roughness, but never gains any
+    assert!(albedo < 1.0 && albedo > 0.7, "{}", albedo);
+
+    // The pdf integrates to the fraction of samples that stay above the surface
+    let steps: usize = 400;
+    let mut integral: f64 = 0.0;
+    for i in 0..steps {
+        for j in 0..steps {
+            let cos_theta: f64 = (i as f64 + 0.5) / steps as f64;
+            let phi: f64 = 2.0 * PI * (j as f64 + 0.5) / steps as f64;
+            let sin_theta: f64 = (1.0 - cos_theta * cos_theta).sqrt();
+            let wi: Vector3 = Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+            integral += mirror.pdf_local(&wo, &wi) * 2.0 * PI / (steps * steps) as f64;
+        }
+    }
+    assert!((integral - valid as f64 / samples as f64).abs() < 1e-2, "{} vs {}", integral, valid);
+}
@@ -0,0 +1,121 @@
+use aabb::Aabb;
+use hitable::{HitRecord, Hittable, SharedHittable};
+use material::Material;
+use random;
+use ray::Ray;
+use vector::Vector3;
+
+// Volume of uniform density inside a closed boundary. A ray passing through
+// scatters at an exponentially distributed distance, or leaves untouched if
+// that lies beyond the far side.
+#[derive(Clone)]
+pub struct ConstantMedium {
+    boundary: SharedHittable,
+    neg_inv_density: f64,
+    phase_function: Material,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: SharedHittable, density: f64, phase_function: Material) -> ConstantMedium {
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+
+    // Entry and exit of the boundary along the whole line, clipped to the interval
+    fn inside(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let mut rec1: HitRecord = HitRecord::new();
+        let mut rec2: HitRecord = HitRecord::new();
+        if !self.boundary.hit(ray, f64::NEG_INFINITY, f64::INFINITY, &mut rec1) {
+            return None;
+        }
+        if !self.boundary.hit(ray, rec1.t + 0.0001, f64::INFINITY, &mut rec2) {
+            return None;
+        }
+        let t_enter: f64 = rec1.t.max(t_min).max(0.0);
+        let t_exit: f64 = rec2.t.min(t_max);
+        if t_enter >= t_exit {
+            None
+        } else {
+            Some((t_enter, t_exit))
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (t_enter, t_exit): (f64, f64) = match self.inside(ray, t_min, t_max) {
+            Some(interval) => interval,
+            None => return false,
+        };
+
+        let ray_length: f64 = ray.direction().length();
+        let distance_inside: f64 = (t_exit - t_enter) * ray_length;
+        let hit_distance: f64 = self.neg_inv_density * (1.0 - random::random_f64()).ln();
+        if hit_distance > distance_inside {
+            return false;
+        }
+
+        rec.t = t_enter + hit_distance / ray_length;
+        rec.p = ray.point_at_parameter(rec.t);
+        // Arbitrary, the phase function ignores it
+        rec.normal = Vector3::new(1.0, 0.0, 0.0);
+        rec.u = 0.0;
+        rec.v = 0.0;
+        rec.footprint = 0.0;
+        rec.material = self.phase_function.clone();
+        true
+    }
+
+    fn hit_surface(&self, _ray: &Ray, _t_min: f64, _t_max: f64, _rec: &mut HitRecord) -> bool {
+        false
+    }
+
+    // Beer-Lambert law over the stretch inside the boundary
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        match self.inside(ray, t_min, t_max) {
+            Some((t_enter, t_exit)) => ((t_exit - t_enter) * ray.direction().length() / self.neg_inv_density).exp(),
+            None => 1.0,
+        }
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        self.boundary.bounding_box(output_box)
+    }
+}
+
+#[test]
+fn test_constant_medium() {
+    use std::sync::Arc;
+
+    use isotropic::Isotropic;
+    use sphere::Sphere;
+
+    let phase: Material = Material::Isotropic(Isotropic::new(Vector3::new(0.8, 0.8, 0.8)));
+    let boundary: SharedHittable = Arc::new(Sphere::new(Vector3::new(0.0, 0.0, -5.0), 1.0, phase.clone()));
+    let ray: Ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -2.0));
+    let mut rec: HitRecord = HitRecord::new();
+
+    // Thick fog scatters right behind the surface
+    let thick: ConstantMedium = ConstantMedium::new(boundary.clone(), 1e6, phase.clone());
+    assert!(thick.hit(&ray, 0.001, f64::MAX, &mut rec));
+    assert!(rec.t >= 2.0 && rec.t < 2.001);
+    let outside: Ray = Ray::new(Vector3::new(0.0, 2.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(!thick.hit(&outside, 0.001, f64::MAX, &mut rec));
+
+    // From inside the medium the distance is measured from the ray origin
+    let inside: Ray = Ray::new(Vector3::new(0.0, 0.0, -5.0), Vector3::new(0.0, 1.0, 0.0));
+    assert!(thick.hit(&inside, 0.001, f64::MAX, &mut rec));
+    assert!(rec.t < 0.01);
+
+    // Transmittance through a diameter of 2 at density 0.5 is exp(-1)
+    let medium: ConstantMedium = ConstantMedium::new(boundary, 0.5, phase);
+    let trials: usize = 20000;
+    let passed: usize = (0..trials).filter(|_| !medium.hit(&ray, 0.001, f64::MAX, &mut rec)).count();
+    assert!((passed as f64 / trials as f64 - (-1.0f64).exp()).abs() < 0.02);
+    assert!((medium.transmittance(&ray, 0.001, f64::MAX) - (-1.0f64).exp()).abs() < 1e-9);
+    assert!((medium.transmittance(&ray, 0.001, 2.5) - (-0.5f64).exp()).abs() < 1e-9);
+    assert!(!medium.hit_surface(&ray, 0.001, f64::MAX, &mut rec));
+}
@@ -0,0 +1,91 @@
+use aabb::Aabb;
+use hitable::{HitRecord, Hittable};
+use hitable_list::HittableList;
+use material::Material;
+use quad::Quad;
+use ray::Ray;
+use vector::Vector3;
+
+// Axis-aligned box made of six quads with outward facing normals
+#[derive(Clone)]
+pub struct Cuboid {
+    min: Vector3,
+    max: Vector3,
+    sides: HittableList,
+}
+
+impl Cuboid {
+    // Corners may be given in any order
+    pub fn new(a: Vector3, b: Vector3, material: Material) -> Cuboid {
+        let min: Vector3 = Vector3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+        let max: Vector3 = Vector3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+        let dx: Vector3 = Vector3::new(max.x() - min.x(), 0.0, 0.0);
+        let dy: Vector3 = Vector3::new(0.0, max.y() - min.y(), 0.0);
+        let dz: Vector3 = Vector3::new(0.0, 0.0, max.z() - min.z());
+
+        let mut sides: HittableList = HittableList::new();
+        // front, right, back, left, top, bottom
+        sides.add(Quad::new(Vector3::new(min.x(), min.y(), max.z()), dx, dy, material.clone()));
+        sides.add(Quad::new(Vector3::new(max.x(), min.y(), max.z()), -dz, dy, material.clone()));
+        sides.add(Quad::new(Vector3::new(max.x(), min.y(), min.z()), -dx, dy, material.clone()));
+        sides.add(Quad::new(Vector3::new(min.x(), min.y(), min.z()), dz, dy, material.clone()));
+        sides.add(Quad::new(Vector3::new(min.x(), max.y(), max.z()), dx, -dz, material.clone()));
+        sides.add(Quad::new(Vector3::new(min.x(), min.y(), min.z()), dx, dz, material));
+        Cuboid { min, max, sides }
+    }
+
+    pub fn min(&self) -> Vector3 {
+        self.min
+    }
+
+    pub fn max(&self) -> Vector3 {
+        self.max
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.sides.hit(ray, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        self.sides.bounding_box(output_box)
+    }
+
+    fn pdf_value(&self, o: &Vector3, v: &Vector3) -> f64 {
+        self.sides.pdf_value(o, v)
+    }
+
+    fn random(&self, o: &Vector3) -> Vector3 {
+        self.sides.random(o)
+    }
+}
+
+#[test]
+fn test_cuboid() {
+    use lambertian::Lambertian;
+
+    let material: Material = Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+    let cuboid: Cuboid = Cuboid::new(Vector3::new(1.0, 1.0, 1.0), Vector3::new(-1.0, 0.0, -1.0), material);
+    assert_eq!(cuboid.min(), Vector3::new(-1.0, 0.0, -1.0));
+    assert_eq!(cuboid.max(), Vector3::new(1.0, 1.0, 1.0));
+
+    // Every face is hit from outside along its normal
+    let faces: [(Vector3, Vector3); 6] = [
+        (Vector3::new(0.0, 0.5, 5.0), Vector3::new(0.0, 0.0, 1.0)),
+        (Vector3::new(0.0, 0.5, -5.0), Vector3::new(0.0, 0.0, -1.0)),
+        (Vector3::new(5.0, 0.5, 0.0), Vector3::new(1.0, 0.0, 0.0)),
+        (Vector3::new(-5.0, 0.5, 0.0), Vector3::new(-1.0, 0.0, 0.0)),
+        (Vector3::new(0.0, 5.0, 0.0), Vector3::new(0.0, 1.0, 0.0)),
+        (Vector3::new(0.0, -5.0, 0.0), Vector3::new(0.0, -1.0, 0.0)),
+    ];
+    for &(origin, normal) in faces.iter() {
+        let mut rec: HitRecord = HitRecord::new();
+        assert!(cuboid.hit(&Ray::new(origin, -normal), 0.001, f64::MAX, &mut rec));
+        assert_eq!(rec.normal(), normal);
+    }
+
+    let mut bbox: Aabb = Aabb::empty();
+    assert!(cuboid.bounding_box(&mut bbox));
+    assert!(bbox.min().x() < -1.0 && bbox.max().y() > 1.0);
+}
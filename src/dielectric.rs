@@ -1,72 +1,297 @@
-extern crate rand;
-
 use vector::Vector3;
-use material::Scatterable;
+use material::{ScatterRecord, Scatterable};
 use hitable::HitRecord;
+use microfacet::{incident_frame, reflect_about, refraction_half_vector, Ggx};
+use onb::Onb;
 use ray::Ray;
-use metal::reflect;
-use rand::Rng;
+use random;
 
+// Glass and other transparent media. Smooth by default; with a roughness the
+// surface is a GGX microfacet BSDF (Walter et al., "Microfacet Models for
+// Refraction through Rough Surfaces"). Light travelling inside is absorbed
+// following Beer-Lambert with the per channel coefficient `absorption`.
 #[derive(Clone, Copy, Debug)]
 pub struct Dielectric {
     ri: f64,
+    distribution: Option<Ggx>,
+    absorption: Vector3,
 }
 
 impl Dielectric {
     pub fn new(ri: f64) -> Dielectric {
-        Dielectric { ri: ri }
+        Dielectric {
+            ri,
+            distribution: None,
+            absorption: Vector3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    // Frosted glass, roughness in [0, 1] along and across the tangent
+    pub fn with_roughness(mut self, roughness_u: f64, roughness_v: f64) -> Dielectric {
+        let (u, v): (f64, f64) = (roughness_u.clamp(0.0, 1.0), roughness_v.clamp(0.0, 1.0));
+        self.distribution = if u > 0.0 || v > 0.0 {
+            Some(Ggx::from_roughness(u, v))
+        } else {
+            None
+        };
+        self
+    }
+
+    // Extinction per unit of distance travelled inside
+    pub fn with_absorption(mut self, absorption: Vector3) -> Dielectric {
+        self.absorption = absorption;
+        self
+    }
+
+    // Absorption that leaves `color` of the light after `distance`
+    pub fn with_color(self, color: Vector3, distance: f64) -> Dielectric {
+        let coefficient = |c: f64| -> f64 { -c.clamp(1e-6, 1.0).ln() / distance };
+        self.with_absorption(Vector3::new(coefficient(color.x()), coefficient(color.y()), coefficient(color.z())))
+    }
+
+    pub fn ri(&self) -> f64 {
+        self.ri
+    }
+
+    // Fraction of the light that survives the stretch of the ray before rec
+    fn transmittance(&self, ray_in: &Ray, rec: &HitRecord) -> Vector3 {
+        let distance: f64 = rec.t * ray_in.direction().length();
+        Vector3::new(
+            (-self.absorption.x() * distance).exp(),
+            (-self.absorption.y() * distance).exp(),
+            (-self.absorption.z() * distance).exp(),
+        )
     }
 }
 
-fn refract(v: &Vector3, n: &Vector3, ni_over_nt: f64, refracted: &mut Vector3) -> bool {
-    let uv: Vector3 = v.unit_vector();
-    let dt: f64 = uv.dot(&n);
+// Unpolarised reflectance at the boundary for light arriving at cos_i on the
+// side of the normal, eta the ratio of the far side's index to the near side's.
+// Total internal reflection gives 1.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i: f64 = cos_i.clamp(0.0, 1.0);
+    let sin2_t: f64 = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t: f64 = (1.0 - sin2_t).sqrt();
+    let rs: f64 = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp: f64 = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
 
-    let discriminant: f64 = 1.0 - ni_over_nt*ni_over_nt*(1.0 - dt*dt);
-    if discriminant > 0.0 {
-        *refracted = ni_over_nt*(uv - *n*dt) - *n*((discriminant).sqrt());
-        return true;
-    } else {
-        return false;
+// Refraction of w (pointing away from the surface, w.n > 0) through the
+// boundary with normal n, None on total internal reflection
+pub fn refract_about(w: &Vector3, n: &Vector3, eta: f64) -> Option<Vector3> {
+    let cos_i: f64 = w.dot(n);
+    let sin2_t: f64 = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
     }
+    let cos_t: f64 = (1.0 - sin2_t).sqrt();
+    Some(-*w / eta + (cos_i / eta - cos_t) * *n)
 }
 
-fn schlick(cosine: f64, ri: f64) -> f64 {
-    let mut r0: f64 = (1.0 - ri)/(1.0 + ri);
-    r0 = r0*r0;
-    r0 + (1.0-r0)*(1.0-cosine).powi(5)
+impl Dielectric {
+    // The frame on the side the ray arrives from, the index across the
+    // boundary relative to that side and the absorption on the way here
+    fn incident(&self, ray_in: &Ray, rec: &HitRecord) -> (Onb, f64, Vector3) {
+        // Normals face out of the object, a ray meeting the back side has been travelling inside it
+        let (frame, front): (Onb, bool) = incident_frame(&ray_in.direction(), &rec.normal());
+        if front {
+            (frame, self.ri, Vector3::new(1.0, 1.0, 1.0))
+        } else {
+            (frame, 1.0 / self.ri, self.transmittance(ray_in, rec))
+        }
+    }
 }
 
 impl Scatterable for Dielectric {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, attenuation: &mut Vector3, scattered: &mut Ray) -> bool {
-        let outward_normal: Vector3;
-        let reflected = reflect(&ray_in.direction().unit_vector(), &rec.normal());
-        let ni_over_nt: f64;
-        *attenuation = Vector3::new(1.0, 1.0, 1.0);
-        let mut refracted: Vector3 = Vector3::new(0.0, 0.0, 0.0);
-        let reflect_prob: f64;
-        let cosine: f64;
-        if ray_in.direction().dot(&rec.normal()) > 0.0 {
-            outward_normal = -1.0*rec.normal();
-            ni_over_nt = self.ri;
-            cosine = self.ri*ray_in.direction().dot(&rec.normal())/ray_in.direction().length();
-        } else {
-            outward_normal = 1.0*rec.normal();
-            ni_over_nt = 1.0/self.ri;
-            cosine = -1.0*ray_in.direction().dot(&rec.normal())/ray_in.direction().length();
+    fn sample(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let (frame, eta, absorbed): (Onb, f64, Vector3) = self.incident(ray_in, rec);
+        let wo: Vector3 = frame.to_local(&-ray_in.direction().unit_vector());
+        let distribution: Ggx = match self.distribution {
+            Some(distribution) => distribution,
+            None => {
+                let n: Vector3 = Vector3::new(0.0, 0.0, 1.0);
+                let reflected: Vector3 = reflect_about(&wo, &n);
+                let wi: Vector3 = if random::random_f64() < fresnel_dielectric(wo.z(), eta) {
+                    reflected
+                } else {
+                    refract_about(&wo, &n, eta).unwrap_or(reflected)
+                };
+                let scattered: Ray = Ray::new(rec.p(), frame.local_vector(&wi)).with_time(ray_in.time());
+                return Some(ScatterRecord::delta(scattered, absorbed));
+            }
+        };
+        let (wi, weight): (Vector3, f64) = sample_rough(&distribution, &wo, eta)?;
+        let scattered: Ray = Ray::new(rec.p(), frame.local_vector(&wi)).with_time(ray_in.time());
+        Some(ScatterRecord::new(scattered, absorbed * weight, pdf_rough(&distribution, &wo, &wi, eta)))
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vector3) -> Vector3 {
+        match self.distribution {
+            Some(ref distribution) => {
+                let (frame, eta, absorbed): (Onb, f64, Vector3) = self.incident(ray_in, rec);
+                let wo: Vector3 = frame.to_local(&-ray_in.direction().unit_vector());
+                absorbed * eval_rough(distribution, &wo, &frame.to_local(&direction.unit_vector()), eta)
+            }
+            None => Vector3::new(0.0, 0.0, 0.0),
         }
-        if refract(&ray_in.direction(), &outward_normal, ni_over_nt, &mut refracted) {
-            reflect_prob = schlick(cosine, self.ri);
-        } else {
-            *scattered = Ray::new(rec.p, reflected);
-            reflect_prob = 1.0;
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f64 {
+        match self.distribution {
+            Some(ref distribution) => {
+                let (frame, front): (Onb, bool) = incident_frame(&ray_in.direction(), &rec.normal());
+                let eta: f64 = if front { self.ri } else { 1.0 / self.ri };
+                let wo: Vector3 = frame.to_local(&-ray_in.direction().unit_vector());
+                pdf_rough(distribution, &wo, &frame.to_local(&direction.unit_vector()), eta)
+            }
+            None => 0.0,
         }
-        let mut rng = rand::thread_rng();
-        if rng.gen::<f64>() < reflect_prob {
-            *scattered = Ray::new(rec.p, reflected);
-        } else {
-            *scattered = Ray::new(rec.p, refracted);
+    }
+}
+
+// Picks reflection or refraction through a visible microfacet with the Fresnel
+// probability, which cancels the Fresnel term of the BSDF and leaves the weight G / G1
+fn sample_rough(distribution: &Ggx, wo: &Vector3, eta: f64) -> Option<(Vector3, f64)> {
+    let m: Vector3 = distribution.sample_visible(wo, random::random_f64(), random::random_f64());
+    let fresnel: f64 = fresnel_dielectric(wo.dot(&m), eta);
+    let wi: Vector3 = if random::random_f64() < fresnel {
+        let wi: Vector3 = reflect_about(wo, &m);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        wi
+    } else {
+        let wi: Vector3 = refract_about(wo, &m, eta)?;
+        if wi.z() >= 0.0 {
+            return None;
+        }
+        wi
+    };
+    Some((wi, distribution.g(wo, &wi) / distribution.g1(wo)))
+}
+
+// BSDF times |cos| of wi for the rough surface, in the frame on wo's side.
+// Refraction leaves out the 1 / eta^2 scaling of radiance, so what enters a
+// closed object also comes out of it.
+fn eval_rough(distribution: &Ggx, wo: &Vector3, wi: &Vector3, eta: f64) -> f64 {
+    if wo.z() <= 0.0 || wi.z() == 0.0 {
+        return 0.0;
+    }
+    if wi.z() > 0.0 {
+        let m: Vector3 = (*wo + *wi).unit_vector();
+        let fresnel: f64 = fresnel_dielectric(wo.dot(&m), eta);
+        return fresnel * distribution.d(&m) * distribution.g(wo, wi) / (4.0 * wo.z());
+    }
+    match refraction_half_vector(wo, wi, eta) {
+        Some(m) => {
+            let (o_m, i_m): (f64, f64) = (wo.dot(&m), wi.dot(&m));
+            let denominator: f64 = o_m + eta * i_m;
+            let fresnel: f64 = fresnel_dielectric(o_m, eta);
+            (1.0 - fresnel) * distribution.d(&m) * distribution.g(wo, wi) * eta * eta * i_m.abs() * o_m
+                / (wo.z() * denominator * denominator)
+        }
+        None => 0.0,
+    }
+}
+
+// Solid angle density of sample_rough returning wi
+fn pdf_rough(distribution: &Ggx, wo: &Vector3, wi: &Vector3, eta: f64) -> f64 {
+    if wo.z() <= 0.0 || wi.z() == 0.0 {
+        return 0.0;
+    }
+    if wi.z() > 0.0 {
+        let m: Vector3 = (*wo + *wi).unit_vector();
+        let fresnel: f64 = fresnel_dielectric(wo.dot(&m), eta);
+        return fresnel * distribution.pdf_visible(wo, &m) / (4.0 * wo.dot(&m));
+    }
+    match refraction_half_vector(wo, wi, eta) {
+        Some(m) => {
+            let (o_m, i_m): (f64, f64) = (wo.dot(&m), wi.dot(&m));
+            let denominator: f64 = o_m + eta * i_m;
+            let fresnel: f64 = fresnel_dielectric(o_m, eta);
+            (1.0 - fresnel) * distribution.pdf_visible(wo, &m) * eta * eta * i_m.abs() / (denominator * denominator)
         }
-        return true;
+        None => 0.0,
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_fresnel_dielectric() {
+    assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+    assert!((fresnel_dielectric(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-12);
+    assert!((fresnel_dielectric(0.0, 1.5) - 1.0).abs() < 1e-12);
+    // Past the critical angle from inside everything is reflected
+    assert_eq!(fresnel_dielectric(0.5, 1.0 / 1.5), 1.0);
+    // At Brewster's angle only the s polarisation reflects
+    let brewster: f64 = 1.5f64.atan();
+    let cos_t: f64 = (1.0 - (brewster.sin() / 1.5).powi(2)).sqrt();
+    let rs: f64 = (brewster.cos() - 1.5 * cos_t) / (brewster.cos() + 1.5 * cos_t);
+    assert!((fresnel_dielectric(brewster.cos(), 1.5) - 0.5 * rs * rs).abs() < 1e-12);
+
+    // Snell's law holds for the refracted direction
+    let w: Vector3 = Vector3::new(0.6, 0.0, 0.8);
+    let n: Vector3 = Vector3::new(0.0, 0.0, 1.0);
+    let t: Vector3 = refract_about(&w, &n, 1.5).unwrap();
+    assert!((t.length() - 1.0).abs() < 1e-12 && t.z() < 0.0);
+    assert!((w.x() - 1.5 * -t.x()).abs() < 1e-12);
+    assert!(refract_about(&Vector3::new(0.8, 0.0, 0.6), &n, 1.0 / 1.5).is_none());
+}
+
+#[test]
+fn test_dielectric_scatter() {
+    let ray_from = |origin: Vector3, t: f64, normal: Vector3| -> (Ray, HitRecord) {
+        let ray: Ray = Ray::new(origin, Vector3::new(0.0, 0.0, -1.0));
+        let mut rec: HitRecord = HitRecord::new();
+        rec.t = t;
+        rec.p = ray.point_at_parameter(t);
+        rec.normal = normal;
+        (ray, rec)
+    };
+
+    // Entering is free, leaving after 2 units pays for the path inside
+    let glass: Dielectric = Dielectric::new(1.5).with_color(Vector3::new(0.5, 0.8, 1.0), 1.0);
+    let (ray, rec) = ray_from(Vector3::new(0.0, 0.0, 1.0), 1.0, Vector3::new(0.0, 0.0, 1.0));
+    let entering: ScatterRecord = glass.sample(&ray, &rec).unwrap();
+    assert_eq!(entering.weight, Vector3::new(1.0, 1.0, 1.0));
+    assert!(entering.delta);
+    let (ray, rec) = ray_from(Vector3::new(0.0, 0.0, 0.0), 2.0, Vector3::new(0.0, 0.0, -1.0));
+    let leaving: ScatterRecord = glass.sample(&ray, &rec).unwrap();
+    assert!((leaving.weight - Vector3::new(0.25, 0.64, 1.0)).length() < 1e-12);
+
+    // Frosted glass spreads the transmitted light, reflects about the Fresnel
+    // fraction and loses only a little energy to shadowing
+    let frosted: Dielectric = Dielectric::new(1.5).with_roughness(0.3, 0.3);
+    random::seed(11);
+    let samples: usize = 20000;
+    let (mut reflected, mut energy, mut spread): (usize, f64, f64) = (0, 0.0, 0.0);
+    for &(origin, normal) in &[(1.0, 1.0), (0.0, -1.0)] {
+        let (ray, rec) = ray_from(Vector3::new(0.0, 0.0, origin), 1.0, Vector3::new(0.0, 0.0, normal));
+        for _ in 0..samples {
+            let sample: ScatterRecord = match frosted.sample(&ray, &rec) {
+                Some(sample) => sample,
+                None => continue,
+            };
+            // The weight is eval / pdf on both sides of the surface
+            let direction: Vector3 = sample.ray.direction().unit_vector();
+            assert!(!sample.delta && (frosted.pdf(&ray, &rec, &direction) - sample.pdf).abs() < 1e-9 * sample.pdf);
+            assert!((frosted.eval(&ray, &rec, &direction) / sample.pdf - sample.weight).length() < 1e-9);
+            if normal < 0.0 {
+                continue;
+            }
+            assert!(sample.weight.x() <= 1.0 + 1e-12);
+            energy += sample.weight.x() / samples as f64;
+            if direction.z() > 0.0 {
+                reflected += 1;
+            } else {
+                spread += (direction.x() * direction.x() + direction.y() * direction.y()).sqrt() / samples as f64;
+            }
+        }
+    }
+    let reflectance: f64 = reflected as f64 / samples as f64;
+    assert!(reflectance > 0.02 && reflectance < 0.08, "{}", reflectance);
+    assert!(energy > 0.95 && energy <= 1.0, "{}", energy);
+    assert!(spread > 0.02, "{}", spread);
+}
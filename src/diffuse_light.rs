@@ -0,0 +1,33 @@
+use material::{ScatterRecord, Scatterable};
+use hitable::HitRecord;
+use vector::Vector3;
+use ray::Ray;
+
+#[derive(Clone, Copy, Debug)]
+pub struct DiffuseLight {
+    pub emit: Vector3,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Vector3) -> DiffuseLight {
+        DiffuseLight { emit }
+    }
+}
+
+impl Scatterable for DiffuseLight {
+    fn sample(&self, _ray_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, _u: f64, _v: f64, _p: &Vector3) -> Vector3 {
+        self.emit
+    }
+}
+
+#[test]
+fn test_emitted() {
+    let light: DiffuseLight = DiffuseLight::new(Vector3::new(4.0, 4.0, 4.0));
+    let ray: Ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(light.sample(&ray, &HitRecord::new()).is_none());
+    assert_eq!(light.emitted(0.0, 0.0, &Vector3::new(0.0, 0.0, -1.0)), Vector3::new(4.0, 4.0, 4.0));
+}
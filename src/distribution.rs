@@ -0,0 +1,122 @@
+// Piecewise constant density over [0, 1) with one bucket per weight
+#[derive(Clone, Debug)]
+pub struct Distribution1D {
+    weights: Vec<f64>,
+    cdf: Vec<f64>,
+    total: f64,
+}
+
+impl Distribution1D {
+    // Negative weights count as zero, if none is positive all buckets are equally likely
+    pub fn new(weights: &[f64]) -> Distribution1D {
+        let mut weights: Vec<f64> = weights.iter().map(|w| w.max(0.0)).collect();
+        let mut total: f64 = weights.iter().sum();
+        if total <= 0.0 || !total.is_finite() {
+            weights = vec![1.0; weights.len()];
+            total = weights.len() as f64;
+        }
+        let mut cdf: Vec<f64> = Vec::with_capacity(weights.len() + 1);
+        let mut running: f64 = 0.0;
+        cdf.push(0.0);
+        for w in &weights {
+            running += w;
+            cdf.push(running / total);
+        }
+        Distribution1D { weights, cdf, total }
+    }
+
+    pub fn len(&self) -> usize {
+        self.weights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.weights.is_empty()
+    }
+
+    // Sum of the weights, after replacing an all zero distribution
+    pub fn total(&self) -> f64 {
+        self.total
+    }
+
+    // Probability of picking bucket index
+    pub fn pmf(&self, index: usize) -> f64 {
+        self.weights[index] / self.total
+    }
+
+    // A bucket with probability pmf and where u fell inside it, in [0, 1)
+    pub fn sample(&self, u: f64) -> (usize, f64) {
+        // The last bucket with cdf <= u, skipping empty ones
+        let mut index: usize = match self.cdf.binary_search_by(|c| c.partial_cmp(&u).unwrap()) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        index = index.min(self.len() - 1);
+        while self.weights[index] == 0.0 && index + 1 < self.len() {
+            index += 1;
+        }
+        let width: f64 = self.cdf[index + 1] - self.cdf[index];
+        let offset: f64 = if width > 0.0 { (u - self.cdf[index]) / width } else { 0.0 };
+        (index, offset.clamp(0.0, 1.0 - f64::EPSILON))
+    }
+}
+
+// Density over the unit square from a grid of weights, row by row: a row is
+// picked from the marginal distribution and a column from that row
+#[derive(Clone, Debug)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    // weights holds height rows of width values each
+    pub fn new(weights: &[f64], width: usize, height: usize) -> Distribution2D {
+        let rows: Vec<Distribution1D> = weights.chunks(width).take(height).map(Distribution1D::new).collect();
+        let sums: Vec<f64> = weights.chunks(width).take(height).map(|row| row.iter().map(|w| w.max(0.0)).sum()).collect();
+        Distribution2D {
+            rows,
+            marginal: Distribution1D::new(&sums),
+        }
+    }
+
+    // Probability of picking cell (x, y)
+    pub fn pmf(&self, x: usize, y: usize) -> f64 {
+        self.marginal.pmf(y) * self.rows[y].pmf(x)
+    }
+
+    // A cell and the position within it, each coordinate in [0, 1)
+    pub fn sample(&self, u1: f64, u2: f64) -> ((usize, usize), (f64, f64)) {
+        let (y, dy): (usize, f64) = self.marginal.sample(u1);
+        let (x, dx): (usize, f64) = self.rows[y].sample(u2);
+        ((x, y), (dx, dy))
+    }
+}
+
+#[test]
+fn test_distribution() {
+    let d: Distribution1D = Distribution1D::new(&[1.0, 0.0, 3.0]);
+    assert_eq!(d.pmf(2), 0.75);
+    let (index, offset): (usize, f64) = d.sample(0.1);
+    assert!(index == 0 && (offset - 0.4).abs() < 1e-12);
+    // Empty buckets are never picked, not even on their boundary
+    assert_eq!(d.sample(0.25).0, 2);
+    assert_eq!(d.sample(0.999).0, 2);
+    assert_eq!(Distribution1D::new(&[0.0, 0.0]).pmf(1), 0.5);
+
+    let grid: Distribution2D = Distribution2D::new(&[1.0, 1.0, 0.0, 6.0, 2.0, 0.0], 3, 2);
+    let mut counts: [usize; 6] = [0; 6];
+    let n: usize = 100000;
+    for i in 0..n {
+        let u1: f64 = (i as f64 + 0.5) / n as f64;
+        let u2: f64 = (i as f64 * 0.618034).fract();
+        let ((x, y), _): ((usize, usize), (f64, f64)) = grid.sample(u1, u2);
+        counts[y * 3 + x] += 1;
+    }
+    for y in 0..2 {
+        for x in 0..3 {
+            let expected: f64 = grid.pmf(x, y);
+            assert!((counts[y * 3 + x] as f64 / n as f64 - expected).abs() < 0.01, "{} {}", x, y);
+        }
+    }
+    assert!((grid.pmf(0, 1) - 0.6).abs() < 1e-12);
+}
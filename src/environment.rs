@@ -0,0 +1,232 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use distribution::Distribution2D;
+use image_reader::{load_image, ImageError};
+use lambertian::random_unit_vector;
+use random;
+use render::Framebuffer;
+use vector::Vector3;
+
+// Light arriving from infinitely far away, seen by every ray that leaves the
+// scene. Environments that can be sampled close to their radiance take part
+// in next event estimation like the lights do.
+pub trait Environment: fmt::Debug {
+    fn radiance(&self, direction: &Vector3) -> Vector3;
+
+    fn importance_sampled(&self) -> bool {
+        false
+    }
+
+    // Solid angle density of random, uniform over the sphere unless overridden
+    fn pdf_value(&self, _direction: &Vector3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn random(&self) -> Vector3 {
+        random_unit_vector()
+    }
+}
+
+pub type SharedEnvironment = Arc<dyn Environment + Send + Sync>;
+
+// Image coordinates in [0, 1) of a direction on a latitude-longitude map,
+// whose top row looks straight up and its center along +x
+pub fn direction_to_uv(direction: &Vector3) -> (f64, f64) {
+    let d: Vector3 = direction.unit_vector();
+    let theta: f64 = d.y().clamp(-1.0, 1.0).acos();
+    let phi: f64 = d.z().atan2(d.x());
+    ((phi + PI) / (2.0 * PI), theta / PI)
+}
+
+pub fn uv_to_direction(u: f64, v: f64) -> Vector3 {
+    let (theta, phi): (f64, f64) = (PI * v, 2.0 * PI * u - PI);
+    Vector3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin())
+}
+
+// Density over directions proportional to a latitude-longitude grid of
+// weights, constant in u and v within each cell
+#[derive(Clone, Debug)]
+pub struct SphericalDistribution {
+    width: usize,
+    height: usize,
+    distribution: Distribution2D,
+}
+
+impl SphericalDistribution {
+    // weights holds height rows of width values each
+    pub fn new(weights: &[f64], width: usize, height: usize) -> SphericalDistribution {
+        // Rows near the poles cover less solid angle than their cell count suggests
+        let mut scaled: Vec<f64> = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let sin_theta: f64 = (PI * (y as f64 + 0.5) / height as f64).sin();
+            scaled.extend(weights[y * width..(y + 1) * width].iter().map(|w| w * sin_theta));
+        }
+        SphericalDistribution {
+            width,
+            height,
+            distribution: Distribution2D::new(&scaled, width, height),
+        }
+    }
+
+    pub fn cell(&self, u: f64, v: f64) -> (usize, usize) {
+        let x: usize = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y: usize = ((v * self.height as f64) as usize).min(self.height - 1);
+        (x, y)
+    }
+
+    // Uniform within a cell the density over the map is pmf * width * height,
+    // and dw = 2 pi^2 sin(theta) du dv
+    pub fn pdf_value(&self, direction: &Vector3) -> f64 {
+        let (u, v): (f64, f64) = direction_to_uv(direction);
+        let sin_theta: f64 = (PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let (x, y): (usize, usize) = self.cell(u, v);
+        let cells: f64 = (self.width * self.height) as f64;
+        self.distribution.pmf(x, y) * cells / (2.0 * PI * PI * sin_theta)
+    }
+
+    pub fn random(&self) -> Vector3 {
+        let ((x, y), (dx, dy)): ((usize, usize), (f64, f64)) =
+            self.distribution.sample(random::random_f64(), random::random_f64());
+        uv_to_direction((x as f64 + dx) / self.width as f64, (y as f64 + dy) / self.height as f64)
+    }
+}
+
+// Equirectangular image, turned by rotation degrees about +y
+#[derive(Clone, Debug)]
+pub struct EnvironmentMap {
+    image: Framebuffer,
+    rotation: f64,
+    intensity: f64,
+    distribution: SphericalDistribution,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Framebuffer) -> EnvironmentMap {
+        let weights: Vec<f64> = image.pixels().iter().map(luminance).collect();
+        EnvironmentMap {
+            distribution: SphericalDistribution::new(&weights, image.width(), image.height()),
+            image,
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<EnvironmentMap, ImageError> {
+        Ok(EnvironmentMap::new(load_image(path)?))
+    }
+
+    pub fn with_rotation(mut self, degrees: f64) -> EnvironmentMap {
+        self.rotation = degrees.to_radians();
+        self
+    }
+
+    // Scales the radiance, the sampling density doesn't change
+    pub fn with_intensity(mut self, intensity: f64) -> EnvironmentMap {
+        self.intensity = intensity;
+        self
+    }
+}
+
+// Turns a direction by angle radians about +y
+fn rotate_y(direction: &Vector3, angle: f64) -> Vector3 {
+    let (sin, cos): (f64, f64) = angle.sin_cos();
+    Vector3::new(
+        cos * direction.x() + sin * direction.z(),
+        direction.y(),
+        -sin * direction.x() + cos * direction.z(),
+    )
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: &Vector3) -> Vector3 {
+        let (u, v): (f64, f64) = direction_to_uv(&rotate_y(direction, -self.rotation));
+        let (x, y): (usize, usize) = self.distribution.cell(u, v);
+        self.intensity * self.image.get(x, y)
+    }
+
+    fn importance_sampled(&self) -> bool {
+        true
+    }
+
+    fn pdf_value(&self, direction: &Vector3) -> f64 {
+        self.distribution.pdf_value(&rotate_y(direction, -self.rotation))
+    }
+
+    fn random(&self) -> Vector3 {
+        rotate_y(&self.distribution.random(), self.rotation)
+    }
+}
+
+pub fn luminance(color: &Vector3) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
+
+#[cfg(test)]
+fn test_map() -> EnvironmentMap {
+    // A dim sky with one bright pixel just above the horizon
+    let mut image: Framebuffer = Framebuffer::new(16, 8);
+    for y in 0..8 {
+        for x in 0..16 {
+            image.set(x, y, Vector3::new(0.1, 0.2, 0.4));
+        }
+    }
+    image.set(11, 3, Vector3::new(200.0, 180.0, 150.0));
+    EnvironmentMap::new(image)
+}
+
+#[test]
+fn test_environment_map_lookup() {
+    let map: EnvironmentMap = test_map().with_intensity(2.0);
+    assert_eq!(map.radiance(&Vector3::new(0.0, 1.0, 0.0)), Vector3::new(0.2, 0.4, 0.8));
+    // Column 11 of 16 is centered at phi = pi * (23 / 16 - 1)
+    let phi: f64 = PI * (23.0 / 16.0 - 1.0);
+    let toward: Vector3 = Vector3::new(phi.cos(), 0.05, phi.sin());
+    assert_eq!(map.radiance(&toward), Vector3::new(400.0, 360.0, 300.0));
+    // Turning the map turns what each direction sees along with it
+    let turned: EnvironmentMap = test_map().with_rotation(90.0);
+    let rotated: Vector3 = rotate_y(&toward, turned.rotation);
+    assert_eq!(turned.radiance(&rotated), Vector3::new(200.0, 180.0, 150.0));
+    assert!((turned.pdf_value(&rotated) - test_map().pdf_value(&toward)).abs() < 1e-9);
+}
+
+#[test]
+fn test_environment_map_sampling() {
+    let map: EnvironmentMap = test_map().with_rotation(30.0);
+    // The density integrates to one over the sphere
+    let steps: usize = 400;
+    let mut integral: f64 = 0.0;
+    let mut irradiance: f64 = 0.0;
+    for i in 0..steps {
+        for j in 0..steps {
+            let cos_theta: f64 = 1.0 - 2.0 * (i as f64 + 0.5) / steps as f64;
+            let phi: f64 = 2.0 * PI * (j as f64 + 0.5) / steps as f64;
+            let sin_theta: f64 = (1.0 - cos_theta * cos_theta).sqrt();
+            let d: Vector3 = Vector3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+            integral += map.pdf_value(&d) * 4.0 * PI / (steps * steps) as f64;
+            irradiance += map.radiance(&d).x() * d.y().max(0.0) * 4.0 * PI / (steps * steps) as f64;
+        }
+    }
+    assert!((integral - 1.0).abs() < 1e-2, "{}", integral);
+
+    // Samples follow the radiance and estimate the irradiance of an upward
+    // facing surface without bias
+    random::seed(3);
+    let samples: usize = 20000;
+    let mut estimate: f64 = 0.0;
+    let mut bright: usize = 0;
+    for _ in 0..samples {
+        let d: Vector3 = map.random();
+        if map.radiance(&d).x() > 100.0 {
+            bright += 1;
+        }
+        estimate += map.radiance(&d).x() * d.y().max(0.0) / map.pdf_value(&d) / samples as f64;
+    }
+    assert!(bright > samples / 2, "{}", bright);
+    assert!((estimate - irradiance).abs() < 0.02 * irradiance, "{} vs {}", estimate, irradiance);
+}
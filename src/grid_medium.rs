@@ -0,0 +1,175 @@
+use std::sync::Arc;
+
+use aabb::Aabb;
+use hitable::{HitRecord, Hittable};
+use material::Material;
+use random;
+use ray::Ray;
+use vector::Vector3;
+use voxel_grid::VoxelGrid;
+
+// Medium whose density comes from a voxel grid stretched over an axis aligned
+// box. Free paths are sampled with delta tracking and shadow rays estimate
+// transmittance with ratio tracking, both against the grid's largest density.
+#[derive(Clone)]
+pub struct GridMedium {
+    grid: Arc<VoxelGrid>,
+    min: Vector3,
+    max: Vector3,
+    // Grid values are multiplied by this to get the extinction coefficient
+    density: f64,
+    majorant: f64,
+    phase_function: Material,
+}
+
+impl GridMedium {
+    pub fn new(grid: Arc<VoxelGrid>, min: Vector3, max: Vector3, density: f64, phase_function: Material) -> GridMedium {
+        let majorant: f64 = density * grid.max();
+        GridMedium {
+            grid,
+            min,
+            max,
+            density,
+            majorant,
+            phase_function,
+        }
+    }
+
+    pub fn density_at(&self, p: &Vector3) -> f64 {
+        let extent: Vector3 = self.max - self.min;
+        let local: Vector3 = *p - self.min;
+        self.density * self.grid.sample(local.x() / extent.x(), local.y() / extent.y(), local.z() / extent.z())
+    }
+
+    // Stretch of the ray inside the box, clipped to the interval
+    fn inside(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let mut t0: f64 = t_min.max(0.0);
+        let mut t1: f64 = t_max;
+        for axis in 0..3 {
+            let inv_d: f64 = 1.0 / ray.direction().axis(axis);
+            let mut near: f64 = (self.min.axis(axis) - ray.origin().axis(axis)) * inv_d;
+            let mut far: f64 = (self.max.axis(axis) - ray.origin().axis(axis)) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            // NaN from 0 * inf leaves the bound alone
+            if near > t0 {
+                t0 = near;
+            }
+            if far < t1 {
+                t1 = far;
+            }
+            if t1 <= t0 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+
+    // Distance along the ray, in units of t, to the next tentative collision
+    fn step(&self, ray_length: f64) -> f64 {
+        -(1.0 - random::random_f64()).ln() / (self.majorant * ray_length)
+    }
+}
+
+impl Hittable for GridMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if self.majorant <= 0.0 {
+            return false;
+        }
+        let (mut t, t_exit): (f64, f64) = match self.inside(ray, t_min, t_max) {
+            Some(interval) => interval,
+            None => return false,
+        };
+        let ray_length: f64 = ray.direction().length();
+        loop {
+            t += self.step(ray_length);
+            if t >= t_exit {
+                return false;
+            }
+            let p: Vector3 = ray.point_at_parameter(t);
+            // A real collision with probability density / majorant, else a null one
+            if random::random_f64() * self.majorant < self.density_at(&p) {
+                rec.t = t;
+                rec.p = p;
+                rec.normal = Vector3::new(1.0, 0.0, 0.0);
+                rec.u = 0.0;
+                rec.v = 0.0;
+                rec.footprint = 0.0;
+                rec.material = self.phase_function.clone();
+                return true;
+            }
+        }
+    }
+
+    fn hit_surface(&self, _ray: &Ray, _t_min: f64, _t_max: f64, _rec: &mut HitRecord) -> bool {
+        false
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        if self.majorant <= 0.0 {
+            return 1.0;
+        }
+        let (mut t, t_exit): (f64, f64) = match self.inside(ray, t_min, t_max) {
+            Some(interval) => interval,
+            None => return 1.0,
+        };
+        let ray_length: f64 = ray.direction().length();
+        let mut transmittance: f64 = 1.0;
+        loop {
+            t += self.step(ray_length);
+            if t >= t_exit {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density_at(&ray.point_at_parameter(t)) / self.majorant;
+        }
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = Aabb::new(self.min, self.max);
+        true
+    }
+}
+
+#[test]
+fn test_grid_medium() {
+    use isotropic::Isotropic;
+
+    let phase: Material = Material::Isotropic(Isotropic::new(Vector3::new(0.8, 0.8, 0.8)));
+    // Density 1 in the back half of the box and 0 in the front half
+    let grid: VoxelGrid = VoxelGrid::new(1, 1, 4, vec![1.0, 1.0, 0.0, 0.0]);
+    let medium: GridMedium = GridMedium::new(
+        Arc::new(grid),
+        Vector3::new(-1.0, -1.0, -4.0),
+        Vector3::new(1.0, 1.0, 0.0),
+        0.5,
+        phase,
+    );
+    assert_eq!(medium.density_at(&Vector3::new(0.0, 0.0, -3.5)), 0.5);
+    assert_eq!(medium.density_at(&Vector3::new(0.0, 0.0, -0.5)), 0.0);
+
+    // Along z the ray crosses 1.5 units at density 0.5, then the density
+    // ramps down to 0 over one unit, so the optical depth is 1
+    let ray: Ray = Ray::new(Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, -1.0));
+    let expected: f64 = (-1.0f64).exp();
+    let trials: usize = 20000;
+    let mut rec: HitRecord = HitRecord::new();
+    let mut passed: usize = 0;
+    let mut ratio: f64 = 0.0;
+    for _ in 0..trials {
+        if !medium.hit(&ray, 0.001, f64::MAX, &mut rec) {
+            passed += 1;
+        } else {
+            assert!(rec.p.z() < -1.5 && rec.p.z() > -4.0);
+        }
+        ratio += medium.transmittance(&ray, 0.001, f64::MAX) / trials as f64;
+    }
+    assert!((passed as f64 / trials as f64 - expected).abs() < 0.02);
+    assert!((ratio - expected).abs() < 0.01, "{} vs {}", ratio, expected);
+
+    // Rays missing the box or stopping before it are untouched
+    let miss: Ray = Ray::new(Vector3::new(2.0, 0.0, 1.0), Vector3::new(0.0, 0.0, -1.0));
+    assert_eq!(medium.transmittance(&miss, 0.001, f64::MAX), 1.0);
+    assert_eq!(medium.transmittance(&ray, 0.001, 2.0), 1.0);
+    assert!(!medium.hit_surface(&ray, 0.001, f64::MAX, &mut rec));
+}
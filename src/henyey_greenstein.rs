@@ -0,0 +1,107 @@
+use std::f64::consts::PI;
+
+use hitable::HitRecord;
+use material::{ScatterRecord, Scatterable};
+use onb::Onb;
+use random;
+use ray::Ray;
+use texture::{self, SharedTexture};
+use vector::Vector3;
+
+// Henyey-Greenstein phase function. g is the mean cosine between the incoming
+// and scattered directions: positive scatters forward, negative backward and
+// zero is isotropic.
+#[derive(Clone, Debug)]
+pub struct HenyeyGreenstein {
+    pub albedo: SharedTexture,
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Vector3, g: f64) -> HenyeyGreenstein {
+        HenyeyGreenstein::textured(texture::constant(albedo), g)
+    }
+
+    // g is kept inside (-1, 1), at the ends the distribution is a delta
+    pub fn textured(albedo: SharedTexture, g: f64) -> HenyeyGreenstein {
+        HenyeyGreenstein {
+            albedo,
+            g: g.clamp(-0.999, 0.999),
+        }
+    }
+
+    pub fn g(&self) -> f64 {
+        self.g
+    }
+}
+
+// Density per steradian of turning by an angle with this cosine
+pub fn phase(g: f64, cos_theta: f64) -> f64 {
+    let denominator: f64 = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
+
+// Inverts the CDF of phase() for the cosine of the scattering angle
+pub fn sample_cos_theta(g: f64, xi: f64) -> f64 {
+    if g.abs() < 1e-3 {
+        return 1.0 - 2.0 * xi;
+    }
+    let s: f64 = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+    ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+}
+
+impl Scatterable for HenyeyGreenstein {
+    fn sample(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let cos_theta: f64 = sample_cos_theta(self.g, random::random_f64());
+        let sin_theta: f64 = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi: f64 = 2.0 * PI * random::random_f64();
+        let uvw: Onb = Onb::build_from_w(&ray_in.direction());
+        let direction: Vector3 = uvw.local(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        let scattered: Ray = Ray::new(rec.p(), direction).with_time(ray_in.time());
+        Some(ScatterRecord::new(scattered, self.albedo.value(rec.u, rec.v, &rec.p()), phase(self.g, cos_theta)))
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vector3) -> Vector3 {
+        self.albedo.value(rec.u, rec.v, &rec.p()) * self.pdf(ray_in, rec, direction)
+    }
+
+    fn pdf(&self, ray_in: &Ray, _rec: &HitRecord, direction: &Vector3) -> f64 {
+        let cos_theta: f64 = ray_in.direction().unit_vector().dot(&direction.unit_vector());
+        phase(self.g, cos_theta)
+    }
+}
+
+#[test]
+fn test_henyey_greenstein() {
+    // Normalised over the sphere for any g
+    for &g in &[-0.7, 0.0, 0.3, 0.9] {
+        let steps: usize = 100000;
+        let integral: f64 = (0..steps)
+            .map(|i| {
+                let cos_theta: f64 = -1.0 + 2.0 * (i as f64 + 0.5) / steps as f64;
+                2.0 * PI * phase(g, cos_theta) * 2.0 / steps as f64
+            })
+            .sum();
+        assert!((integral - 1.0).abs() < 1e-3, "g = {}: {}", g, integral);
+    }
+
+    // Sampled directions have mean cosine g
+    let material: HenyeyGreenstein = HenyeyGreenstein::new(Vector3::new(0.9, 0.9, 0.9), 0.6);
+    let ray: Ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 2.0, 0.0));
+    let samples: usize = 20000;
+    let mut mean: f64 = 0.0;
+    for _ in 0..samples {
+        let sample: ScatterRecord = material.sample(&ray, &HitRecord::new()).unwrap();
+        mean += sample.ray.direction().unit_vector().y() / samples as f64;
+        assert_eq!(sample.weight, Vector3::new(0.9, 0.9, 0.9));
+        // The density sampled with is the one pdf() reports
+        let pdf: f64 = material.pdf(&ray, &HitRecord::new(), &sample.ray.direction());
+        assert!((pdf - sample.pdf).abs() < 1e-9 * pdf);
+    }
+    assert!((mean - 0.6).abs() < 0.02, "{}", mean);
+
+    let forward: Vector3 = Vector3::new(0.0, 1.0, 0.0);
+    let backward: Vector3 = Vector3::new(0.0, -1.0, 0.0);
+    assert!(material.pdf(&ray, &HitRecord::new(), &forward) > material.pdf(&ray, &HitRecord::new(), &backward));
+    assert_eq!(HenyeyGreenstein::new(Vector3::new(1.0, 1.0, 1.0), 2.0).g(), 0.999);
+}
@@ -1,4 +1,7 @@
-use lambertian::Lambertian;
+use std::sync::Arc;
+
+use aabb::Aabb;
+use diffuse_light::DiffuseLight;
 use material::Material;
 use ray::Ray;
 use vector::Vector3;
@@ -8,6 +11,17 @@ pub struct HitRecord {
     pub p: Vector3,
     pub normal: Vector3,
     pub material: Material,
+    // Surface parametrisation at the hit point
+    pub u: f64,
+    pub v: f64,
+    // Width of the ray's footprint in texture space, 0 when unknown
+    pub footprint: f64,
+}
+
+impl Default for HitRecord {
+    fn default() -> HitRecord {
+        HitRecord::new()
+    }
 }
 
 impl HitRecord {
@@ -16,7 +30,11 @@ impl HitRecord {
             t: 0.0,
             p: Vector3::new(0.0, 0.0, 0.0),
             normal: Vector3::new(0.0, 0.0, 0.0),
-            material: Material::Lambertian(Lambertian::new(Vector3::new(0.0, 0.0, 0.0))),
+            // Black placeholder that needs no allocation, every hit overwrites it
+            material: Material::DiffuseLight(DiffuseLight::new(Vector3::new(0.0, 0.0, 0.0))),
+            u: 0.0,
+            v: 0.0,
+            footprint: 0.0,
         }
     }
 
@@ -28,15 +46,43 @@ impl HitRecord {
         self.p
     }
 
-    pub fn material(&self) -> Material {
-        self.material
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    pub fn uv(&self) -> (f64, f64) {
+        (self.u, self.v)
     }
 }
 
 pub trait Hittable {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool;
+
+    // Like hit, but participating media let the ray through. Shadow rays use
+    // this to find the light and account for media with transmittance().
+    fn hit_surface(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.hit(ray, t_min, t_max, rec)
+    }
+
+    // Fraction of light that gets through the media between t_min and t_max
+    fn transmittance(&self, _ray: &Ray, _t_min: f64, _t_max: f64) -> f64 {
+        1.0
+    }
+
+    // Solid angle density of random() sampling direction v from origin o;
+    // shapes that can't be sampled as lights keep the defaults
+    fn pdf_value(&self, _o: &Vector3, _v: &Vector3) -> f64 {
+        0.0
+    }
+
+    fn random(&self, _o: &Vector3) -> Vector3 {
+        Vector3::new(1.0, 0.0, 0.0)
+    }
 }
 
+pub type SharedHittable = Arc<dyn Hittable + Send + Sync>;
+
 #[test]
 fn test_hit_record() {
     let hit_record = HitRecord::new();
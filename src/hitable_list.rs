@@ -1,42 +1,164 @@
-use hitable::{HitRecord, Hittable};
+use std::sync::Arc;
+
+use aabb::Aabb;
+use hitable::{HitRecord, Hittable, SharedHittable};
+use random;
 use ray::Ray;
-use sphere::Sphere;
+use vector::Vector3;
 
-#[derive(Debug)]
+#[derive(Clone)]
 pub struct HittableList {
-    pub list: Vec<Sphere>,
+    pub list: Vec<SharedHittable>,
 }
 
 impl HittableList {
     pub fn new() -> HittableList {
-        let spheres_list: Vec<Sphere> = Vec::new();
-        HittableList { list: spheres_list }
+        let objects_list: Vec<SharedHittable> = Vec::new();
+        HittableList { list: objects_list }
+    }
+
+    pub fn add<H: Hittable + Send + Sync + 'static>(&mut self, object: H) {
+        self.list.push(Arc::new(object));
+    }
+
+    // Adds an object that may also be referenced from elsewhere in the scene
+    pub fn add_shared(&mut self, object: SharedHittable) {
+        self.list.push(object);
+    }
+
+    pub fn remove(&mut self, index: usize) -> SharedHittable {
+        self.list.remove(index)
+    }
+
+    pub fn clear(&mut self) {
+        self.list.clear();
+    }
+
+    pub fn get(&self, index: usize) -> Option<&SharedHittable> {
+        self.list.get(index)
+    }
+
+    pub fn objects(&self) -> &[SharedHittable] {
+        &self.list
     }
 
-    pub fn add_sphere(&mut self, sphere: Sphere) {
-        self.list.push(sphere);
+    pub fn iter(&self) -> std::slice::Iter<'_, SharedHittable> {
+        self.list.iter()
     }
 
     pub fn size(&self) -> usize {
         self.list.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+}
+
+impl Default for HittableList {
+    fn default() -> HittableList {
+        HittableList::new()
+    }
+}
+
+impl<'a> IntoIterator for &'a HittableList {
+    type Item = &'a SharedHittable;
+    type IntoIter = std::slice::Iter<'a, SharedHittable>;
+
+    fn into_iter(self) -> std::slice::Iter<'a, SharedHittable> {
+        self.list.iter()
+    }
 }
 
 impl Hittable for HittableList {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
-        let mut temp_rec: HitRecord = HitRecord::new();
         let mut hit_anything: bool = false;
         let mut closest_so_far: f64 = t_max;
-        for i in 0..self.size() {
-            if self.list[i].hit(ray, t_min, closest_so_far, &mut temp_rec) {
+        for object in &self.list {
+            let mut temp_rec: HitRecord = HitRecord::new();
+            if object.hit(ray, t_min, closest_so_far, &mut temp_rec) {
                 hit_anything = true;
                 closest_so_far = temp_rec.t;
-                rec.p = temp_rec.p;
-                rec.t = temp_rec.t;
-                rec.normal = temp_rec.normal;
-                rec.material = temp_rec.material;
+                *rec = temp_rec;
             }
         }
         hit_anything
     }
+
+    fn hit_surface(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut hit_anything: bool = false;
+        let mut closest_so_far: f64 = t_max;
+        for object in &self.list {
+            let mut temp_rec: HitRecord = HitRecord::new();
+            if object.hit_surface(ray, t_min, closest_so_far, &mut temp_rec) {
+                hit_anything = true;
+                closest_so_far = temp_rec.t;
+                *rec = temp_rec;
+            }
+        }
+        hit_anything
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.list.iter().map(|object| object.transmittance(ray, t_min, t_max)).product()
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        if self.list.is_empty() {
+            return false;
+        }
+        let mut result: Aabb = Aabb::empty();
+        let mut temp_box: Aabb = Aabb::empty();
+        for object in &self.list {
+            if !object.bounding_box(&mut temp_box) {
+                return false;
+            }
+            result = result.surrounding_box(&temp_box);
+        }
+        *output_box = result;
+        true
+    }
+
+    // Mixture of the members, each picked with equal probability
+    fn pdf_value(&self, o: &Vector3, v: &Vector3) -> f64 {
+        if self.list.is_empty() {
+            return 0.0;
+        }
+        let weight: f64 = 1.0 / self.list.len() as f64;
+        self.list.iter().map(|object| weight * object.pdf_value(o, v)).sum()
+    }
+
+    fn random(&self, o: &Vector3) -> Vector3 {
+        if self.list.is_empty() {
+            return Vector3::new(1.0, 0.0, 0.0);
+        }
+        let index: usize = ((random::random_f64() * self.list.len() as f64) as usize).min(self.list.len() - 1);
+        self.list[index].random(o)
+    }
+}
+
+#[test]
+fn test_nested_list() {
+    use lambertian::Lambertian;
+    use material::Material;
+    use sphere::Sphere;
+    use vector::Vector3;
+
+    let material: Material = Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+    let mut inner: HittableList = HittableList::new();
+    inner.add(Sphere::new(Vector3::new(0.0, 0.0, -5.0), 1.0, material.clone()));
+    let mut world: HittableList = HittableList::new();
+    world.add(Sphere::new(Vector3::new(0.0, 0.0, -10.0), 1.0, material));
+    world.add(inner);
+    assert_eq!(world.size(), 2);
+
+    let ray: Ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+    let mut rec: HitRecord = HitRecord::new();
+    assert!(world.hit(&ray, 0.001, f64::MAX, &mut rec));
+    assert_eq!(rec.t, 4.0);
+
+    world.remove(1);
+    assert_eq!(world.size(), 1);
+    assert!(world.hit(&ray, 0.001, f64::MAX, &mut rec));
+    assert_eq!(rec.t, 9.0);
 }
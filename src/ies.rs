@@ -0,0 +1,372 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use light::aim_or_down;
+use onb::Onb;
+use vector::Vector3;
+
+// IES LM-63 photometric files: free form keyword lines, a TILT= line, then
+// whitespace or comma separated numbers
+//
+//     <lamps> <lumens per lamp> <candela multiplier> <vertical count> <horizontal count>
+//     <photometric type> <units> <width> <length> <height>
+//     <ballast factor> <future use> <input watts>
+//     <vertical angles> <horizontal angles>
+//     <candela values, one vertical run per horizontal angle>
+//
+// Only type C photometry is read, the one used by building fixtures: vertical
+// angles run from 0 straight down to 180 straight up and horizontal angles
+// go around the vertical axis.
+#[derive(Debug)]
+pub enum IesError {
+    Io { file: String, error: io::Error },
+    Format { file: String, message: String },
+}
+
+impl fmt::Display for IesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IesError::Io { ref file, ref error } => write!(f, "{}: {}", file, error),
+            IesError::Format { ref file, ref message } => write!(f, "{}: {}", file, message),
+        }
+    }
+}
+
+impl Error for IesError {}
+
+#[derive(Clone, Debug)]
+pub struct IesProfile {
+    vertical: Vec<f64>,
+    horizontal: Vec<f64>,
+    // One run over the vertical angles per horizontal angle
+    candela: Vec<Vec<f64>>,
+    max: f64,
+}
+
+impl IesProfile {
+    pub fn load(path: &Path) -> Result<IesProfile, IesError> {
+        let file: String = path.display().to_string();
+        let data: Vec<u8> = fs::read(path).map_err(|error| IesError::Io {
+            file: file.clone(),
+            error,
+        })?;
+        IesProfile::parse(&String::from_utf8_lossy(&data), &file)
+    }
+
+    pub fn parse(source: &str, file: &str) -> Result<IesProfile, IesError> {
+        parse_profile(source).map_err(|message| IesError::Format {
+            file: file.to_string(),
+            message,
+        })
+    }
+
+    // Candela at the largest value in the file
+    pub fn max_candela(&self) -> f64 {
+        self.max
+    }
+
+    // Candela in a direction given by its angles in degrees, interpolated
+    // between the measured ones and zero outside the vertical range
+    pub fn candela(&self, vertical: f64, horizontal: f64) -> f64 {
+        let (first, last): (f64, f64) = (self.vertical[0], self.vertical[self.vertical.len() - 1]);
+        if vertical < first || vertical > last {
+            return 0.0;
+        }
+        let (v0, v1, tv): (usize, usize, f64) = bracket(&self.vertical, vertical);
+        let (h0, h1, th): (usize, usize, f64) = bracket(&self.horizontal, self.fold(horizontal));
+        let along = |h: usize| self.candela[h][v0] + tv * (self.candela[h][v1] - self.candela[h][v0]);
+        along(h0) + th * (along(h1) - along(h0))
+    }
+
+    // Horizontal angle in the range the file covers, using the symmetry its
+    // range implies
+    fn fold(&self, horizontal: f64) -> f64 {
+        let h: f64 = horizontal.rem_euclid(360.0);
+        let (first, last): (f64, f64) = (self.horizontal[0], self.horizontal[self.horizontal.len() - 1]);
+        if self.horizontal.len() == 1 {
+            first
+        } else if first == 90.0 {
+            // Symmetric about the 90-270 plane
+            if h < 90.0 {
+                180.0 - h
+            } else if h > 270.0 {
+                540.0 - h
+            } else {
+                h
+            }
+        } else if last == 90.0 {
+            let h: f64 = if h > 180.0 { 360.0 - h } else { h };
+            if h > 90.0 {
+                180.0 - h
+            } else {
+                h
+            }
+        } else if last == 180.0 && h > 180.0 {
+            360.0 - h
+        } else {
+            h
+        }
+    }
+}
+
+// Indices of the angles around x and the weight of the second, angles ascend
+fn bracket(angles: &[f64], x: f64) -> (usize, usize, f64) {
+    let n: usize = angles.len();
+    if n == 1 || x <= angles[0] {
+        return (0, 0, 0.0);
+    }
+    if x >= angles[n - 1] {
+        return (n - 1, n - 1, 0.0);
+    }
+    let i: usize = angles.iter().rposition(|&a| a <= x).unwrap();
+    (i, i + 1, (x - angles[i]) / (angles[i + 1] - angles[i]))
+}
+
+fn parse_profile(source: &str) -> Result<IesProfile, String> {
+    let mut lines = source.lines();
+    let tilt: &str = loop {
+        match lines.next() {
+            Some(line) if line.trim_start().starts_with("TILT=") => break line.trim_start()["TILT=".len()..].trim(),
+            Some(_) => continue,
+            None => return Err("missing TILT= line".to_string()),
+        }
+    };
+    let rest: Vec<&str> = lines.collect();
+    let mut values = rest
+        .iter()
+        .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+        .filter(|s| !s.is_empty());
+    let mut next = |what: &str| -> Result<f64, String> {
+        let token: &str = values.next().ok_or_else(|| format!("unexpected end of file, expected {}", what))?;
+        token.parse::<f64>().map_err(|_| format!("invalid {} '{}'", what, token))
+    };
+    let count = |value: f64, what: &str| -> Result<usize, String> {
+        if value < 1.0 || value.fract() != 0.0 {
+            return Err(format!("invalid {} {}", what, value));
+        }
+        Ok(value as usize)
+    };
+
+    match tilt {
+        "NONE" => {}
+        // Lamp tilt only changes output for tilted mountings, skip the table
+        "INCLUDE" => {
+            next("lamp to luminaire geometry")?;
+            let pairs: f64 = next("tilt angle count")?;
+            for _ in 0..count(pairs, "tilt angle count")? {
+                next("tilt angle")?;
+                next("tilt multiplier")?;
+            }
+        }
+        _ => return Err(format!("TILT={} refers to a separate file, which isn't supported", tilt)),
+    }
+
+    let _lamps: f64 = next("lamp count")?;
+    let _lumens: f64 = next("lumens per lamp")?;
+    let multiplier: f64 = next("candela multiplier")?;
+    let vertical_count: usize = count(next("vertical angle count")?, "vertical angle count")?;
+    let horizontal_count: usize = count(next("horizontal angle count")?, "horizontal angle count")?;
+    let photometric_type: f64 = next("photometric type")?;
+    if photometric_type != 1.0 {
+        return Err(format!("photometric type {} isn't supported, only type C (1)", photometric_type));
+    }
+    for what in &["units type", "width", "length", "height"] {
+        next(what)?;
+    }
+    let ballast: f64 = next("ballast factor")?;
+    next("future use")?;
+    next("input watts")?;
+
+    // The counts aren't trusted for allocation, the vectors only grow as far
+    // as the file has values
+    let mut vertical: Vec<f64> = Vec::new();
+    for _ in 0..vertical_count {
+        vertical.push(next("vertical angle")?);
+    }
+    let mut horizontal: Vec<f64> = Vec::new();
+    for _ in 0..horizontal_count {
+        horizontal.push(next("horizontal angle")?);
+    }
+    if vertical.windows(2).any(|w| w[1] <= w[0]) {
+        return Err("vertical angles must be increasing".to_string());
+    }
+    if horizontal.windows(2).any(|w| w[1] <= w[0]) {
+        return Err("horizontal angles must be increasing".to_string());
+    }
+    let (first, last): (f64, f64) = (vertical[0], vertical[vertical_count - 1]);
+    if !(first == 0.0 || first == 90.0) || !(last == 90.0 || last == 180.0) {
+        return Err(format!("vertical angles must start at 0 or 90 and end at 90 or 180, found {} to {}", first, last));
+    }
+    let (first, last): (f64, f64) = (horizontal[0], horizontal[horizontal_count - 1]);
+    let symmetric: bool = horizontal_count == 1
+        || (first == 0.0 && (last == 90.0 || last == 180.0 || last == 360.0))
+        || (first == 90.0 && last == 270.0);
+    if !symmetric {
+        return Err(format!("horizontal angles from {} to {} don't match a symmetry of type C photometry", first, last));
+    }
+
+    let scale: f64 = multiplier * ballast;
+    let mut candela: Vec<Vec<f64>> = Vec::new();
+    let mut max: f64 = 0.0;
+    for _ in 0..horizontal_count {
+        let mut run: Vec<f64> = Vec::new();
+        for _ in 0..vertical_count {
+            let value: f64 = next("candela value")? * scale;
+            if value < 0.0 || !value.is_finite() {
+                return Err(format!("invalid candela value {}", value));
+            }
+            max = max.max(value);
+            run.push(value);
+        }
+        candela.push(run);
+    }
+    if let Some(extra) = values.next() {
+        return Err(format!("unexpected '{}' after the candela values", extra));
+    }
+    if max <= 0.0 {
+        return Err("all candela values are zero".to_string());
+    }
+    Ok(IesProfile {
+        vertical,
+        horizontal,
+        candela,
+        max,
+    })
+}
+
+// A profile aimed along a direction, its 0 degree vertical angle. The 0
+// degree horizontal plane lies towards +x, or +z for profiles aimed along x.
+#[derive(Clone, Debug)]
+pub struct Photometry {
+    profile: Arc<IesProfile>,
+    frame: Onb,
+}
+
+impl Photometry {
+    // A zero aim points down like an unaimed profile
+    pub fn new(profile: Arc<IesProfile>, aim: Vector3) -> Photometry {
+        let w: Vector3 = aim_or_down(&aim);
+        let reference: Vector3 = if w.x().abs() > 0.99 {
+            Vector3::new(0.0, 0.0, 1.0)
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let u: Vector3 = (reference - reference.dot(&w) * w).unit_vector();
+        Photometry {
+            profile,
+            frame: Onb { u, v: w.cross(&u), w },
+        }
+    }
+
+    // Intensity along a unit direction leaving the light relative to the
+    // brightest direction of the profile
+    pub fn relative(&self, direction: &Vector3) -> f64 {
+        let local: Vector3 = self.frame.to_local(direction);
+        let vertical: f64 = local.z().clamp(-1.0, 1.0).acos().to_degrees();
+        let horizontal: f64 = local.y().atan2(local.x()).to_degrees();
+        self.profile.candela(vertical, horizontal) / self.profile.max_candela()
+    }
+}
+
+#[test]
+fn test_ies_downlight() {
+    // Rotationally symmetric, lights the lower hemisphere only
+    let profile: IesProfile = IesProfile::load(Path::new("scenes/ies/downlight.ies")).unwrap();
+    assert_eq!(profile.max_candela(), 2000.0);
+    assert_eq!(profile.candela(0.0, 0.0), 2000.0);
+    assert_eq!(profile.candela(0.0, 123.0), 2000.0);
+    assert_eq!(profile.candela(30.0, 250.0), 1200.0);
+    assert_eq!(profile.candela(15.0, 10.0), 1600.0);
+    assert_eq!(profile.candela(90.0, 0.0), 0.0);
+    assert_eq!(profile.candela(120.0, 0.0), 0.0);
+
+    // Aimed down, straight down is the brightest and sideways is dark
+    let photometry: Photometry = Photometry::new(Arc::new(profile), Vector3::new(0.0, -1.0, 0.0));
+    assert_eq!(photometry.relative(&Vector3::new(0.0, -1.0, 0.0)), 1.0);
+    let down30: Vector3 = Vector3::new(0.5, -(3.0f64.sqrt() / 2.0), 0.0);
+    assert!((photometry.relative(&down30) - 0.6).abs() < 1e-9);
+    assert_eq!(photometry.relative(&Vector3::new(0.0, 1.0, 0.0)), 0.0);
+}
+
+#[test]
+fn test_ies_asymmetric() {
+    // Bilateral profile with a tilt table and values split over lines and
+    // commas, throwing more light towards 0 than 180 degrees horizontally
+    let profile: IesProfile = IesProfile::load(Path::new("scenes/ies/wallwasher.ies")).unwrap();
+    // Multiplier 2 and ballast factor 0.5 cancel out
+    assert_eq!(profile.max_candela(), 900.0);
+    assert_eq!(profile.candela(45.0, 0.0), 900.0);
+    assert_eq!(profile.candela(45.0, 180.0), 100.0);
+    assert_eq!(profile.candela(45.0, 45.0), 700.0);
+    // Mirrored across the 0-180 plane
+    assert_eq!(profile.candela(45.0, 315.0), profile.candela(45.0, 45.0));
+    assert_eq!(profile.candela(45.0, 270.0), profile.candela(45.0, 90.0));
+    // Halfway between two vertical and two horizontal angles
+    let corner: f64 = (profile.candela(0.0, 0.0) + profile.candela(0.0, 90.0)
+        + profile.candela(45.0, 0.0) + profile.candela(45.0, 90.0))
+        / 4.0;
+    assert!((profile.candela(22.5, 45.0) - corner).abs() < 1e-9);
+
+    // Quadrant symmetry folds every quadrant onto the first
+    let quadrant: IesProfile = IesProfile::load(Path::new("scenes/ies/quadrant.ies")).unwrap();
+    for &h in &[10.0, 170.0, 190.0, 350.0] {
+        assert_eq!(quadrant.candela(60.0, h), quadrant.candela(60.0, 10.0));
+    }
+    assert!(quadrant.candela(60.0, 0.0) > quadrant.candela(60.0, 90.0));
+}
+
+#[test]
+fn test_ies_errors() {
+    let message = |source: &str| -> String {
+        match IesProfile::parse(source, "lamp.ies") {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e.to_string(),
+        }
+    };
+    assert_eq!(message("IESNA:LM-63-2002\n[TEST] x\n"), "lamp.ies: missing TILT= line");
+    assert_eq!(
+        message("TILT=lamp.tlt\n"),
+        "lamp.ies: TILT=lamp.tlt refers to a separate file, which isn't supported"
+    );
+    assert_eq!(
+        message("TILT=NONE\n1 1000 1 2 1 2 1 0 0 0\n1 1 10\n0 90\n0\n5 5\n"),
+        "lamp.ies: photometric type 2 isn't supported, only type C (1)"
+    );
+    assert_eq!(
+        message("TILT=NONE\n1 1000 1 2 1 1 1 0 0 0\n1 1 10\n0 90\n0\n5\n"),
+        "lamp.ies: unexpected end of file, expected candela value"
+    );
+    assert_eq!(
+        message("TILT=NONE\n1 1000 1 2 1 1 1 0 0 0\n1 1 10\n90 0\n0\n5 5\n"),
+        "lamp.ies: vertical angles must be increasing"
+    );
+    assert_eq!(
+        message("TILT=NONE\n1 1000 1 2 2 1 1 0 0 0\n1 1 10\n0 90\n0 45\n5 5 5 5\n"),
+        "lamp.ies: horizontal angles from 0 to 45 don't match a symmetry of type C photometry"
+    );
+    assert_eq!(
+        message("TILT=NONE\n1 1000 1 2 1 1 1 0 0 0\n1 1 10\n0 90\n0\n5 x\n"),
+        "lamp.ies: invalid candela value 'x'"
+    );
+    assert_eq!(
+        message("TILT=NONE\n1 1000 1 2 1 1 1 0 0 0\n1 1 10\n0 90\n0\n5 5 5\n"),
+        "lamp.ies: unexpected '5' after the candela values"
+    );
+    // Huge counts run out of values instead of allocating for them up front
+    assert_eq!(
+        message("TILT=NONE\n1 1000 1 1e18 1 1 1 0 0 0\n1 1 10\n"),
+        "lamp.ies: unexpected end of file, expected vertical angle"
+    );
+    assert_eq!(
+        message("TILT=NONE\n1 1000 1 1 1e18 1 1 0 0 0\n1 1 10\n0\n"),
+        "lamp.ies: unexpected end of file, expected horizontal angle"
+    );
+    assert_eq!(
+        message("TILT=INCLUDE\n1\n1e300\n0 1\n"),
+        "lamp.ies: unexpected end of file, expected tilt angle"
+    );
+}
@@ -0,0 +1,1301 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use render::Framebuffer;
+use vector::Vector3;
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io { file: String, error: io::Error },
+    Decode { file: String, message: String },
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ImageError::Io { ref file, ref error } => write!(f, "{}: {}", file, error),
+            ImageError::Decode { ref file, ref message } => write!(f, "{}: {}", file, message),
+        }
+    }
+}
+
+impl Error for ImageError {}
+
+// Loads a PNG, JPEG or Radiance HDR file, told apart by their magic bytes.
+// 8 and 16 bit images are taken to be sRGB and converted to linear values.
+pub fn load_image(path: &Path) -> Result<Framebuffer, ImageError> {
+    let file: String = path.display().to_string();
+    let data: Vec<u8> = fs::read(path).map_err(|error| ImageError::Io {
+        file: file.clone(),
+        error,
+    })?;
+    decode_image(&data, &file)
+}
+
+pub fn decode_image(data: &[u8], file: &str) -> Result<Framebuffer, ImageError> {
+    let decoded: Result<Framebuffer, String> = if data.starts_with(PNG_SIGNATURE) {
+        decode_png(data)
+    } else if data.starts_with(&[0xff, 0xd8]) {
+        decode_jpeg(data)
+    } else if data.starts_with(b"#?") {
+        decode_hdr(data)
+    } else {
+        Err("unknown image format, expected PNG, JPEG or Radiance HDR".to_string())
+    };
+    decoded.map_err(|message| ImageError::Decode {
+        file: file.to_string(),
+        message,
+    })
+}
+
+pub fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn truncated() -> String {
+    "unexpected end of data".to_string()
+}
+
+// Largest image the decoders allocate for, an 8192 x 4096 environment map, so
+// a corrupt header costs at most ~800 MB of Vector3 pixels
+const MAX_PIXELS: usize = 1 << 25;
+
+fn check_size(width: usize, height: usize) -> Result<(), String> {
+    match width.checked_mul(height) {
+        Some(pixels) if pixels <= MAX_PIXELS => Ok(()),
+        _ => Err(format!("{} x {} image is too large", width, height)),
+    }
+}
+
+fn framebuffer(width: usize, height: usize, pixels: Vec<Vector3>) -> Framebuffer {
+    let mut image: Framebuffer = Framebuffer::new(width, height);
+    for (i, pixel) in pixels.into_iter().enumerate() {
+        image.set(i % width, i / width, pixel);
+    }
+    image
+}
+
+// Deflate (RFC 1951) with canonical Huffman codes decoded a bit at a time
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, n: u32) -> Result<u32, String> {
+        while self.count < n {
+            let byte: u8 = *self.data.get(self.pos).ok_or_else(truncated)?;
+            self.pos += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value: u32 = self.buffer & ((1u64 << n) - 1) as u32;
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+struct Huffman {
+    // Number of codes of each length, then the symbols ordered by code
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts: [u16; 16] = [0; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets: [u16; 16] = [0; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols: Vec<u16> = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count: i32 = self.counts[len] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code in deflate stream".to_string())
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097,
+    6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn too_long(limit: usize) -> String {
+    format!("deflate stream inflates past the expected {} bytes", limit)
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    limit: usize,
+    lengths: &Huffman,
+    distances: &Huffman,
+) -> Result<(), String> {
+    loop {
+        let symbol: usize = lengths.decode(reader)? as usize;
+        if symbol < 256 && out.len() >= limit {
+            return Err(too_long(limit));
+        } else if symbol < 256 {
+            out.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let index: usize = symbol - 257;
+            if index >= 29 {
+                return Err("invalid length code in deflate stream".to_string());
+            }
+            let length: usize = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+            let code: usize = distances.decode(reader)? as usize;
+            if code >= 30 {
+                return Err("invalid distance code in deflate stream".to_string());
+            }
+            let distance: usize = DISTANCE_BASE[code] as usize + reader.bits(DISTANCE_EXTRA[code] as u32)? as usize;
+            if distance > out.len() {
+                return Err("deflate distance reaches before the start of the data".to_string());
+            }
+            if out.len() + length > limit {
+                return Err(too_long(limit));
+            }
+            let start: usize = out.len() - distance;
+            // Copies may overlap the bytes they produce, so go one at a time
+            for i in 0..length {
+                let byte: u8 = out[start + i];
+                out.push(byte);
+            }
+        }
+    }
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let literal_count: usize = reader.bits(5)? as usize + 257;
+    let distance_count: usize = reader.bits(5)? as usize + 1;
+    let code_count: usize = reader.bits(4)? as usize + 4;
+    let mut code_lengths: [u8; 19] = [0; 19];
+    for &index in CODE_LENGTH_ORDER.iter().take(code_count) {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_table: Huffman = Huffman::new(&code_lengths);
+
+    let mut lengths: Vec<u8> = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol: u16 = code_table.decode(reader)?;
+        let (value, repeat): (u8, u32) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous: u8 = *lengths
+                    .last()
+                    .ok_or_else(|| "deflate repeat code without a previous length".to_string())?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err("deflate code lengths overrun the table".to_string());
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+// Decompresses raw deflate data, failing once the output grows past limit
+pub fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+    let mut reader: BitReader = BitReader {
+        data,
+        pos: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let mut out: Vec<u8> = Vec::new();
+    loop {
+        let last: bool = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let pos: usize = reader.pos;
+                if pos + 4 > data.len() {
+                    return Err(truncated());
+                }
+                let length: usize = data[pos] as usize | (data[pos + 1] as usize) << 8;
+                let complement: usize = data[pos + 2] as usize | (data[pos + 3] as usize) << 8;
+                if length != !complement & 0xffff {
+                    return Err("corrupt stored block length in deflate stream".to_string());
+                }
+                let block: &[u8] = data.get(pos + 4..pos + 4 + length).ok_or_else(truncated)?;
+                if out.len() + length > limit {
+                    return Err(too_long(limit));
+                }
+                out.extend_from_slice(block);
+                reader.pos = pos + 4 + length;
+            }
+            1 => {
+                let mut lengths: [u8; 288] = [8; 288];
+                for length in lengths.iter_mut().skip(144).take(112) {
+                    *length = 9;
+                }
+                for length in lengths.iter_mut().skip(256).take(24) {
+                    *length = 7;
+                }
+                inflate_block(&mut reader, &mut out, limit, &Huffman::new(&lengths), &Huffman::new(&[5; 30]))?;
+            }
+            2 => {
+                let (lengths, distances) = dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &mut out, limit, &lengths, &distances)?;
+            }
+            _ => return Err("invalid deflate block type".to_string()),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn zlib_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+    if data.len() < 2 {
+        return Err(truncated());
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    if cmf & 0x0f != 8 || !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+        return Err("invalid zlib header".to_string());
+    }
+    if flg & 0x20 != 0 {
+        return Err("zlib preset dictionaries are not supported".to_string());
+    }
+    inflate(&data[2..], limit)
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+fn be_u32(data: &[u8]) -> u32 {
+    (data[0] as u32) << 24 | (data[1] as u32) << 16 | (data[2] as u32) << 8 | data[3] as u32
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p: i16 = a as i16 + b as i16 - c as i16;
+    let pa: i16 = (p - a as i16).abs();
+    let pb: i16 = (p - b as i16).abs();
+    let pc: i16 = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Reverses the per scanline filters in place, rows are filter byte + row_bytes
+fn unfilter(data: &mut [u8], rows: usize, row_bytes: usize, pixel_bytes: usize) -> Result<(), String> {
+    let stride: usize = row_bytes + 1;
+    for y in 0..rows {
+        let (before, current) = data.split_at_mut(y * stride);
+        let previous: Option<&[u8]> = if y > 0 { Some(&before[(y - 1) * stride + 1..]) } else { None };
+        let filter: u8 = current[0];
+        let row: &mut [u8] = &mut current[1..stride];
+        for x in 0..row_bytes {
+            let a: u8 = if x >= pixel_bytes { row[x - pixel_bytes] } else { 0 };
+            let b: u8 = previous.map_or(0, |p| p[x]);
+            let c: u8 = if x >= pixel_bytes { previous.map_or(0, |p| p[x - pixel_bytes]) } else { 0 };
+            row[x] = row[x].wrapping_add(match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(format!("invalid PNG filter type {}", filter)),
+            });
+        }
+    }
+    Ok(())
+}
+
+struct PngHeader {
+    width: usize,
+    height: usize,
+    depth: u8,
+    color_type: u8,
+    channels: usize,
+}
+
+impl PngHeader {
+    fn row_bytes(&self, width: usize) -> usize {
+        (width * self.channels * self.depth as usize).div_ceil(8)
+    }
+
+    // Channel c of pixel x in an unfiltered row, scaled to 0..=max
+    fn sample(&self, row: &[u8], x: usize, c: usize) -> u32 {
+        let index: usize = x * self.channels + c;
+        match self.depth {
+            16 => (row[2 * index] as u32) << 8 | row[2 * index + 1] as u32,
+            8 => row[index] as u32,
+            depth => {
+                let bit: usize = index * depth as usize;
+                let byte: u8 = row[bit / 8];
+                let shift: usize = 8 - depth as usize - bit % 8;
+                ((byte >> shift) & ((1u16 << depth) - 1) as u8) as u32
+            }
+        }
+    }
+}
+
+fn decode_png(data: &[u8]) -> Result<Framebuffer, String> {
+    let mut pos: usize = PNG_SIGNATURE.len();
+    let mut header: Option<PngHeader> = None;
+    let mut interlaced: bool = false;
+    let mut palette: Vec<Vector3> = Vec::new();
+    let mut compressed: Vec<u8> = Vec::new();
+    loop {
+        if pos + 8 > data.len() {
+            return Err(truncated());
+        }
+        let length: usize = be_u32(&data[pos..]) as usize;
+        let kind: &[u8] = &data[pos + 4..pos + 8];
+        let body: &[u8] = data.get(pos + 8..pos + 8 + length).ok_or_else(truncated)?;
+        // Skip the CRC as well
+        pos += length + 12;
+        match kind {
+            b"IHDR" => {
+                if length != 13 {
+                    return Err("invalid PNG header".to_string());
+                }
+                let depth: u8 = body[8];
+                let color_type: u8 = body[9];
+                let channels: usize = match (color_type, depth) {
+                    (0, 1) | (0, 2) | (0, 4) | (0, 8) | (0, 16) => 1,
+                    (3, 1) | (3, 2) | (3, 4) | (3, 8) => 1,
+                    (2, 8) | (2, 16) => 3,
+                    (4, 8) | (4, 16) => 2,
+                    (6, 8) | (6, 16) => 4,
+                    _ => return Err(format!("unsupported PNG color type {} at {} bits", color_type, depth)),
+                };
+                if body[10] != 0 || body[11] != 0 || body[12] > 1 {
+                    return Err("unsupported PNG compression, filter or interlace method".to_string());
+                }
+                interlaced = body[12] == 1;
+                header = Some(PngHeader {
+                    width: be_u32(body) as usize,
+                    height: be_u32(&body[4..]) as usize,
+                    depth,
+                    color_type,
+                    channels,
+                });
+            }
+            b"PLTE" => {
+                palette = body
+                    .chunks(3)
+                    .filter(|rgb| rgb.len() == 3)
+                    .map(|rgb| Vector3::new(rgb[0] as f64 / 255.0, rgb[1] as f64 / 255.0, rgb[2] as f64 / 255.0))
+                    .collect();
+            }
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+    let header: PngHeader = header.ok_or_else(|| "PNG without an IHDR chunk".to_string())?;
+    if header.width == 0 || header.height == 0 {
+        return Err("PNG image is empty".to_string());
+    }
+    check_size(header.width, header.height)?;
+    if header.color_type == 3 && palette.is_empty() {
+        return Err("palette PNG without a PLTE chunk".to_string());
+    }
+    let pixel_bytes: usize = (header.channels * header.depth as usize).div_ceil(8);
+    let max: f64 = ((1u32 << header.depth) - 1) as f64;
+
+    // Adam7 passes as (x0, y0, dx, dy), a single full pass otherwise
+    let passes: &[(usize, usize, usize, usize)] = if interlaced {
+        &[(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)]
+    } else {
+        &[(0, 0, 1, 1)]
+    };
+    let pass_size = |&(x0, y0, dx, dy): &(usize, usize, usize, usize)| -> (usize, usize) {
+        ((header.width + dx - 1 - x0) / dx, (header.height + dy - 1 - y0) / dy)
+    };
+    // Each pass row is a filter byte followed by the packed pixels
+    let expected: usize = passes
+        .iter()
+        .map(pass_size)
+        .filter(|&(width, height)| width > 0 && height > 0)
+        .map(|(width, height)| height * (header.row_bytes(width) + 1))
+        .sum();
+    let mut raw: Vec<u8> = zlib_decompress(&compressed, expected)?;
+    let mut pixels: Vec<Vector3> = vec![Vector3::new(0.0, 0.0, 0.0); header.width * header.height];
+    let mut offset: usize = 0;
+    for pass in passes {
+        let (width, height): (usize, usize) = pass_size(pass);
+        if width == 0 || height == 0 {
+            continue;
+        }
+        let &(x0, y0, dx, dy) = pass;
+        let row_bytes: usize = header.row_bytes(width);
+        let size: usize = height * (row_bytes + 1);
+        if offset + size > raw.len() {
+            return Err("PNG image data is shorter than the image".to_string());
+        }
+        let pass: &mut [u8] = &mut raw[offset..offset + size];
+        unfilter(pass, height, row_bytes, pixel_bytes)?;
+        for y in 0..height {
+            let row: &[u8] = &pass[y * (row_bytes + 1) + 1..(y + 1) * (row_bytes + 1)];
+            for x in 0..width {
+                let color: Vector3 = match header.color_type {
+                    3 => *palette
+                        .get(header.sample(row, x, 0) as usize)
+                        .ok_or_else(|| "PNG palette index out of range".to_string())?,
+                    0 | 4 => {
+                        let g: f64 = header.sample(row, x, 0) as f64 / max;
+                        Vector3::new(g, g, g)
+                    }
+                    _ => Vector3::new(
+                        header.sample(row, x, 0) as f64 / max,
+                        header.sample(row, x, 1) as f64 / max,
+                        header.sample(row, x, 2) as f64 / max,
+                    ),
+                };
+                pixels[(y0 + y * dy) * header.width + x0 + x * dx] = Vector3::new(
+                    srgb_to_linear(color.x),
+                    srgb_to_linear(color.y),
+                    srgb_to_linear(color.z),
+                );
+            }
+        }
+        offset += size;
+    }
+    Ok(framebuffer(header.width, header.height, pixels))
+}
+
+// Radiance RGBE with flat, old style or adaptive run length encoded scanlines
+fn decode_hdr(data: &[u8]) -> Result<Framebuffer, String> {
+    let mut pos: usize = 0;
+    let next_line = |pos: &mut usize| -> Result<String, String> {
+        let start: usize = *pos;
+        let end: usize = start + data[start..].iter().position(|&b| b == b'\n').ok_or_else(truncated)?;
+        *pos = end + 1;
+        Ok(String::from_utf8_lossy(&data[start..end]).trim().to_string())
+    };
+    loop {
+        let line: String = next_line(&mut pos)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(format!("unsupported Radiance format '{}'", format));
+            }
+        }
+    }
+    let resolution: String = next_line(&mut pos)?;
+    let fields: Vec<&str> = resolution.split_whitespace().collect();
+    let (height, width): (usize, usize) = match fields[..] {
+        ["-Y", h, "+X", w] => (
+            h.parse().map_err(|_| format!("invalid height '{}'", h))?,
+            w.parse().map_err(|_| format!("invalid width '{}'", w))?,
+        ),
+        _ => return Err(format!("unsupported Radiance resolution line '{}'", resolution)),
+    };
+    check_size(width, height)?;
+
+    let mut pixels: Vec<Vector3> = Vec::with_capacity(width * height);
+    let mut scanline: Vec<[u8; 4]> = vec![[0; 4]; width];
+    for _ in 0..height {
+        let rest: &[u8] = &data[pos..];
+        if (8..0x8000).contains(&width) && rest.len() >= 4 && rest[0] == 2 && rest[1] == 2 && rest[2] & 0x80 == 0 {
+            if ((rest[2] as usize) << 8 | rest[3] as usize) != width {
+                return Err("Radiance scanline width doesn't match the image".to_string());
+            }
+            pos += 4;
+            for channel in 0..4 {
+                let mut x: usize = 0;
+                while x < width {
+                    let count: usize = *data.get(pos).ok_or_else(truncated)? as usize;
+                    pos += 1;
+                    if count > 128 {
+                        let run: usize = count - 128;
+                        let value: u8 = *data.get(pos).ok_or_else(truncated)?;
+                        pos += 1;
+                        if x + run > width {
+                            return Err("Radiance run overflows the scanline".to_string());
+                        }
+                        for pixel in scanline.iter_mut().skip(x).take(run) {
+                            pixel[channel] = value;
+                        }
+                        x += run;
+                    } else {
+                        if count == 0 || x + count > width {
+                            return Err("invalid Radiance run length".to_string());
+                        }
+                        let values: &[u8] = data.get(pos..pos + count).ok_or_else(truncated)?;
+                        for (pixel, &value) in scanline.iter_mut().skip(x).zip(values) {
+                            pixel[channel] = value;
+                        }
+                        pos += count;
+                        x += count;
+                    }
+                }
+            }
+        } else {
+            let mut x: usize = 0;
+            let mut shift: u32 = 0;
+            while x < width {
+                let rgbe: &[u8] = data.get(pos..pos + 4).ok_or_else(truncated)?;
+                pos += 4;
+                if rgbe[0] == 1 && rgbe[1] == 1 && rgbe[2] == 1 && x > 0 {
+                    // Old style run: repeat the previous pixel, consecutive
+                    // runs hold ever higher bytes of the count
+                    if shift > 24 {
+                        return Err("Radiance run is too long".to_string());
+                    }
+                    let run: usize = (rgbe[3] as usize) << shift;
+                    if run == 0 {
+                        return Err("invalid Radiance run length".to_string());
+                    }
+                    if x + run > width {
+                        return Err("Radiance run overflows the scanline".to_string());
+                    }
+                    for i in 0..run {
+                        scanline[x + i] = scanline[x - 1];
+                    }
+                    x += run;
+                    shift += 8;
+                } else {
+                    scanline[x] = [rgbe[0], rgbe[1], rgbe[2], rgbe[3]];
+                    x += 1;
+                    shift = 0;
+                }
+            }
+        }
+        pixels.extend(scanline.iter().map(from_rgbe));
+    }
+    Ok(framebuffer(width, height, pixels))
+}
+
+fn from_rgbe(rgbe: &[u8; 4]) -> Vector3 {
+    if rgbe[3] == 0 {
+        return Vector3::new(0.0, 0.0, 0.0);
+    }
+    let scale: f64 = 2f64.powi(rgbe[3] as i32 - 136);
+    Vector3::new(
+        (rgbe[0] as f64 + 0.5) * scale,
+        (rgbe[1] as f64 + 0.5) * scale,
+        (rgbe[2] as f64 + 0.5) * scale,
+    )
+}
+
+// Baseline sequential JPEG: Huffman coded, 8 bit samples, grayscale or YCbCr
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21,
+    28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61,
+    54, 47, 55, 62, 63,
+];
+
+#[derive(Clone, Default)]
+struct JpegHuffman {
+    counts: [u16; 17],
+    symbols: Vec<u8>,
+}
+
+struct JpegComponent {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant: usize,
+    dc_table: usize,
+    ac_table: usize,
+    predictor: i32,
+    // Decoded samples, padded to whole MCUs
+    stride: usize,
+    samples: Vec<u8>,
+}
+
+// Entropy coded data with byte stuffing, stops at the next marker
+struct JpegBits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> JpegBits<'a> {
+    fn bit(&mut self) -> Result<u32, String> {
+        if self.count == 0 {
+            let mut byte: u8 = 0;
+            if self.pos < self.data.len() {
+                byte = self.data[self.pos];
+                if byte == 0xff {
+                    match self.data.get(self.pos + 1) {
+                        Some(0) => self.pos += 2,
+                        // A marker: feed zeros, a valid stream never reads them
+                        _ => byte = 0,
+                    }
+                } else {
+                    self.pos += 1;
+                }
+            }
+            self.buffer = byte as u32;
+            self.count = 8;
+        }
+        self.count -= 1;
+        Ok((self.buffer >> self.count) & 1)
+    }
+
+    fn bits(&mut self, n: u32) -> Result<i32, String> {
+        if n > 16 {
+            return Err(format!("invalid JPEG magnitude category {}", n));
+        }
+        let mut value: i32 = 0;
+        for _ in 0..n {
+            value = value << 1 | self.bit()? as i32;
+        }
+        Ok(value)
+    }
+
+    fn decode(&mut self, table: &JpegHuffman) -> Result<u8, String> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..17 {
+            code |= self.bit()? as i32;
+            let count: i32 = table.counts[len] as i32;
+            if code - count < first {
+                return table
+                    .symbols
+                    .get((index + code - first) as usize)
+                    .cloned()
+                    .ok_or_else(|| "invalid JPEG Huffman table".to_string());
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code in JPEG data".to_string())
+    }
+
+    // Sign extension of an s bit magnitude category
+    fn receive_extend(&mut self, s: u32) -> Result<i32, String> {
+        if s == 0 {
+            return Ok(0);
+        }
+        // bits rejects categories above 16, so the shifts below stay in range
+        let value: i32 = self.bits(s)?;
+        Ok(if value < 1 << (s - 1) { value - (1 << s) + 1 } else { value })
+    }
+
+    fn restart(&mut self) -> Result<(), String> {
+        self.count = 0;
+        match (self.data.get(self.pos), self.data.get(self.pos + 1)) {
+            (Some(&0xff), Some(&marker)) if (0xd0..=0xd7).contains(&marker) => {
+                self.pos += 2;
+                Ok(())
+            }
+            _ => Err("missing JPEG restart marker".to_string()),
+        }
+    }
+}
+
+fn idct_block(coefficients: &[i32; 64], quant: &[u16; 64], out: &mut [u8], stride: usize) {
+    let mut cosines: [[f64; 8]; 8] = [[0.0; 8]; 8];
+    for (x, row) in cosines.iter_mut().enumerate() {
+        for (u, value) in row.iter_mut().enumerate() {
+            let scale: f64 = if u == 0 { 0.5f64.sqrt() } else { 1.0 };
+            *value = scale * ((2 * x + 1) as f64 * u as f64 * std::f64::consts::PI / 16.0).cos() / 2.0;
+        }
+    }
+    let mut block: [f64; 64] = [0.0; 64];
+    for i in 0..64 {
+        block[i] = coefficients[i] as f64 * quant[i] as f64;
+    }
+    let mut rows: [f64; 64] = [0.0; 64];
+    for v in 0..8 {
+        for x in 0..8 {
+            rows[v * 8 + x] = (0..8).map(|u| cosines[x][u] * block[v * 8 + u]).sum();
+        }
+    }
+    for y in 0..8 {
+        for x in 0..8 {
+            let value: f64 = (0..8).map(|v| cosines[y][v] * rows[v * 8 + x]).sum::<f64>() + 128.0;
+            out[y * stride + x] = value.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+fn decode_jpeg(data: &[u8]) -> Result<Framebuffer, String> {
+    let mut pos: usize = 2;
+    let mut quant: [[u16; 64]; 4] = [[1; 64]; 4];
+    let mut dc_tables: Vec<JpegHuffman> = vec![JpegHuffman::default(); 4];
+    let mut ac_tables: Vec<JpegHuffman> = vec![JpegHuffman::default(); 4];
+    let mut components: Vec<JpegComponent> = Vec::new();
+    let (mut width, mut height): (usize, usize) = (0, 0);
+    let mut restart_interval: usize = 0;
+    // Adobe files may store plain RGB instead of YCbCr
+    let mut transform: bool = true;
+
+    loop {
+        // Fill bytes may precede a marker
+        while data.get(pos) == Some(&0xff) && data.get(pos + 1) == Some(&0xff) {
+            pos += 1;
+        }
+        if pos + 2 > data.len() || data[pos] != 0xff {
+            return Err("invalid JPEG marker".to_string());
+        }
+        let marker: u8 = data[pos + 1];
+        if marker == 0xd9 {
+            break;
+        }
+        if pos + 4 > data.len() {
+            return Err(truncated());
+        }
+        let length: usize = (data[pos + 2] as usize) << 8 | data[pos + 3] as usize;
+        let segment: &[u8] = data.get(pos + 4..pos + 2 + length).ok_or_else(truncated)?;
+        pos += 2 + length;
+        match marker {
+            0xdb => {
+                let mut s: &[u8] = segment;
+                while !s.is_empty() {
+                    let precision: u8 = s[0] >> 4;
+                    let id: usize = (s[0] & 3) as usize;
+                    let size: usize = if precision == 0 { 64 } else { 128 };
+                    let values: &[u8] = s.get(1..1 + size).ok_or_else(truncated)?;
+                    for i in 0..64 {
+                        quant[id][ZIGZAG[i]] = if precision == 0 {
+                            values[i] as u16
+                        } else {
+                            (values[2 * i] as u16) << 8 | values[2 * i + 1] as u16
+                        };
+                    }
+                    s = &s[1 + size..];
+                }
+            }
+            0xc4 => {
+                let mut s: &[u8] = segment;
+                while !s.is_empty() {
+                    let class: u8 = s[0] >> 4;
+                    let id: usize = (s[0] & 3) as usize;
+                    let mut table: JpegHuffman = JpegHuffman::default();
+                    let counts: &[u8] = s.get(1..17).ok_or_else(truncated)?;
+                    for (len, &count) in counts.iter().enumerate() {
+                        table.counts[len + 1] = count as u16;
+                    }
+                    let total: usize = counts.iter().map(|&c| c as usize).sum();
+                    table.symbols = s.get(17..17 + total).ok_or_else(truncated)?.to_vec();
+                    if class == 0 {
+                        dc_tables[id] = table;
+                    } else {
+                        ac_tables[id] = table;
+                    }
+                    s = &s[17 + total..];
+                }
+            }
+            0xc0 | 0xc1 => {
+                if segment.len() < 6 || segment[0] != 8 {
+                    return Err("only 8 bit JPEG images are supported".to_string());
+                }
+                height = (segment[1] as usize) << 8 | segment[2] as usize;
+                width = (segment[3] as usize) << 8 | segment[4] as usize;
+                let count: usize = segment[5] as usize;
+                if count != 1 && count != 3 {
+                    return Err(format!("unsupported JPEG with {} components", count));
+                }
+                for i in 0..count {
+                    let c: &[u8] = segment.get(6 + 3 * i..9 + 3 * i).ok_or_else(truncated)?;
+                    let (h, v): (usize, usize) = ((c[1] >> 4) as usize, (c[1] & 15) as usize);
+                    if h == 0 || v == 0 || h > 4 || v > 4 {
+                        return Err("invalid JPEG sampling factors".to_string());
+                    }
+                    components.push(JpegComponent {
+                        id: c[0],
+                        h,
+                        v,
+                        quant: (c[2] & 3) as usize,
+                        dc_table: 0,
+                        ac_table: 0,
+                        predictor: 0,
+                        stride: 0,
+                        samples: Vec::new(),
+                    });
+                }
+                if width == 0 || height == 0 {
+                    return Err("JPEG image is empty".to_string());
+                }
+                check_size(width, height)?;
+                let h_max: usize = components.iter().map(|c| c.h).max().unwrap();
+                let v_max: usize = components.iter().map(|c| c.v).max().unwrap();
+                let mcus_x: usize = width.div_ceil(8 * h_max);
+                let mcus_y: usize = height.div_ceil(8 * v_max);
+                for c in components.iter_mut() {
+                    c.stride = mcus_x * c.h * 8;
+                    c.samples = vec![0; c.stride * mcus_y * c.v * 8];
+                }
+            }
+            0xc2 | 0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => {
+                return Err("only baseline JPEG images are supported, not progressive, lossless or arithmetic".to_string());
+            }
+            0xdd => {
+                let value: &[u8] = segment.get(..2).ok_or_else(truncated)?;
+                restart_interval = (value[0] as usize) << 8 | value[1] as usize;
+            }
+            0xee if segment.starts_with(b"Adobe") && segment.len() >= 12 => {
+                transform = segment[11] != 0;
+            }
+            0xda => {
+                if components.is_empty() {
+                    return Err("JPEG scan before the frame header".to_string());
+                }
+                let count: usize = *segment.first().ok_or_else(truncated)? as usize;
+                let mut scan: Vec<usize> = Vec::with_capacity(count);
+                for i in 0..count {
+                    let s: &[u8] = segment.get(1 + 2 * i..3 + 2 * i).ok_or_else(truncated)?;
+                    let index: usize = components
+                        .iter()
+                        .position(|c| c.id == s[0])
+                        .ok_or_else(|| format!("JPEG scan refers to unknown component {}", s[0]))?;
+                    components[index].dc_table = (s[1] >> 4) as usize & 3;
+                    components[index].ac_table = (s[1] & 15) as usize & 3;
+                    components[index].predictor = 0;
+                    scan.push(index);
+                }
+                pos = decode_scan(data, pos, &mut components, &scan, &quant, &dc_tables, &ac_tables, width, height, restart_interval)?;
+            }
+            _ => {}
+        }
+    }
+    if components.is_empty() {
+        return Err("JPEG without a frame".to_string());
+    }
+
+    let h_max: usize = components.iter().map(|c| c.h).max().unwrap();
+    let v_max: usize = components.iter().map(|c| c.v).max().unwrap();
+    let sample = |c: &JpegComponent, x: usize, y: usize| -> f64 {
+        c.samples[(y * c.v / v_max) * c.stride + x * c.h / h_max] as f64
+    };
+    let mut pixels: Vec<Vector3> = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let color: Vector3 = if components.len() == 1 {
+                let g: f64 = sample(&components[0], x, y);
+                Vector3::new(g, g, g)
+            } else if transform {
+                let luma: f64 = sample(&components[0], x, y);
+                let cb: f64 = sample(&components[1], x, y) - 128.0;
+                let cr: f64 = sample(&components[2], x, y) - 128.0;
+                Vector3::new(luma + 1.402 * cr, luma - 0.344136 * cb - 0.714136 * cr, luma + 1.772 * cb)
+            } else {
+                Vector3::new(sample(&components[0], x, y), sample(&components[1], x, y), sample(&components[2], x, y))
+            };
+            pixels.push(Vector3::new(
+                srgb_to_linear((color.x / 255.0).clamp(0.0, 1.0)),
+                srgb_to_linear((color.y / 255.0).clamp(0.0, 1.0)),
+                srgb_to_linear((color.z / 255.0).clamp(0.0, 1.0)),
+            ));
+        }
+    }
+    Ok(framebuffer(width, height, pixels))
+}
+
+// Decodes the entropy coded segment after a SOS header, returns where it ends
+#[allow(clippy::too_many_arguments)]
+fn decode_scan(
+    data: &[u8],
+    start: usize,
+    components: &mut [JpegComponent],
+    scan: &[usize],
+    quant: &[[u16; 64]; 4],
+    dc_tables: &[JpegHuffman],
+    ac_tables: &[JpegHuffman],
+    width: usize,
+    height: usize,
+    restart_interval: usize,
+) -> Result<usize, String> {
+    let h_max: usize = components.iter().map(|c| c.h).max().unwrap();
+    let v_max: usize = components.iter().map(|c| c.v).max().unwrap();
+    let mut bits: JpegBits = JpegBits {
+        data,
+        pos: start,
+        buffer: 0,
+        count: 0,
+    };
+
+    // Which blocks make up each MCU: all components interleaved, or single blocks
+    let (mcus_x, mcus_y): (usize, usize) = if scan.len() == 1 {
+        let c: &JpegComponent = &components[scan[0]];
+        ((width * c.h).div_ceil(h_max).div_ceil(8), (height * c.v).div_ceil(v_max).div_ceil(8))
+    } else {
+        (width.div_ceil(8 * h_max), height.div_ceil(8 * v_max))
+    };
+    let mut coefficients: [i32; 64] = [0; 64];
+    for mcu in 0..mcus_x * mcus_y {
+        if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+            bits.restart()?;
+            for &index in scan {
+                components[index].predictor = 0;
+            }
+        }
+        let (mx, my): (usize, usize) = (mcu % mcus_x, mcu / mcus_x);
+        for &index in scan {
+            let c: &mut JpegComponent = &mut components[index];
+            let (blocks_x, blocks_y): (usize, usize) = if scan.len() == 1 { (1, 1) } else { (c.h, c.v) };
+            for by in 0..blocks_y {
+                for bx in 0..blocks_x {
+                    coefficients.iter_mut().for_each(|x| *x = 0);
+                    let category: u8 = bits.decode(&dc_tables[c.dc_table])?;
+                    c.predictor = c.predictor.wrapping_add(bits.receive_extend(category as u32)?);
+                    coefficients[0] = c.predictor;
+                    let mut k: usize = 1;
+                    while k < 64 {
+                        let rs: u8 = bits.decode(&ac_tables[c.ac_table])?;
+                        let (run, size): (usize, u32) = ((rs >> 4) as usize, (rs & 15) as u32);
+                        if size == 0 {
+                            if run != 15 {
+                                break;
+                            }
+                            k += 16;
+                            continue;
+                        }
+                        k += run;
+                        if k > 63 {
+                            return Err("JPEG coefficient index out of range".to_string());
+                        }
+                        coefficients[ZIGZAG[k]] = bits.receive_extend(size)?;
+                        k += 1;
+                    }
+                    let (x, y): (usize, usize) = if scan.len() == 1 {
+                        (mx * 8, my * 8)
+                    } else {
+                        ((mx * c.h + bx) * 8, (my * c.v + by) * 8)
+                    };
+                    let stride: usize = c.stride;
+                    idct_block(&coefficients, &quant[c.quant], &mut c.samples[y * stride + x..], stride);
+                }
+            }
+        }
+    }
+    // Continue after the entropy coded data, at the next marker that isn't a restart
+    let mut pos: usize = bits.pos;
+    while pos + 1 < data.len() && !(data[pos] == 0xff && data[pos + 1] != 0 && !(0xd0..=0xd7).contains(&data[pos + 1])) {
+        pos += 1;
+    }
+    Ok(pos)
+}
+
+#[test]
+fn test_inflate() {
+    // From Python's zlib: a fixed Huffman block with back references...
+    let fixed: [u8; 17] = [
+        0x78, 0xda, 0x4b, 0x4c, 0x4a, 0x4e, 0x1c, 0x6a, 0xa8, 0xa2, 0xb2, 0x0a, 0x00, 0x2f, 0x46, 0x46, 0x54,
+    ];
+    let mut expected: Vec<u8> = b"abcabcabc".repeat(20);
+    expected.extend_from_slice(b"xyz");
+    assert_eq!(zlib_decompress(&fixed, expected.len()).unwrap(), expected);
+
+    // ...and a dynamic one, compressed with Z_HUFFMAN_ONLY
+    let dynamic: [u8; 24] = [
+        0x78, 0x01, 0x05, 0xc1, 0x01, 0x0d, 0x00, 0x00, 0x00, 0xc2, 0xa0, 0xac, 0x7c, 0xf6, 0xcf, 0x20, 0x88, 0x91,
+        0x20, 0x1d, 0x3a, 0x57, 0x06, 0x7a,
+    ];
+    assert_eq!(zlib_decompress(&dynamic, 17).unwrap(), b"abaadaababaaaabab");
+
+    let stored: [u8; 8] = [0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c'];
+    assert_eq!(inflate(&stored, 3).unwrap(), b"abc");
+
+    // Output past the expected size is an error, whichever block produced it
+    assert_eq!(
+        zlib_decompress(&fixed, 100).unwrap_err(),
+        "deflate stream inflates past the expected 100 bytes"
+    );
+    assert_eq!(
+        zlib_decompress(&dynamic, 16).unwrap_err(),
+        "deflate stream inflates past the expected 16 bytes"
+    );
+    assert_eq!(inflate(&stored, 2).unwrap_err(), "deflate stream inflates past the expected 2 bytes");
+}
+
+#[cfg(test)]
+fn assert_close(a: Vector3, b: Vector3) {
+    assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+}
+
+#[cfg(test)]
+fn gray(value: f64) -> Vector3 {
+    Vector3::new(value, value, value)
+}
+
+#[test]
+fn test_png() {
+    use image_writer::{ImageWriter, PngWriter};
+
+    // The writer stores gamma 2 values, so these land exactly on the given codes
+    let codes: [f64; 3] = [0.0, 128.0, 255.0];
+    let mut image: Framebuffer = Framebuffer::new(3, 1);
+    for (x, code) in codes.iter().enumerate() {
+        image.set(x, 0, gray((code / 255.0) * (code / 255.0)));
+    }
+    for &sixteen_bit in &[false, true] {
+        let mut data: Vec<u8> = Vec::new();
+        PngWriter { sixteen_bit }.write(&image, &mut data).unwrap();
+        let decoded: Framebuffer = decode_image(&data, "test.png").unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (3, 1));
+        let max: f64 = if sixteen_bit { 65535.0 } else { 255.0 };
+        for (x, code) in codes.iter().enumerate() {
+            let stored: f64 = ((code / 255.0) * max).round() / max;
+            assert_close(decoded.get(x, 0), gray(srgb_to_linear(stored)));
+        }
+    }
+
+    // Hand built files with a single stored deflate block; CRCs and the
+    // Adler checksum aren't verified so they are left at zero
+    let png = |ihdr: &[u8], palette: &[u8], raw: &[u8]| -> Vec<u8> {
+        let mut data: Vec<u8> = PNG_SIGNATURE.to_vec();
+        let mut chunk = |kind: &[u8], body: &[u8]| {
+            data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            data.extend_from_slice(kind);
+            data.extend_from_slice(body);
+            data.extend_from_slice(&[0; 4]);
+        };
+        chunk(b"IHDR", ihdr);
+        if !palette.is_empty() {
+            chunk(b"PLTE", palette);
+        }
+        let mut zlib: Vec<u8> = vec![0x78, 0x01, 0x01, raw.len() as u8, 0, !raw.len() as u8, 0xff];
+        zlib.extend_from_slice(raw);
+        zlib.extend_from_slice(&[0; 4]);
+        chunk(b"IDAT", &zlib);
+        chunk(b"IEND", &[]);
+        data
+    };
+
+    // 3x2, 2 bit palette; the second row is Up filtered
+    let ihdr: [u8; 13] = [0, 0, 0, 3, 0, 0, 0, 2, 2, 3, 0, 0, 0];
+    let palette: [u8; 9] = [0, 0, 0, 255, 0, 0, 0, 0, 255];
+    let raw: [u8; 4] = [0, 0b0001_1000, 2, 0b0100_0000];
+    let decoded: Framebuffer = decode_image(&png(&ihdr, &palette, &raw), "palette.png").unwrap();
+    let red: Vector3 = Vector3::new(1.0, 0.0, 0.0);
+    let blue: Vector3 = Vector3::new(0.0, 0.0, 1.0);
+    assert_eq!(decoded.get(0, 0), gray(0.0));
+    assert_eq!(decoded.get(1, 0), red);
+    assert_eq!(decoded.get(2, 0), blue);
+    assert_eq!(decoded.get(0, 1), red);
+    assert_eq!(decoded.get(1, 1), red);
+    assert_eq!(decoded.get(2, 1), blue);
+
+    // 3x3 Adam7 interlaced grayscale, pixel value = its index, the last
+    // pass is Sub filtered
+    let ihdr: [u8; 13] = [0, 0, 0, 3, 0, 0, 0, 3, 8, 0, 0, 0, 1];
+    let raw: [u8; 15] = [0, 0, 0, 2, 0, 6, 8, 0, 1, 0, 7, 1, 3, 1, 1];
+    let decoded: Framebuffer = decode_image(&png(&ihdr, &[], &raw), "adam7.png").unwrap();
+    for y in 0..3 {
+        for x in 0..3 {
+            assert_close(decoded.get(x, y), gray(srgb_to_linear((y * 3 + x) as f64 / 255.0)));
+        }
+    }
+
+    // Image data past the last pass means the stream doesn't match the header
+    let mut long: Vec<u8> = raw.to_vec();
+    long.push(0);
+    assert_eq!(
+        decode_image(&png(&ihdr, &[], &long), "long.png").unwrap_err().to_string(),
+        "long.png: deflate stream inflates past the expected 15 bytes"
+    );
+}
+
+#[test]
+fn test_jpeg() {
+    // Writes MSB first with 0xff stuffing, pads bytes with ones
+    fn entropy(bits: &str) -> Vec<u8> {
+        let mut out: Vec<u8> = Vec::new();
+        let padded: String = format!("{}{}", bits, "1".repeat((8 - bits.len() % 8) % 8));
+        for i in (0..padded.len()).step_by(8) {
+            let byte: u8 = u8::from_str_radix(&padded[i..i + 8], 2).unwrap();
+            out.push(byte);
+            if byte == 0xff {
+                out.push(0);
+            }
+        }
+        out
+    }
+    fn segment(marker: u8, body: &[u8]) -> Vec<u8> {
+        let mut out: Vec<u8> = vec![0xff, marker, 0, (body.len() + 2) as u8];
+        out.extend_from_slice(body);
+        out
+    }
+    // Unit quantisation; DC codes 00, 01, 10 for categories 0, 5, 8 and a
+    // lone AC code 0 for end of block, so every block is flat
+    let mut tables: Vec<u8> = segment(0xdb, &[&[0u8][..], &[1; 64]].concat());
+    tables.extend(segment(0xc4, &[0x00, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 8]));
+    tables.extend(segment(0xc4, &[0x10, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]));
+
+    // 16x8 grayscale with a restart after each block, so both DC values
+    // are coded from zero: 160 and 136 come out as 128 + 20 and 128 + 17
+    let mut data: Vec<u8> = vec![0xff, 0xd8];
+    data.extend(&tables);
+    data.extend(segment(0xc0, &[8, 0, 8, 0, 16, 1, 1, 0x11, 0]));
+    data.extend(segment(0xdd, &[0, 1]));
+    data.extend(segment(0xda, &[1, 1, 0x00, 0, 63, 0]));
+    data.extend(entropy("10101000000"));
+    data.extend(&[0xff, 0xd0]);
+    data.extend(entropy("10100010000"));
+    data.extend(&[0xff, 0xd9]);
+    let decoded: Framebuffer = decode_image(&data, "gray.jpg").unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (16, 8));
+    assert_close(decoded.get(3, 4), gray(srgb_to_linear(148.0 / 255.0)));
+    assert_close(decoded.get(12, 4), gray(srgb_to_linear(145.0 / 255.0)));
+
+    // 16x16 YCbCr 4:2:0 in one MCU: four neutral luma blocks, Cb 128 and Cr 148
+    let mut data: Vec<u8> = vec![0xff, 0xd8];
+    data.extend(&tables);
+    data.extend(segment(0xc0, &[8, 0, 16, 0, 16, 3, 1, 0x22, 0, 2, 0x11, 0, 3, 0x11, 0]));
+    data.extend(segment(0xda, &[3, 1, 0x00, 2, 0x00, 3, 0x00, 0, 63, 0]));
+    data.extend(entropy("00000000000000010101000000"));
+    data.extend(&[0xff, 0xd9]);
+    let decoded: Framebuffer = decode_image(&data, "color.jpg").unwrap();
+    let expected: Vector3 = Vector3::new(
+        srgb_to_linear((128.0 + 1.402 * 20.0) / 255.0),
+        srgb_to_linear((128.0 - 0.714136 * 20.0) / 255.0),
+        srgb_to_linear(128.0 / 255.0),
+    );
+    assert_close(decoded.get(0, 0), expected);
+    assert_close(decoded.get(15, 15), expected);
+}
+
+#[test]
+fn test_hdr() {
+    use image_writer::{HdrWriter, ImageWriter};
+
+    let mut image: Framebuffer = Framebuffer::new(2, 2);
+    image.set(0, 0, Vector3::new(1.0, 0.5, 0.25));
+    image.set(1, 1, Vector3::new(100.0, 3.0, 0.0));
+    let mut data: Vec<u8> = Vec::new();
+    HdrWriter.write(&image, &mut data).unwrap();
+    let decoded: Framebuffer = decode_image(&data, "flat.hdr").unwrap();
+    for (a, b) in decoded.pixels().iter().zip(image.pixels()) {
+        assert!((*a - *b).length() <= 0.01 * b.length().max(0.01), "{:?} != {:?}", a, b);
+    }
+
+    // One adaptive run length encoded scanline: runs for R, B and E, literals for G
+    let mut data: Vec<u8> = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+    data.extend_from_slice(&[2, 2, 0, 8]);
+    data.extend_from_slice(&[136, 128]);
+    data.extend_from_slice(&[8, 0, 16, 32, 48, 64, 80, 96, 112]);
+    data.extend_from_slice(&[136, 0]);
+    data.extend_from_slice(&[136, 129]);
+    let decoded: Framebuffer = decode_image(&data, "rle.hdr").unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (8, 1));
+    for x in 0..8 {
+        let scale: f64 = 1.0 / 128.0;
+        assert_close(decoded.get(x, 0), Vector3::new(128.5 * scale, (16 * x) as f64 * scale + 0.5 * scale, 0.5 * scale));
+    }
+
+    match decode_image(b"GIF89a", "image.gif") {
+        Err(e) => assert_eq!(e.to_string(), "image.gif: unknown image format, expected PNG, JPEG or Radiance HDR"),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn test_malformed_images() {
+    let error = |data: &[u8]| -> String {
+        match decode_image(data, "bad") {
+            Err(e) => e.to_string(),
+            Ok(_) => panic!("expected an error"),
+        }
+    };
+    // One 8x8 grayscale component using quantization table 0
+    let sof: [u8; 13] = [0xff, 0xc0, 0x00, 0x0b, 8, 0, 8, 0, 8, 1, 1, 0x11, 0];
+    let jpeg = |segments: &[&[u8]]| -> Vec<u8> {
+        let mut data: Vec<u8> = vec![0xff, 0xd8];
+        for segment in segments {
+            data.extend_from_slice(segment);
+        }
+        data.extend_from_slice(&[0xff, 0xd9]);
+        data
+    };
+    assert_eq!(error(&jpeg(&[&[0xff, 0xdd, 0x00, 0x02]])), "bad: unexpected end of data");
+    assert_eq!(error(&jpeg(&[&sof, &[0xff, 0xda, 0x00, 0x02]])), "bad: unexpected end of data");
+
+    // DC and AC tables with a single one bit code each
+    let table = |class: u8, symbol: u8| -> Vec<u8> {
+        let mut segment: Vec<u8> = vec![0xff, 0xc4, 0x00, 0x14, class << 4, 1];
+        segment.extend_from_slice(&[0; 15]);
+        segment.push(symbol);
+        segment
+    };
+    let sos: [u8; 10] = [0xff, 0xda, 0x00, 0x08, 1, 1, 0x00, 0, 63, 0];
+    let data: Vec<u8> = jpeg(&[&sof, &table(0, 17), &table(1, 0), &sos, &[0x00]]);
+    assert_eq!(error(&data), "bad: invalid JPEG magnitude category 17");
+
+    // The largest DC value times the largest 16 bit quantizer doesn't fit an i32
+    let mut dqt: Vec<u8> = vec![0xff, 0xdb, 0x00, 0x83, 0x10];
+    dqt.extend_from_slice(&[0xff; 128]);
+    let data: Vec<u8> = jpeg(&[&dqt, &sof, &table(0, 16), &table(1, 0), &sos, &[0x7f, 0xff, 0x00, 0x80]]);
+    let decoded: Framebuffer = decode_image(&data, "loud.jpg").unwrap();
+    assert_eq!(decoded.get(0, 0), Vector3::new(1.0, 1.0, 1.0));
+
+    // Old style Radiance runs: a zero count, and counts adding up past the scanline
+    let hdr = |pixels: &[[u8; 4]]| -> Vec<u8> {
+        let mut data: Vec<u8> = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 4\n".to_vec();
+        for pixel in pixels {
+            data.extend_from_slice(pixel);
+        }
+        data
+    };
+    let zero_runs: Vec<[u8; 4]> = [[10, 10, 10, 128]].iter().chain([[1, 1, 1, 0]; 9].iter()).cloned().collect();
+    assert_eq!(error(&hdr(&zero_runs)), "bad: invalid Radiance run length");
+    assert_eq!(error(&hdr(&[[10, 10, 10, 128], [1, 1, 1, 2], [1, 1, 1, 1]])), "bad: Radiance run overflows the scanline");
+
+    // Headers claiming huge images fail before anything is allocated for them
+    let huge: &[u8] = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 4000000000 +X 4000000000\n";
+    assert_eq!(error(huge), "bad: 4000000000 x 4000000000 image is too large");
+    let sof: [u8; 13] = [0xff, 0xc0, 0x00, 0x0b, 8, 0xff, 0xff, 0xff, 0xff, 1, 1, 0x11, 0];
+    assert_eq!(error(&jpeg(&[&sof])), "bad: 65535 x 65535 image is too large");
+}
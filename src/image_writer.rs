@@ -0,0 +1,400 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use render::Framebuffer;
+use vector::Vector3;
+
+pub trait ImageWriter {
+    fn write(&self, image: &Framebuffer, out: &mut dyn Write) -> io::Result<()>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Ppm,
+    Png8,
+    Png16,
+    Hdr,
+    ExrHalf,
+    ExrFloat,
+}
+
+impl ImageFormat {
+    // Names accepted by the --format flag
+    pub fn from_name(name: &str) -> Option<ImageFormat> {
+        match name {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" | "png8" => Some(ImageFormat::Png8),
+            "png16" => Some(ImageFormat::Png16),
+            "hdr" => Some(ImageFormat::Hdr),
+            "exr" | "exr-half" => Some(ImageFormat::ExrHalf),
+            "exr-float" => Some(ImageFormat::ExrFloat),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        let extension: String = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png8),
+            "hdr" | "pic" => Some(ImageFormat::Hdr),
+            "exr" => Some(ImageFormat::ExrHalf),
+            _ => None,
+        }
+    }
+
+    pub fn writer(&self) -> Box<dyn ImageWriter> {
+        match *self {
+            ImageFormat::Ppm => Box::new(PpmWriter),
+            ImageFormat::Png8 => Box::new(PngWriter { sixteen_bit: false }),
+            ImageFormat::Png16 => Box::new(PngWriter { sixteen_bit: true }),
+            ImageFormat::Hdr => Box::new(HdrWriter),
+            ImageFormat::ExrHalf => Box::new(ExrWriter { half: true }),
+            ImageFormat::ExrFloat => Box::new(ExrWriter { half: false }),
+        }
+    }
+}
+
+pub fn save_image(image: &Framebuffer, path: &Path, format: ImageFormat) -> io::Result<()> {
+    let mut out: BufWriter<File> = BufWriter::new(File::create(path)?);
+    format.writer().write(image, &mut out)?;
+    out.flush()
+}
+
+// Display referred formats store gamma 2 encoded values, like the renderer always did
+fn encode(value: f64, max: f64) -> f64 {
+    (value.max(0.0).sqrt() * max).round().min(max)
+}
+
+fn to_u8(value: f64) -> u8 {
+    encode(value, 255.0) as u8
+}
+
+fn to_u16(value: f64) -> u16 {
+    encode(value, 65535.0) as u16
+}
+
+// Binary P6 portable pixmap, 8 bits per channel
+pub struct PpmWriter;
+
+impl ImageWriter for PpmWriter {
+    fn write(&self, image: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", image.width(), image.height())?;
+        let mut bytes: Vec<u8> = Vec::with_capacity(3 * image.pixels().len());
+        for col in image.pixels() {
+            bytes.extend_from_slice(&[to_u8(col.x), to_u8(col.y), to_u8(col.z)]);
+        }
+        out.write_all(&bytes)
+    }
+}
+
+fn crc32(chunks: &[&[u8]]) -> u32 {
+    let mut table: [u32; 256] = [0; 256];
+    for (n, entry) in table.iter_mut().enumerate() {
+        let mut c: u32 = n as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+        }
+        *entry = c;
+    }
+    let mut crc: u32 = 0xffff_ffff;
+    for chunk in chunks {
+        for &byte in chunk.iter() {
+            crc = table[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+        }
+    }
+    crc ^ 0xffff_ffff
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b): (u32, u32) = (1, 0);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+// zlib stream made of stored deflate blocks: larger files, but no compressor to maintain
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = if data.is_empty() { vec![data] } else { data.chunks(65535).collect() };
+    for (i, block) in blocks.iter().enumerate() {
+        out.push(if i + 1 == blocks.len() { 1 } else { 0 });
+        let len: u16 = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn png_chunk(out: &mut dyn Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    out.write_all(&crc32(&[kind, data]).to_be_bytes())
+}
+
+// RGB PNG with 8 or 16 bits per channel
+pub struct PngWriter {
+    pub sixteen_bit: bool,
+}
+
+impl ImageWriter for PngWriter {
+    fn write(&self, image: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+        let mut header: Vec<u8> = Vec::with_capacity(13);
+        header.extend_from_slice(&(image.width() as u32).to_be_bytes());
+        header.extend_from_slice(&(image.height() as u32).to_be_bytes());
+        // bit depth, colour type 2 (RGB), compression, filter, interlace
+        header.extend_from_slice(&[if self.sixteen_bit { 16 } else { 8 }, 2, 0, 0, 0]);
+
+        let mut raw: Vec<u8> = Vec::new();
+        for y in 0..image.height() {
+            // Filter type 0 (None) for every scanline
+            raw.push(0);
+            for x in 0..image.width() {
+                let col: Vector3 = image.get(x, y);
+                for &c in &[col.x, col.y, col.z] {
+                    if self.sixteen_bit {
+                        raw.extend_from_slice(&to_u16(c).to_be_bytes());
+                    } else {
+                        raw.push(to_u8(c));
+                    }
+                }
+            }
+        }
+
+        out.write_all(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'])?;
+        png_chunk(out, b"IHDR", &header)?;
+        png_chunk(out, b"IDAT", &zlib_stored(&raw))?;
+        png_chunk(out, b"IEND", &[])
+    }
+}
+
+// Shared exponent encoding used by Radiance files
+fn rgbe(col: &Vector3) -> [u8; 4] {
+    let v: f64 = col.x.max(col.y).max(col.z);
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    let exponent: i32 = v.log2().floor() as i32 + 1;
+    let scale: f64 = 256.0 / 2f64.powi(exponent);
+    [
+        (col.x.max(0.0) * scale) as u8,
+        (col.y.max(0.0) * scale) as u8,
+        (col.z.max(0.0) * scale) as u8,
+        (exponent + 128) as u8,
+    ]
+}
+
+// Radiance RGBE, written as flat (not run length encoded) scanlines
+pub struct HdrWriter;
+
+impl ImageWriter for HdrWriter {
+    fn write(&self, image: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+        write!(
+            out,
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            image.height(),
+            image.width()
+        )?;
+        let mut bytes: Vec<u8> = Vec::with_capacity(4 * image.pixels().len());
+        for col in image.pixels() {
+            bytes.extend_from_slice(&rgbe(col));
+        }
+        out.write_all(&bytes)
+    }
+}
+
+// IEEE 754 binary16 with round to nearest even, overflow goes to infinity
+pub fn f32_to_half(value: f32) -> u16 {
+    let bits: u32 = value.to_bits();
+    let sign: u16 = ((bits >> 16) & 0x8000) as u16;
+    let exponent: i32 = ((bits >> 23) & 0xff) as i32;
+    let mantissa: u32 = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        let nan: u16 = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let half_exponent: i32 = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if half_exponent <= 0 {
+        // Subnormal half, or zero when even the subnormals can't hold it
+        if half_exponent < -10 {
+            return sign;
+        }
+        let m: u32 = mantissa | 0x80_0000;
+        let shift: u32 = (14 - half_exponent) as u32;
+        let mut half_mantissa: u32 = m >> shift;
+        let rest: u32 = m & ((1 << shift) - 1);
+        let halfway: u32 = 1 << (shift - 1);
+        if rest > halfway || (rest == halfway && half_mantissa & 1 == 1) {
+            half_mantissa += 1;
+        }
+        return sign | half_mantissa as u16;
+    }
+    let mut half: u32 = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    let rest: u32 = mantissa & 0x1fff;
+    if rest > 0x1000 || (rest == 0x1000 && half & 1 == 1) {
+        // A carry into the exponent is exactly the right result
+        half += 1;
+    }
+    sign | half as u16
+}
+
+// Single part, scanline OpenEXR without compression, linear values
+pub struct ExrWriter {
+    pub half: bool,
+}
+
+fn exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+impl ImageWriter for ExrWriter {
+    fn write(&self, image: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+        let width: usize = image.width();
+        let height: usize = image.height();
+        let pixel_type: u32 = if self.half { 1 } else { 2 };
+        let sample_size: usize = if self.half { 2 } else { 4 };
+
+        let mut header: Vec<u8> = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+        // Channels have to be listed in alphabetical order
+        let mut channels: Vec<u8> = Vec::new();
+        for name in &["B", "G", "R"] {
+            channels.extend_from_slice(name.as_bytes());
+            channels.push(0);
+            channels.extend_from_slice(&pixel_type.to_le_bytes());
+            channels.extend_from_slice(&[0, 0, 0, 0]);
+            channels.extend_from_slice(&1i32.to_le_bytes());
+            channels.extend_from_slice(&1i32.to_le_bytes());
+        }
+        channels.push(0);
+        exr_attribute(&mut header, "channels", "chlist", &channels);
+        exr_attribute(&mut header, "compression", "compression", &[0]);
+        let mut window: Vec<u8> = Vec::new();
+        for v in &[0i32, 0, width as i32 - 1, height as i32 - 1] {
+            window.extend_from_slice(&v.to_le_bytes());
+        }
+        exr_attribute(&mut header, "dataWindow", "box2i", &window);
+        exr_attribute(&mut header, "displayWindow", "box2i", &window);
+        exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        exr_attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+        exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+        exr_attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+        header.push(0);
+
+        // One scanline per chunk: y, byte count, then each channel's samples
+        let line_size: usize = 3 * width * sample_size;
+        let chunk_size: usize = 8 + line_size;
+        let table_end: usize = header.len() + 8 * height;
+        for y in 0..height {
+            header.extend_from_slice(&((table_end + y * chunk_size) as u64).to_le_bytes());
+        }
+        out.write_all(&header)?;
+
+        let mut line: Vec<u8> = Vec::with_capacity(chunk_size);
+        for y in 0..height {
+            line.clear();
+            line.extend_from_slice(&(y as i32).to_le_bytes());
+            line.extend_from_slice(&(line_size as u32).to_le_bytes());
+            for channel in 0..3 {
+                for x in 0..width {
+                    let col: Vector3 = image.get(x, y);
+                    let value: f32 = match channel {
+                        0 => col.z,
+                        1 => col.y,
+                        _ => col.x,
+                    } as f32;
+                    if self.half {
+                        line.extend_from_slice(&f32_to_half(value).to_le_bytes());
+                    } else {
+                        line.extend_from_slice(&value.to_le_bytes());
+                    }
+                }
+            }
+            out.write_all(&line)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_checksums() {
+    assert_eq!(crc32(&[b"123456789"]), 0xcbf4_3926);
+    assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+}
+
+#[test]
+fn test_half() {
+    assert_eq!(f32_to_half(0.0), 0x0000);
+    assert_eq!(f32_to_half(-0.0), 0x8000);
+    assert_eq!(f32_to_half(1.0), 0x3c00);
+    assert_eq!(f32_to_half(0.5), 0x3800);
+    assert_eq!(f32_to_half(-2.0), 0xc000);
+    assert_eq!(f32_to_half(65504.0), 0x7bff);
+    assert_eq!(f32_to_half(1e6), 0x7c00);
+    assert_eq!(f32_to_half(5.960_464_5e-8), 0x0001);
+    assert_eq!(f32_to_half(1.0 + 1.0 / 4096.0), 0x3c00);
+}
+
+#[test]
+fn test_writers() {
+    let mut image: Framebuffer = Framebuffer::new(3, 2);
+    image.set(0, 0, Vector3::new(1.0, 0.25, 0.0));
+
+    let mut ppm: Vec<u8> = Vec::new();
+    PpmWriter.write(&image, &mut ppm).unwrap();
+    assert!(ppm.starts_with(b"P6\n3 2\n255\n"));
+    assert_eq!(&ppm[11..14], &[255, 128, 0]);
+    assert_eq!(ppm.len(), 11 + 3 * 6);
+
+    let mut png: Vec<u8> = Vec::new();
+    PngWriter { sixteen_bit: true }.write(&image, &mut png).unwrap();
+    assert_eq!(&png[12..16], b"IHDR");
+    assert_eq!(png[24], 16);
+    assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+
+    let mut hdr: Vec<u8> = Vec::new();
+    HdrWriter.write(&image, &mut hdr).unwrap();
+    let start: usize = hdr.len() - 4 * 6;
+    assert_eq!(&hdr[start..start + 4], &[128, 32, 0, 129]);
+
+    let mut exr: Vec<u8> = Vec::new();
+    ExrWriter { half: true }.write(&image, &mut exr).unwrap();
+    assert_eq!(&exr[0..4], &[0x76, 0x2f, 0x31, 0x01]);
+    let first_chunk: usize = u64::from_le_bytes([
+        exr[exr.len() - 2 * 26 - 16],
+        exr[exr.len() - 2 * 26 - 15],
+        exr[exr.len() - 2 * 26 - 14],
+        exr[exr.len() - 2 * 26 - 13],
+        exr[exr.len() - 2 * 26 - 12],
+        exr[exr.len() - 2 * 26 - 11],
+        exr[exr.len() - 2 * 26 - 10],
+        exr[exr.len() - 2 * 26 - 9],
+    ]) as usize;
+    assert_eq!(first_chunk, exr.len() - 2 * 26);
+}
+
+#[test]
+fn test_format_selection() {
+    assert_eq!(ImageFormat::from_path(Path::new("out.PNG")), Some(ImageFormat::Png8));
+    assert_eq!(ImageFormat::from_path(Path::new("out.exr")), Some(ImageFormat::ExrHalf));
+    assert_eq!(ImageFormat::from_path(Path::new("out")), None);
+    assert_eq!(ImageFormat::from_name("exr-float"), Some(ImageFormat::ExrFloat));
+}
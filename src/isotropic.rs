@@ -0,0 +1,60 @@
+use std::f64::consts::PI;
+
+use hitable::HitRecord;
+use lambertian::random_unit_vector;
+use material::{ScatterRecord, Scatterable};
+use ray::Ray;
+use texture::{self, SharedTexture};
+use vector::Vector3;
+
+// Phase function of a medium that scatters equally in every direction
+#[derive(Clone, Debug)]
+pub struct Isotropic {
+    pub albedo: SharedTexture,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Vector3) -> Isotropic {
+        Isotropic::textured(texture::constant(albedo))
+    }
+
+    pub fn textured(albedo: SharedTexture) -> Isotropic {
+        Isotropic { albedo }
+    }
+}
+
+impl Scatterable for Isotropic {
+    fn sample(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let scattered: Ray = Ray::new(rec.p(), random_unit_vector()).with_time(ray_in.time());
+        Some(ScatterRecord::new(scattered, self.albedo.value(rec.u, rec.v, &rec.p()), 1.0 / (4.0 * PI)))
+    }
+
+    fn eval(&self, _ray_in: &Ray, rec: &HitRecord, _direction: &Vector3) -> Vector3 {
+        self.albedo.value(rec.u, rec.v, &rec.p()) / (4.0 * PI)
+    }
+
+    fn pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: &Vector3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+#[test]
+fn test_isotropic() {
+    let isotropic: Isotropic = Isotropic::new(Vector3::new(0.5, 0.6, 0.7));
+    let ray: Ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0)).with_time(0.3);
+    // Scattering does not depend on the direction of the incoming ray
+    let mut mean: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+    let mut sample: Option<ScatterRecord> = None;
+    for _ in 0..10000 {
+        sample = isotropic.sample(&ray, &HitRecord::new());
+        mean = mean + sample.unwrap().ray.direction() / 10000.0;
+    }
+    assert!(mean.length() < 0.05);
+    let sample: ScatterRecord = sample.unwrap();
+    assert_eq!(sample.weight, Vector3::new(0.5, 0.6, 0.7));
+    assert_eq!(sample.ray.time(), 0.3);
+    assert!(!sample.delta);
+    let direction: Vector3 = sample.ray.direction();
+    assert_eq!(isotropic.pdf(&ray, &HitRecord::new(), &direction), 1.0 / (4.0 * PI));
+    assert_eq!(isotropic.eval(&ray, &HitRecord::new(), &direction) / sample.pdf, sample.weight);
+}
@@ -0,0 +1,287 @@
+use std::fmt;
+
+// Minimal JSON reader used for scene files. Every value remembers the line
+// it started on so the scene loader can point at the offending key.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    // Members keep their file order
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Json {
+    pub value: JsonValue,
+    pub line: usize,
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self.value {
+            JsonValue::Object(ref members) => members.iter().find(|m| m.0 == key).map(|m| &m.1),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self.value {
+            JsonValue::Null => "null",
+            JsonValue::Bool(_) => "a boolean",
+            JsonValue::Number(_) => "a number",
+            JsonValue::String(_) => "a string",
+            JsonValue::Array(_) => "an array",
+            JsonValue::Object(_) => "an object",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct JsonError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+// Deepest nesting of arrays and objects, so a hostile file can't overflow the stack
+const MAX_DEPTH: usize = 128;
+
+struct Reader<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    column: usize,
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, message: &str) -> JsonError {
+        JsonError {
+            line: self.line,
+            column: self.column,
+            message: message.to_string(),
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c: Option<char> = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+            self.column = 1;
+        } else if c.is_some() {
+            self.column += 1;
+        }
+        c
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), JsonError> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(&format!("expected '{}', found '{}'", expected, c))),
+            None => Err(self.error(&format!("expected '{}', found end of file", expected))),
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: JsonValue) -> Result<JsonValue, JsonError> {
+        for expected in word.chars() {
+            if self.next() != Some(expected) {
+                return Err(self.error("invalid literal"));
+            }
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.skip_whitespace();
+        let line: usize = self.line;
+        let value: JsonValue = match self.peek() {
+            Some('{') => self.nested(Reader::object)?,
+            Some('[') => self.nested(Reader::array)?,
+            Some('"') => JsonValue::String(self.string()?),
+            Some('t') => self.keyword("true", JsonValue::Bool(true))?,
+            Some('f') => self.keyword("false", JsonValue::Bool(false))?,
+            Some('n') => self.keyword("null", JsonValue::Null)?,
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number()?,
+            Some(c) => return Err(self.error(&format!("unexpected character '{}'", c))),
+            None => return Err(self.error("unexpected end of file")),
+        };
+        Ok(Json { value, line })
+    }
+
+    fn nested(&mut self, parse: fn(&mut Reader<'a>) -> Result<JsonValue, JsonError>) -> Result<JsonValue, JsonError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(&format!("values nested more than {} levels deep", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        let value: Result<JsonValue, JsonError> = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn number(&mut self) -> Result<JsonValue, JsonError> {
+        let mut text: String = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E' {
+                text.push(c);
+                self.next();
+            } else {
+                break;
+            }
+        }
+        text.parse::<f64>()
+            .map(JsonValue::Number)
+            .map_err(|_| self.error(&format!("invalid number '{}'", text)))
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect('"')?;
+        let mut s: String = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.next() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('/') => s.push('/'),
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('r') => s.push('\r'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('u') => {
+                        let mut code: u32 = 0;
+                        for _ in 0..4 {
+                            let digit: Option<u32> = self.next().and_then(|c| c.to_digit(16));
+                            match digit {
+                                Some(d) => code = code * 16 + d,
+                                None => return Err(self.error("invalid unicode escape")),
+                            }
+                        }
+                        s.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    _ => return Err(self.error("invalid escape sequence")),
+                },
+                Some(c) => s.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<JsonValue, JsonError> {
+        self.expect('[')?;
+        let mut items: Vec<Json> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.next();
+            return Ok(JsonValue::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(JsonValue::Array(items)),
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<JsonValue, JsonError> {
+        self.expect('{')?;
+        let mut members: Vec<(String, Json)> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(JsonValue::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key: String = self.string()?;
+            if members.iter().any(|m| m.0 == key) {
+                return Err(self.error(&format!("duplicate key '{}'", key)));
+            }
+            self.expect(':')?;
+            let value: Json = self.value()?;
+            members.push((key, value));
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(JsonValue::Object(members)),
+                _ => return Err(self.error("expected ',' or '}' in object")),
+            }
+        }
+    }
+}
+
+pub fn parse_json(source: &str) -> Result<Json, JsonError> {
+    let mut reader: Reader = Reader {
+        chars: source.chars().peekable(),
+        line: 1,
+        column: 1,
+        depth: 0,
+    };
+    let value: Json = reader.value()?;
+    reader.skip_whitespace();
+    if reader.peek().is_some() {
+        return Err(reader.error("trailing characters after value"));
+    }
+    Ok(value)
+}
+
+#[test]
+fn test_parse_json() {
+    let json: Json = parse_json("{\n  \"a\": [1, -2.5e1, true],\n  \"b\": {\"c\": \"x\\\"y\"}, \"n\": null\n}").unwrap();
+    match json.get("a").unwrap().value {
+        JsonValue::Array(ref items) => {
+            assert_eq!(items[1].value, JsonValue::Number(-25.0));
+            assert_eq!(items[2].value, JsonValue::Bool(true));
+        }
+        _ => panic!("expected an array"),
+    }
+    assert_eq!(json.get("a").unwrap().line, 2);
+    let c: &Json = json.get("b").unwrap().get("c").unwrap();
+    assert_eq!(c.value, JsonValue::String("x\"y".to_string()));
+    assert_eq!(json.get("n").unwrap().value, JsonValue::Null);
+}
+
+#[test]
+fn test_parse_json_errors() {
+    assert_eq!(
+        parse_json("{\n  \"a\": 1,\n  \"a\": 2\n}").err().unwrap().to_string(),
+        "line 3, column 6: duplicate key 'a'"
+    );
+    assert!(parse_json("[1, 2").is_err());
+    assert!(parse_json("{} x").is_err());
+
+    let deepest: String = "[".repeat(128) + &"]".repeat(128);
+    assert!(parse_json(&deepest).is_ok());
+    let deeper: String = "[".repeat(129) + &"]".repeat(129);
+    assert_eq!(
+        parse_json(&deeper).err().unwrap().to_string(),
+        "line 1, column 129: values nested more than 128 levels deep"
+    );
+    let mixed: String = "{\"a\": [".repeat(100) + &"]}".repeat(100);
+    assert!(parse_json(&mixed).is_err());
+}
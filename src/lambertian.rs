@@ -1,43 +1,87 @@
 extern crate rand;
 
-use material::Scatterable;
+use material::{ScatterRecord, Scatterable};
 use hitable::HitRecord;
 use vector::Vector3;
 use ray::Ray;
 use rand::Rng;
+use random;
+use texture::{self, SharedTexture};
+use std::f64::consts::PI;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct Lambertian {
-    pub albedo: Vector3,
+    pub albedo: SharedTexture,
 }
 
 impl Lambertian {
     pub fn new(albedo: Vector3) -> Lambertian {
-        Lambertian { albedo: albedo }
+        Lambertian::textured(texture::constant(albedo))
+    }
+
+    pub fn textured(albedo: SharedTexture) -> Lambertian {
+        Lambertian { albedo }
     }
 }
 
 impl Scatterable for Lambertian {
-    fn scatter(&self, _ray_in: &Ray, rec: &HitRecord, attenuation: &mut Vector3, scattered: &mut Ray) -> bool {
-        let target: Vector3 = rec.p() + rec.normal() + point_in_unit_sphere();
-        *scattered = Ray::new(rec.p(), target - rec.p());
-        *attenuation = self.albedo;
-        true
+    fn sample(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        // A point on (not in) the unit sphere gives exactly cosine weighted directions
+        let mut direction: Vector3 = rec.normal() + random_unit_vector();
+        if direction.norm() < 1e-16 {
+            direction = rec.normal();
+        }
+        let scattered: Ray = Ray::new(rec.p(), direction).with_time(ray_in.time());
+        let albedo: Vector3 = self.albedo.filtered(rec.u, rec.v, &rec.p(), rec.footprint);
+        Some(ScatterRecord::new(scattered, albedo, self.pdf(ray_in, rec, &direction)))
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vector3) -> Vector3 {
+        self.albedo.filtered(rec.u, rec.v, &rec.p(), rec.footprint) * self.pdf(ray_in, rec, direction)
+    }
+
+    fn pdf(&self, _ray_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f64 {
+        let cosine: f64 = rec.normal().dot(&direction.unit_vector());
+        if cosine < 0.0 {
+            0.0
+        } else {
+            cosine / PI
+        }
     }
 }
 
-pub fn point_in_unit_sphere() -> Vector3 {
-    // Initialising p with a value outside the unit sphere
-    let mut p: Vector3 = Vector3::new(2.0, 2.0, 2.0);
-    let mut rng = rand::thread_rng();
-    while p.dot(&p) >= 1.0 {
-        p = 2.0 * Vector3::new(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>()) - Vector3::new(1.0, 1.0, 1.0);
+pub fn random_unit_vector() -> Vector3 {
+    let mut rng = random::thread_rng();
+    let z: f64 = 2.0 * rng.gen::<f64>() - 1.0;
+    let phi: f64 = 2.0 * PI * rng.gen::<f64>();
+    let r: f64 = (1.0 - z * z).sqrt();
+    Vector3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+#[test]
+fn test_unit_vector() {
+    for _ in 0..100 {
+        assert!((random_unit_vector().length() - 1.0).abs() < 1e-12);
     }
-    p
 }
 
 #[test]
-fn test_point() {
-    let vec: Vector3 = point_in_unit_sphere();
-    assert_eq!(vec.dot(&Vector3::new(0.0, 0.0, 0.0)), 0.0);
-}
\ No newline at end of file
+fn test_lambertian_sampling() {
+    let albedo: Vector3 = Vector3::new(0.8, 0.5, 0.2);
+    let lambertian: Lambertian = Lambertian::new(albedo);
+    let mut rec: HitRecord = HitRecord::new();
+    rec.normal = Vector3::new(0.0, 0.0, 1.0);
+    let ray_in: Ray = Ray::new(Vector3::new(0.0, 1.0, 1.0), Vector3::new(0.0, -1.0, -1.0));
+    random::seed(9);
+    for _ in 0..1000 {
+        let sample: ScatterRecord = lambertian.sample(&ray_in, &rec).unwrap();
+        let direction: Vector3 = sample.ray.direction();
+        assert!(!sample.delta);
+        assert!((lambertian.pdf(&ray_in, &rec, &direction) - sample.pdf).abs() < 1e-12);
+        if sample.pdf > 0.0 {
+            let ratio: Vector3 = lambertian.eval(&ray_in, &rec, &direction) / sample.pdf;
+            assert!((ratio - sample.weight).length() < 1e-9, "{:?} vs {:?}", ratio, sample.weight);
+        }
+    }
+    assert_eq!(lambertian.pdf(&ray_in, &rec, &Vector3::new(0.0, 0.0, -1.0)), 0.0);
+}
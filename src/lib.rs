@@ -0,0 +1,46 @@
+extern crate rand;
+
+pub mod aabb;
+pub mod background;
+pub mod bvh;
+pub mod camera;
+pub mod conductor;
+pub mod constant_medium;
+pub mod cuboid;
+pub mod dielectric;
+pub mod diffuse_light;
+pub mod distribution;
+pub mod environment;
+pub mod grid_medium;
+pub mod henyey_greenstein;
+pub mod hitable;
+pub mod hitable_list;
+pub mod ies;
+pub mod image_reader;
+pub mod image_writer;
+pub mod isotropic;
+pub mod json;
+pub mod lambertian;
+pub mod light;
+pub mod material;
+pub mod matrix;
+pub mod mesh;
+pub mod metal;
+pub mod microfacet;
+pub mod moving_sphere;
+pub mod noise;
+pub mod obj;
+pub mod onb;
+pub mod principled;
+pub mod quad;
+pub mod random;
+pub mod ray;
+pub mod render;
+pub mod scene;
+pub mod sky;
+pub mod sphere;
+pub mod texture;
+pub mod transform;
+pub mod triangle;
+pub mod vector;
+pub mod voxel_grid;
@@ -0,0 +1,331 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::sync::Arc;
+
+use ies::{IesProfile, Photometry};
+use onb::Onb;
+use random;
+use vector::Vector3;
+
+// Where a punctual light is seen from a point and how much it delivers there
+#[derive(Clone, Copy, Debug)]
+pub struct LightSample {
+    // Unit vector from the point towards the light
+    pub direction: Vector3,
+    // Up to the light, f64::MAX for lights at infinity
+    pub distance: f64,
+    // Irradiance on a surface facing the light, before any shadowing
+    pub irradiance: Vector3,
+}
+
+// Lights without a surface, rays can never hit them so they are only reached
+// through shadow rays
+pub trait Light: fmt::Debug {
+    fn sample(&self, p: &Vector3) -> Option<LightSample>;
+}
+
+pub type SharedLight = Arc<dyn Light + Send + Sync>;
+
+#[derive(Clone, Debug, Default)]
+pub struct LightList {
+    list: Vec<SharedLight>,
+}
+
+impl LightList {
+    pub fn new() -> LightList {
+        LightList { list: Vec::new() }
+    }
+
+    pub fn add<L: Light + Send + Sync + 'static>(&mut self, light: L) {
+        self.list.push(Arc::new(light));
+    }
+
+    pub fn add_shared(&mut self, light: SharedLight) {
+        self.list.push(light);
+    }
+
+    pub fn size(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, SharedLight> {
+        self.list.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a LightList {
+    type Item = &'a SharedLight;
+    type IntoIter = std::slice::Iter<'a, SharedLight>;
+
+    fn into_iter(self) -> std::slice::Iter<'a, SharedLight> {
+        self.list.iter()
+    }
+}
+
+// Light seen from p as coming from a point within radius of position, on the
+// disk facing p. A radius softens the shadows without changing the power.
+fn jittered(position: &Vector3, radius: f64, p: &Vector3) -> Vector3 {
+    let to_p: Vector3 = *p - *position;
+    // At the light itself no disk faces p, sample() then finds no direction
+    if radius <= 0.0 || to_p.norm() == 0.0 {
+        return *position;
+    }
+    let r: f64 = radius * random::random_f64().sqrt();
+    let phi: f64 = 2.0 * PI * random::random_f64();
+    *position + Onb::build_from_w(&to_p).local(r * phi.cos(), r * phi.sin(), 0.0)
+}
+
+// Shines equally in every direction unless given a profile, intensity is the
+// radiant intensity
+#[derive(Clone, Debug)]
+pub struct PointLight {
+    position: Vector3,
+    intensity: Vector3,
+    radius: f64,
+    photometry: Option<Photometry>,
+}
+
+impl PointLight {
+    pub fn new(position: Vector3, intensity: Vector3) -> PointLight {
+        PointLight {
+            position,
+            intensity,
+            radius: 0.0,
+            photometry: None,
+        }
+    }
+
+    pub fn with_radius(mut self, radius: f64) -> PointLight {
+        self.radius = radius.max(0.0);
+        self
+    }
+
+    // Shapes the light with a measured distribution whose 0 degree vertical
+    // angle points along aim, intensity is reached in its brightest direction
+    pub fn with_profile(mut self, profile: Arc<IesProfile>, aim: Vector3) -> PointLight {
+        self.photometry = Some(Photometry::new(profile, aim));
+        self
+    }
+}
+
+// Share of the intensity a profile sends along a direction leaving the light
+fn profile_scale(photometry: &Option<Photometry>, direction: &Vector3) -> f64 {
+    match *photometry {
+        Some(ref photometry) => photometry.relative(direction),
+        None => 1.0,
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: &Vector3) -> Option<LightSample> {
+        let to_light: Vector3 = jittered(&self.position, self.radius, p) - *p;
+        let distance: f64 = to_light.length();
+        if distance <= 0.0 {
+            return None;
+        }
+        let direction: Vector3 = to_light / distance;
+        let scale: f64 = profile_scale(&self.photometry, &-direction);
+        if scale <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance,
+            irradiance: self.intensity * (scale / (distance * distance)),
+        })
+    }
+}
+
+// Unit vector along direction, straight down for a zero vector that has none
+pub fn aim_or_down(direction: &Vector3) -> Vector3 {
+    if direction.norm() == 0.0 {
+        Vector3::new(0.0, -1.0, 0.0)
+    } else {
+        direction.unit_vector()
+    }
+}
+
+// A point light restricted to a cone around direction, fading out over the
+// last falloff degrees towards its edge
+#[derive(Clone, Debug)]
+pub struct SpotLight {
+    position: Vector3,
+    direction: Vector3,
+    intensity: Vector3,
+    cos_outer: f64,
+    cos_inner: f64,
+    radius: f64,
+    photometry: Option<Photometry>,
+}
+
+impl SpotLight {
+    // angle is the half angle of the cone in degrees, a zero direction points down
+    pub fn new(position: Vector3, direction: Vector3, intensity: Vector3, angle: f64, falloff: f64) -> SpotLight {
+        let outer: f64 = angle.clamp(0.0, 180.0);
+        let inner: f64 = (outer - falloff.max(0.0)).max(0.0);
+        SpotLight {
+            position,
+            direction: aim_or_down(&direction),
+            intensity,
+            cos_outer: outer.to_radians().cos(),
+            cos_inner: inner.to_radians().cos(),
+            radius: 0.0,
+            photometry: None,
+        }
+    }
+
+    pub fn with_radius(mut self, radius: f64) -> SpotLight {
+        self.radius = radius.max(0.0);
+        self
+    }
+
+    // A measured distribution aimed along the spot's direction, on top of the cone
+    pub fn with_profile(mut self, profile: Arc<IesProfile>) -> SpotLight {
+        self.photometry = Some(Photometry::new(profile, self.direction));
+        self
+    }
+
+    // Fraction of the intensity sent along a unit direction
+    pub fn falloff(&self, direction: &Vector3) -> f64 {
+        let cos_theta: f64 = direction.dot(&self.direction);
+        if cos_theta >= self.cos_inner {
+            1.0
+        } else if cos_theta <= self.cos_outer {
+            0.0
+        } else {
+            let t: f64 = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: &Vector3) -> Option<LightSample> {
+        let to_light: Vector3 = jittered(&self.position, self.radius, p) - *p;
+        let distance: f64 = to_light.length();
+        if distance <= 0.0 {
+            return None;
+        }
+        let direction: Vector3 = to_light / distance;
+        let falloff: f64 = self.falloff(&-direction) * profile_scale(&self.photometry, &-direction);
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance,
+            irradiance: self.intensity * (falloff / (distance * distance)),
+        })
+    }
+}
+
+// Parallel light from infinitely far away travelling along direction, like
+// the sun, with the given irradiance. A zero direction shines straight down.
+#[derive(Clone, Copy, Debug)]
+pub struct DirectionalLight {
+    direction: Vector3,
+    irradiance: Vector3,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vector3, irradiance: Vector3) -> DirectionalLight {
+        DirectionalLight {
+            direction: aim_or_down(&direction),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: &Vector3) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -self.direction,
+            distance: f64::MAX,
+            irradiance: self.irradiance,
+        })
+    }
+}
+
+#[test]
+fn test_point_and_directional_lights() {
+    let origin: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+    let point: PointLight = PointLight::new(Vector3::new(0.0, 4.0, 0.0), Vector3::new(32.0, 16.0, 0.0));
+    let sample: LightSample = point.sample(&origin).unwrap();
+    assert_eq!(sample.direction, Vector3::new(0.0, 1.0, 0.0));
+    assert_eq!(sample.distance, 4.0);
+    assert_eq!(sample.irradiance, Vector3::new(2.0, 1.0, 0.0));
+
+    // A radius spreads the samples over a disk facing the point
+    random::seed(4);
+    let soft: PointLight = point.with_radius(0.5);
+    for _ in 0..100 {
+        let sample: LightSample = soft.sample(&origin).unwrap();
+        let hit: Vector3 = sample.direction * sample.distance;
+        assert!((hit.y() - 4.0).abs() < 1e-9);
+        assert!((hit - Vector3::new(0.0, 4.0, 0.0)).length() <= 0.5 + 1e-9);
+    }
+
+    let sun: DirectionalLight = DirectionalLight::new(Vector3::new(0.0, -2.0, 0.0), Vector3::new(3.0, 3.0, 3.0));
+    let sample: LightSample = sun.sample(&Vector3::new(5.0, 1.0, -7.0)).unwrap();
+    assert_eq!(sample.direction, Vector3::new(0.0, 1.0, 0.0));
+    assert_eq!(sample.distance, f64::MAX);
+    assert_eq!(sample.irradiance, Vector3::new(3.0, 3.0, 3.0));
+}
+
+#[test]
+fn test_spot_light() {
+    let spot: SpotLight =
+        SpotLight::new(Vector3::new(0.0, 2.0, 0.0), Vector3::new(0.0, -1.0, 0.0), Vector3::new(8.0, 8.0, 8.0), 30.0, 10.0);
+    // Full strength inside the inner cone, none outside the outer one and a
+    // smooth fade in between
+    assert_eq!(spot.sample(&Vector3::new(0.0, 0.0, 0.0)).unwrap().irradiance, Vector3::new(2.0, 2.0, 2.0));
+    assert_eq!(spot.falloff(&Vector3::new(19.0f64.to_radians().sin(), -19.0f64.to_radians().cos(), 0.0)), 1.0);
+    let edge: f64 = spot.falloff(&Vector3::new(25.0f64.to_radians().sin(), -25.0f64.to_radians().cos(), 0.0));
+    assert!(edge > 0.0 && edge < 1.0);
+    assert!(spot.sample(&Vector3::new(2.0, 0.0, 0.0)).is_none());
+    assert!(spot.sample(&Vector3::new(0.0, 3.0, 0.0)).is_none());
+}
+
+#[test]
+fn test_profiled_lights() {
+    use std::path::Path;
+
+    let profile: Arc<IesProfile> = Arc::new(IesProfile::load(Path::new("scenes/ies/downlight.ies")).unwrap());
+    let intensity: Vector3 = Vector3::new(10.0, 10.0, 10.0);
+    let origin: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+    // Aimed down the profile peaks below the light and goes dark sideways
+    let point: PointLight = PointLight::new(Vector3::new(0.0, 2.0, 0.0), intensity)
+        .with_profile(profile.clone(), Vector3::new(0.0, -1.0, 0.0));
+    assert_eq!(point.sample(&origin).unwrap().irradiance, Vector3::new(2.5, 2.5, 2.5));
+    assert!(point.sample(&Vector3::new(5.0, 2.0, 0.0)).is_none());
+    // 30 degrees off the aim the profile gives 60%
+    let side: Vector3 = Vector3::new(2.0 * 30.0f64.to_radians().tan(), 0.0, 0.0);
+    let sample: LightSample = point.sample(&side).unwrap();
+    let expected: f64 = 10.0 * 0.6 / (side.x() * side.x() + 4.0);
+    assert!((sample.irradiance.x() - expected).abs() < 1e-9);
+
+    // On a spot the profile follows the spot's aim and the cone still cuts it off
+    let aim: Vector3 = Vector3::new(1.0, -1.0, 0.0);
+    let spot: SpotLight = SpotLight::new(Vector3::new(0.0, 2.0, 0.0), aim, intensity, 20.0, 0.0).with_profile(profile);
+    assert!((spot.sample(&Vector3::new(2.0, 0.0, 0.0)).unwrap().irradiance.x() - 10.0 / 8.0).abs() < 1e-9);
+    assert!(spot.sample(&origin).is_none());
+}
+
+#[test]
+fn test_degenerate_lights() {
+    // A zero direction falls back to shining down instead of spreading NaN
+    let intensity: Vector3 = Vector3::new(4.0, 4.0, 4.0);
+    let zero: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+    let spot: SpotLight = SpotLight::new(Vector3::new(0.0, 1.0, 0.0), zero, intensity, 30.0, 0.0);
+    assert_eq!(spot.sample(&zero).unwrap().irradiance, intensity);
+    let sun: DirectionalLight = DirectionalLight::new(zero, intensity);
+    assert_eq!(sun.sample(&zero).unwrap().direction, Vector3::new(0.0, 1.0, 0.0));
+
+    // A shading point on a soft light's center has no direction to it
+    let position: Vector3 = Vector3::new(1.0, 2.0, 3.0);
+    assert!(PointLight::new(position, intensity).with_radius(0.5).sample(&position).is_none());
+}
@@ -1,180 +1,93 @@
-extern crate rand;
+extern crate ray_learn;
 
-mod camera;
-mod dielectric;
-mod hitable;
-mod hitable_list;
-mod lambertian;
-mod material;
-mod metal;
-mod ray;
-mod sphere;
-mod vector;
+use std::env;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
 
-use camera::Camera;
-use dielectric::Dielectric;
-use hitable::{HitRecord, Hittable};
-use hitable_list::HittableList;
-use lambertian::Lambertian;
-use material::Material;
-use material::Scatterable;
-use metal::Metal;
-use rand::Rng;
-use ray::Ray;
-use sphere::Sphere;
-use vector::Vector3;
+use ray_learn::bvh::BvhNode;
+use ray_learn::image_writer::{save_image, ImageFormat};
+use ray_learn::render::{render, Framebuffer};
+use ray_learn::scene::{load_scene, parse_scene, Scene, SceneError};
 
-fn main() {
-    let nx = 200;
-    let ny = 100;
-    let ns = 100;
-    print!("P3\n{} {}\n255\n", nx, ny);
-    let mut world = random_scene();
-    world.add_sphere(Sphere::new(
-        Vector3::new(0.0, 0.0, -1.0),
-        0.5,
-        Material::Lambertian(Lambertian::new(Vector3::new(0.1, 0.2, 0.5))),
-    ));
-    world.add_sphere(Sphere::new(
-        Vector3::new(0.0, -100.5, -1.0),
-        100.0,
-        Material::Lambertian(Lambertian::new(Vector3::new(0.8, 0.8, 0.0))),
-    ));
-    world.add_sphere(Sphere::new(
-        Vector3::new(1.0, 0.0, -1.0),
-        0.5,
-        Material::Metal(Metal::new(Vector3::new(0.8, 0.6, 0.2), 0.3)),
-    ));
-    world.add_sphere(Sphere::new(
-        Vector3::new(-1.0, 0.0, -1.0),
-        0.5,
-        Material::Dielectric(Dielectric::new(1.5)),
-    ));
-    world.add_sphere(Sphere::new(
-        Vector3::new(-1.0, 0.0, -1.0),
-        -0.45,
-        Material::Dielectric(Dielectric::new(1.5)),
-    ));
+// Rendered when no scene file is given on the command line
+const DEFAULT_SCENE: &str = include_str!("../scenes/random.json");
 
-    let cam: Camera = Camera::new(
-        Vector3::new(13.0, 2.0, 3.0),
-        Vector3::new(0.0, 0.0, 0.0),
-        Vector3::new(0.0, 1.0, 0.0),
-        20.0,
-        (nx as f64) / (ny as f64),
-        0.1,
-        10.0,
-    );
-    for j in (0..=ny - 1).rev() {
-        for i in 0..nx {
-            let mut col = Vector3::new(0.0, 0.0, 0.0);
-            for _s in 0..ns {
-                let u: f64 = (i as f64 + rand::random::<f64>()) / nx as f64;
-                let v: f64 = (j as f64 + rand::random::<f64>()) / ny as f64;
-                let r = cam.get_ray(u, v);
-                let _p = r.point_at_parameter(2.0);
-                col = col + color(&r, &world, 0);
-            }
-            col = col / ns as f64;
-            let ir = (255.99 * col.x.sqrt()) as i32;
-            let ig = (255.99 * col.y.sqrt()) as i32;
-            let ib = (255.99 * col.z.sqrt()) as i32;
-            print!("{} {} {}\n", ir, ig, ib);
-        }
-    }
+const USAGE: &str = "usage: ray_learn [scene.json] [-o output] [--format ppm|png|png16|hdr|exr|exr-float]
+
+The format follows the output extension (.ppm, .png, .hdr, .exr) unless
+--format is given. png16 and exr-float have no extension of their own and
+are only chosen with --format. Without -o the image goes to stdout as a
+binary (P6) PPM.";
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1);
 }
 
-fn color(ray: &Ray, world: &Hittable, depth: i32) -> Vector3 {
-    let mut rec: HitRecord = HitRecord::new();
-    if world.hit(&ray, 0.001, std::f64::MAX, &mut rec) {
-        let mut scattered: Ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
-        let mut attenuation: Vector3 = Vector3::new(0.0, 0.0, 0.0);
-        if depth < 50 && rec
-            .material()
-            .scatter(ray, &mut rec, &mut attenuation, &mut scattered)
-        {
-            return attenuation * color(&scattered, world, depth + 1);
-        } else {
-            return Vector3::new(1.0, 1.0, 1.0);
+fn main() {
+    let mut scene_path: Option<String> = None;
+    let mut output: Option<String> = None;
+    let mut format: Option<ImageFormat> = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => match args.next() {
+                Some(path) => output = Some(path),
+                None => fail(USAGE),
+            },
+            "--format" => match args.next().as_ref().and_then(|name| ImageFormat::from_name(name)) {
+                Some(f) => format = Some(f),
+                None => fail(USAGE),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if scene_path.is_none() && !arg.starts_with('-') => scene_path = Some(arg),
+            _ => fail(USAGE),
         }
-    } else {
-        let unit_direction = ray.direction().unit_vector();
-        let t = 0.5 * (unit_direction.y + 1.0);
-        return (1.0 - t) * Vector3::new(1.0, 1.0, 1.0) + t * Vector3::new(0.5, 0.7, 1.0);
     }
-}
 
-fn random_scene() -> HittableList {
-    let mut rng = rand::thread_rng();
+    let loaded: Result<Scene, SceneError> = match scene_path {
+        Some(ref path) => load_scene(Path::new(path)),
+        None => parse_scene(DEFAULT_SCENE, "scenes/random.json", Path::new("scenes")),
+    };
+    let scene: Scene = match loaded {
+        Ok(scene) => scene,
+        Err(e) => fail(&e.to_string()),
+    };
 
-    let mut list: HittableList = HittableList::new();
-    list.add_sphere(Sphere::new(
-        Vector3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5))),
-    ));
+    let world = BvhNode::new(scene.world);
+    let image: Framebuffer = render(
+        &world,
+        &scene.lights,
+        &scene.punctual_lights,
+        &scene.camera,
+        &*scene.background,
+        &scene.settings,
+    );
 
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat: f64 = rng.gen::<f64>();
-            let center: Vector3 = Vector3::new(
-                a as f64 + 0.9 * rng.gen::<f64>(),
-                0.2,
-                b as f64 + 0.9 * rng.gen::<f64>(),
-            );
-            if (center - Vector3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                if choose_mat < 0.8 {
-                    // diffuse
-                    list.add_sphere(Sphere::new(
-                        center,
-                        0.2,
-                        Material::Lambertian(Lambertian::new(Vector3::new(
-                            rng.gen::<f64>() * rng.gen::<f64>(),
-                            rng.gen::<f64>() * rng.gen::<f64>(),
-                            rng.gen::<f64>() * rng.gen::<f64>(),
-                        ))),
-                    ));
-                } else if choose_mat < 0.95 {
-                    //metal
-                    list.add_sphere(Sphere::new(
-                        center,
-                        0.2,
-                        Material::Metal(Metal::new(
-                            Vector3::new(
-                                0.5 * (1.0 + rng.gen::<f64>()),
-                                0.5 * (1.0 + rng.gen::<f64>()),
-                                0.5 * (1.0 + rng.gen::<f64>()),
-                            ),
-                            0.5 * rng.gen::<f64>(),
-                        )),
-                    ));
-                } else {
-                    // dielectric
-                    list.add_sphere(Sphere::new(
-                        center,
-                        0.2,
-                        Material::Dielectric(Dielectric::new(1.5)),
-                    ));
-                }
+    // The flag wins over the extension; without an output the image goes to stdout
+    let result: io::Result<()> = match output {
+        Some(ref path) => {
+            let path: &Path = Path::new(path);
+            match format.or_else(|| ImageFormat::from_path(path)) {
+                Some(f) => save_image(&image, path, f),
+                None => fail(&format!("cannot tell the image format of {}, use --format", path.display())),
             }
-
-            list.add_sphere(Sphere::new(
-                Vector3::new(0.0, 1.0, 0.0),
-                1.0,
-                Material::Dielectric(Dielectric::new(1.5)),
-            ));
-            list.add_sphere(Sphere::new(
-                Vector3::new(-4.0, 1.0, 0.0),
-                1.0,
-                Material::Lambertian(Lambertian::new(Vector3::new(0.4, 0.4, 0.1))),
-            ));
-            list.add_sphere(Sphere::new(
-                Vector3::new(4.0, 1.0, 0.0),
-                1.0,
-                Material::Metal(Metal::new(Vector3::new(0.7, 0.6, 0.5), 0.0)),
-            ));
         }
+        None => {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            format
+                .unwrap_or(ImageFormat::Ppm)
+                .writer()
+                .write(&image, &mut out)
+                .and_then(|_| out.flush())
+        }
+    };
+    if let Err(e) = result {
+        fail(&e.to_string());
     }
-
-    list
 }
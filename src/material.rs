@@ -4,26 +4,129 @@ use vector::Vector3;
 use lambertian::Lambertian;
 use metal::Metal;
 use dielectric::Dielectric;
+use diffuse_light::DiffuseLight;
+use isotropic::Isotropic;
+use henyey_greenstein::HenyeyGreenstein;
+use conductor::Conductor;
+use principled::Principled;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum Material {
     Lambertian(Lambertian),
     Metal(Metal),
 	Dielectric(Dielectric),
+	DiffuseLight(DiffuseLight),
+	Isotropic(Isotropic),
+	HenyeyGreenstein(HenyeyGreenstein),
+	Conductor(Conductor),
+	Principled(Principled),
 }
 
 
 impl Scatterable for Material {
-	fn scatter(&self, ray_in: &Ray, rec: &HitRecord, attenuation: &mut Vector3, scattered: &mut Ray) -> bool {
+	fn sample(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+		match *self {
+			Material::Lambertian(ref inner) => inner.sample(ray_in, rec),
+			Material::Metal(ref inner) => inner.sample(ray_in, rec),
+			Material::Dielectric(ref inner) => inner.sample(ray_in, rec),
+			Material::DiffuseLight(ref inner) => inner.sample(ray_in, rec),
+			Material::Isotropic(ref inner) => inner.sample(ray_in, rec),
+			Material::HenyeyGreenstein(ref inner) => inner.sample(ray_in, rec),
+			Material::Conductor(ref inner) => inner.sample(ray_in, rec),
+			Material::Principled(ref inner) => inner.sample(ray_in, rec),
+		}
+	}
+
+	fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vector3) -> Vector3 {
+		match *self {
+			Material::Lambertian(ref inner) => inner.eval(ray_in, rec, direction),
+			Material::Metal(ref inner) => inner.eval(ray_in, rec, direction),
+			Material::Dielectric(ref inner) => inner.eval(ray_in, rec, direction),
+			Material::DiffuseLight(ref inner) => inner.eval(ray_in, rec, direction),
+			Material::Isotropic(ref inner) => inner.eval(ray_in, rec, direction),
+			Material::HenyeyGreenstein(ref inner) => inner.eval(ray_in, rec, direction),
+			Material::Conductor(ref inner) => inner.eval(ray_in, rec, direction),
+			Material::Principled(ref inner) => inner.eval(ray_in, rec, direction),
+		}
+	}
+
+	fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f64 {
 		match *self {
-			Material::Lambertian(ref inner) => inner.scatter(ray_in, rec, attenuation, scattered),
-			Material::Metal(ref inner) => inner.scatter(ray_in, rec, attenuation, scattered),
-			Material::Dielectric(ref inner) => inner.scatter(ray_in, rec, attenuation, scattered),
+			Material::Lambertian(ref inner) => inner.pdf(ray_in, rec, direction),
+			Material::Metal(ref inner) => inner.pdf(ray_in, rec, direction),
+			Material::Dielectric(ref inner) => inner.pdf(ray_in, rec, direction),
+			Material::DiffuseLight(ref inner) => inner.pdf(ray_in, rec, direction),
+			Material::Isotropic(ref inner) => inner.pdf(ray_in, rec, direction),
+			Material::HenyeyGreenstein(ref inner) => inner.pdf(ray_in, rec, direction),
+			Material::Conductor(ref inner) => inner.pdf(ray_in, rec, direction),
+			Material::Principled(ref inner) => inner.pdf(ray_in, rec, direction),
+		}
+	}
+
+	fn emitted(&self, u: f64, v: f64, p: &Vector3) -> Vector3 {
+		match *self {
+			Material::Lambertian(ref inner) => inner.emitted(u, v, p),
+			Material::Metal(ref inner) => inner.emitted(u, v, p),
+			Material::Dielectric(ref inner) => inner.emitted(u, v, p),
+			Material::DiffuseLight(ref inner) => inner.emitted(u, v, p),
+			Material::Isotropic(ref inner) => inner.emitted(u, v, p),
+			Material::HenyeyGreenstein(ref inner) => inner.emitted(u, v, p),
+			Material::Conductor(ref inner) => inner.emitted(u, v, p),
+			Material::Principled(ref inner) => inner.emitted(u, v, p),
 		}
 	}
 }
 
-pub trait Scatterable {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Vector3, scattered: &mut Ray) -> bool;
+// A sampled scattering event. weight is the BSDF times the cosine over pdf,
+// the factor the light arriving along ray is multiplied by. Delta lobes, like
+// mirrors and smooth glass, can't be evaluated for any other direction and
+// leave pdf unused.
+#[derive(Clone, Copy, Debug)]
+pub struct ScatterRecord {
+    pub ray: Ray,
+    pub weight: Vector3,
+    pub pdf: f64,
+    pub delta: bool,
+}
+
+impl ScatterRecord {
+    pub fn new(ray: Ray, weight: Vector3, pdf: f64) -> ScatterRecord {
+        ScatterRecord {
+            ray,
+            weight,
+            pdf,
+            delta: false,
+        }
+    }
+
+    pub fn delta(ray: Ray, weight: Vector3) -> ScatterRecord {
+        ScatterRecord {
+            ray,
+            weight,
+            pdf: 0.0,
+            delta: true,
+        }
+    }
 }
 
+pub trait Scatterable {
+    // Picks a direction to continue the path in, None when it ends here
+    fn sample(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord>;
+
+    // BSDF times the cosine to the normal (the phase function for media) for
+    // light arriving from direction and leaving back along ray_in. Zero for
+    // delta lobes.
+    fn eval(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: &Vector3) -> Vector3 {
+        Vector3::new(0.0, 0.0, 0.0)
+    }
+
+    // Solid angle density of sample() picking direction
+    fn pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: &Vector3) -> f64 {
+        0.0
+    }
+
+    // Light leaving the surface on its own, black for everything but emitters
+    fn emitted(&self, _u: f64, _v: f64, _p: &Vector3) -> Vector3 {
+        Vector3::new(0.0, 0.0, 0.0)
+    }
+}
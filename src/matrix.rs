@@ -0,0 +1,176 @@
+use std::f64::consts::PI;
+use std::ops::Mul;
+
+use vector::Vector3;
+
+// Row-major 4x4 matrix for affine transforms, points are columns (x, y, z, 1)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Matrix4 {
+        Matrix4 { m }
+    }
+
+    pub fn identity() -> Matrix4 {
+        Matrix4::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn translation(offset: &Vector3) -> Matrix4 {
+        Matrix4::new([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scaling(factors: &Vector3) -> Matrix4 {
+        Matrix4::new([
+            [factors.x(), 0.0, 0.0, 0.0],
+            [0.0, factors.y(), 0.0, 0.0],
+            [0.0, 0.0, factors.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Counter-clockwise rotation by degrees around axis, looking down the axis
+    pub fn rotation(axis: &Vector3, degrees: f64) -> Matrix4 {
+        let a: Vector3 = axis.unit_vector();
+        let (sin, cos): (f64, f64) = (degrees * PI / 180.0).sin_cos();
+        let k: f64 = 1.0 - cos;
+        Matrix4::new([
+            [
+                cos + a.x() * a.x() * k,
+                a.x() * a.y() * k - a.z() * sin,
+                a.x() * a.z() * k + a.y() * sin,
+                0.0,
+            ],
+            [
+                a.y() * a.x() * k + a.z() * sin,
+                cos + a.y() * a.y() * k,
+                a.y() * a.z() * k - a.x() * sin,
+                0.0,
+            ],
+            [
+                a.z() * a.x() * k - a.y() * sin,
+                a.z() * a.y() * k + a.x() * sin,
+                cos + a.z() * a.z() * k,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Rotates around x, then y, then z, all angles in degrees
+    pub fn euler(angles: &Vector3) -> Matrix4 {
+        Matrix4::rotation(&Vector3::new(0.0, 0.0, 1.0), angles.z())
+            * Matrix4::rotation(&Vector3::new(0.0, 1.0, 0.0), angles.y())
+            * Matrix4::rotation(&Vector3::new(1.0, 0.0, 0.0), angles.x())
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut t: Matrix4 = *self;
+        for (r, row) in t.m.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = self.m[c][r];
+            }
+        }
+        t
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, None for singular matrices
+    pub fn inverse(&self) -> Option<Matrix4> {
+        let mut a: [[f64; 4]; 4] = self.m;
+        let mut inv: [[f64; 4]; 4] = Matrix4::identity().m;
+        for col in 0..4 {
+            let pivot: usize = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().partial_cmp(&a[j][col].abs()).unwrap())
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let scale: f64 = 1.0 / a[col][col];
+            for c in 0..4 {
+                a[col][c] *= scale;
+                inv[col][c] *= scale;
+            }
+            for row in 0..4 {
+                if row == col {
+                    continue;
+                }
+                let factor: f64 = a[row][col];
+                for c in 0..4 {
+                    a[row][c] -= factor * a[col][c];
+                    inv[row][c] -= factor * inv[col][c];
+                }
+            }
+        }
+        Some(Matrix4::new(inv))
+    }
+
+    // Determinant of the upper 3x3 block, the volume scale of the transform
+    pub fn linear_determinant(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_point(&self, p: &Vector3) -> Vector3 {
+        self.transform_vector(p) + Vector3::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    // Ignores the translation, for directions
+    pub fn transform_vector(&self, v: &Vector3) -> Vector3 {
+        let m = &self.m;
+        Vector3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, other: Matrix4) -> Matrix4 {
+        let mut result: Matrix4 = Matrix4::new([[0.0; 4]; 4]);
+        for r in 0..4 {
+            for c in 0..4 {
+                result.m[r][c] = (0..4).map(|k| self.m[r][k] * other.m[k][c]).sum();
+            }
+        }
+        result
+    }
+}
+
+#[test]
+fn test_matrix() {
+    let close = |a: &Matrix4, b: &Matrix4| -> bool {
+        (0..4).all(|r| (0..4).all(|c| (a.m[r][c] - b.m[r][c]).abs() < 1e-12))
+    };
+    let rotation: Matrix4 = Matrix4::rotation(&Vector3::new(0.0, 0.0, 2.0), 90.0);
+    let p: Vector3 = rotation.transform_point(&Vector3::new(1.0, 0.0, 0.0));
+    assert!((p - Vector3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+    assert!(close(&Matrix4::euler(&Vector3::new(0.0, 0.0, 90.0)), &rotation));
+    assert!(close(&(rotation * rotation.transpose()), &Matrix4::identity()));
+
+    let m: Matrix4 = Matrix4::translation(&Vector3::new(1.0, 2.0, 3.0))
+        * Matrix4::euler(&Vector3::new(30.0, -45.0, 10.0))
+        * Matrix4::scaling(&Vector3::new(2.0, 0.5, 3.0));
+    assert!(close(&(m * m.inverse().unwrap()), &Matrix4::identity()));
+    assert!((m.linear_determinant() - 3.0).abs() < 1e-12);
+    assert_eq!(m.transform_vector(&Vector3::new(0.0, 0.0, 0.0)), Vector3::new(0.0, 0.0, 0.0));
+    assert_eq!(m.transform_point(&Vector3::new(0.0, 0.0, 0.0)), Vector3::new(1.0, 2.0, 3.0));
+    assert!(Matrix4::scaling(&Vector3::new(1.0, 0.0, 1.0)).inverse().is_none());
+}
@@ -0,0 +1,309 @@
+use std::sync::Arc;
+
+use aabb::Aabb;
+use bvh::BvhNode;
+use distribution::Distribution1D;
+use hitable::{HitRecord, Hittable};
+use hitable_list::HittableList;
+use material::Material;
+use random;
+use ray::Ray;
+use triangle::{fill_triangle_record, intersect_triangle, triangle_box};
+use vector::Vector3;
+
+// Vertex attributes shared by every triangle of a mesh; normals and uvs are
+// either empty or indexed exactly like positions
+#[derive(Debug)]
+pub struct MeshData {
+    pub positions: Vec<Vector3>,
+    pub normals: Vec<Vector3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub indices: Vec<[usize; 3]>,
+    pub material: Material,
+}
+
+impl MeshData {
+    fn vertices(&self, face: usize) -> [Vector3; 3] {
+        let idx: [usize; 3] = self.indices[face];
+        [self.positions[idx[0]], self.positions[idx[1]], self.positions[idx[2]]]
+    }
+}
+
+fn face_area(v: &[Vector3; 3]) -> f64 {
+    0.5 * (v[1] - v[0]).cross(&(v[2] - v[0])).length()
+}
+
+// A single face of a mesh, only stores its index into the shared buffers
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: usize,
+    // Area of the whole mesh, which is sampled as one light
+    mesh_area: f64,
+}
+
+impl MeshTriangle {
+    fn vertices(&self) -> [Vector3; 3] {
+        self.mesh.vertices(self.face)
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let v: [Vector3; 3] = self.vertices();
+        match intersect_triangle(ray, &v[0], &v[1], &v[2], t_min, t_max) {
+            Some((t, b1, b2)) => {
+                let idx: [usize; 3] = self.mesh.indices[self.face];
+                let normals: Option<[Vector3; 3]> = if self.mesh.normals.is_empty() {
+                    None
+                } else {
+                    let n: &Vec<Vector3> = &self.mesh.normals;
+                    Some([n[idx[0]], n[idx[1]], n[idx[2]]])
+                };
+                let uvs: Option<[(f64, f64); 3]> = if self.mesh.uvs.is_empty() {
+                    None
+                } else {
+                    let uv: &Vec<(f64, f64)> = &self.mesh.uvs;
+                    Some([uv[idx[0]], uv[idx[1]], uv[idx[2]]])
+                };
+                fill_triangle_record(
+                    ray,
+                    t,
+                    b1,
+                    b2,
+                    &v,
+                    normals.as_ref(),
+                    uvs.as_ref(),
+                    &self.mesh.material,
+                    rec,
+                );
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        let v: [Vector3; 3] = self.vertices();
+        *output_box = triangle_box(&v[0], &v[1], &v[2]);
+        true
+    }
+
+    // Density of picking this face by area and a uniform point on it, as a
+    // solid angle: distance^2 / (cos * mesh area)
+    fn pdf_value(&self, o: &Vector3, v: &Vector3) -> f64 {
+        let vertices: [Vector3; 3] = self.vertices();
+        let cross: Vector3 = (vertices[1] - vertices[0]).cross(&(vertices[2] - vertices[0]));
+        match intersect_triangle(&Ray::new(*o, *v), &vertices[0], &vertices[1], &vertices[2], 0.001, f64::MAX) {
+            Some((t, _, _)) if self.mesh_area > 0.0 => {
+                let cosine: f64 = (v.dot(&cross) / (v.length() * cross.length())).abs();
+                if cosine <= 0.0 {
+                    0.0
+                } else {
+                    t * t * v.norm() / (cosine * self.mesh_area)
+                }
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+pub struct TriangleMesh {
+    data: Arc<MeshData>,
+    bvh: BvhNode,
+    // Faces weighted by area, to sample the mesh as a light
+    areas: Distribution1D,
+}
+
+impl TriangleMesh {
+    // Panics unless normals and uvs are empty or match positions and every
+    // index is in range, try_new checks input that hasn't been validated
+    pub fn new(
+        positions: Vec<Vector3>,
+        normals: Vec<Vector3>,
+        uvs: Vec<(f64, f64)>,
+        indices: Vec<[usize; 3]>,
+        material: Material,
+    ) -> TriangleMesh {
+        TriangleMesh::try_new(positions, normals, uvs, indices, material).unwrap_or_else(|message| panic!("{}", message))
+    }
+
+    pub fn try_new(
+        positions: Vec<Vector3>,
+        normals: Vec<Vector3>,
+        uvs: Vec<(f64, f64)>,
+        indices: Vec<[usize; 3]>,
+        material: Material,
+    ) -> Result<TriangleMesh, String> {
+        if !normals.is_empty() && normals.len() != positions.len() {
+            return Err(format!("mesh needs one normal per position, found {} for {}", normals.len(), positions.len()));
+        }
+        if !uvs.is_empty() && uvs.len() != positions.len() {
+            return Err(format!("mesh needs one uv per position, found {} for {}", uvs.len(), positions.len()));
+        }
+        if let Some(&i) = indices.iter().flat_map(|f| f.iter()).find(|&&i| i >= positions.len()) {
+            return Err(format!("mesh index {} out of range ({} positions)", i, positions.len()));
+        }
+
+        let data: Arc<MeshData> = Arc::new(MeshData {
+            positions,
+            normals,
+            uvs,
+            indices,
+            material,
+        });
+        let areas: Vec<f64> = (0..data.indices.len()).map(|face| face_area(&data.vertices(face))).collect();
+        let mesh_area: f64 = areas.iter().sum();
+        let mut faces: HittableList = HittableList::new();
+        for face in 0..data.indices.len() {
+            faces.add(MeshTriangle {
+                mesh: data.clone(),
+                face,
+                mesh_area,
+            });
+        }
+        Ok(TriangleMesh {
+            data,
+            bvh: BvhNode::new(faces),
+            areas: Distribution1D::new(&areas),
+        })
+    }
+
+    pub fn data(&self) -> &MeshData {
+        &self.data
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.data.indices.len()
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.bvh.hit(ray, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        self.bvh.bounding_box(output_box)
+    }
+
+    // A direction can cross several faces, each adds its own density
+    fn pdf_value(&self, o: &Vector3, v: &Vector3) -> f64 {
+        self.bvh.pdf_sum(o, v)
+    }
+
+    fn random(&self, o: &Vector3) -> Vector3 {
+        if self.areas.is_empty() {
+            return Vector3::new(1.0, 0.0, 0.0);
+        }
+        let (face, _): (usize, f64) = self.areas.sample(random::random_f64());
+        let mut b1: f64 = random::random_f64();
+        let mut b2: f64 = random::random_f64();
+        if b1 + b2 > 1.0 {
+            b1 = 1.0 - b1;
+            b2 = 1.0 - b2;
+        }
+        let v: [Vector3; 3] = self.data.vertices(face);
+        v[0] + b1 * (v[1] - v[0]) + b2 * (v[2] - v[0]) - *o
+    }
+}
+
+#[test]
+fn test_mesh_quad() {
+    use lambertian::Lambertian;
+
+    let material: Material = Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+    let mesh: TriangleMesh = TriangleMesh::new(
+        vec![
+            Vector3::new(-1.0, -1.0, -3.0),
+            Vector3::new(1.0, -1.0, -3.0),
+            Vector3::new(1.0, 1.0, -3.0),
+            Vector3::new(-1.0, 1.0, -3.0),
+        ],
+        Vec::new(),
+        vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+        vec![[0, 1, 2], [0, 2, 3]],
+        material,
+    );
+    assert_eq!(mesh.triangle_count(), 2);
+
+    let mut rec: HitRecord = HitRecord::new();
+    let ray: Ray = Ray::new(Vector3::new(-0.5, 0.5, 0.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(mesh.hit(&ray, 0.001, f64::MAX, &mut rec));
+    assert_eq!(rec.t, 3.0);
+    assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.75).abs() < 1e-12);
+
+    let miss: Ray = Ray::new(Vector3::new(1.5, 0.5, 0.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(!mesh.hit(&miss, 0.001, f64::MAX, &mut rec));
+}
+
+#[test]
+fn test_mesh_try_new_errors() {
+    use lambertian::Lambertian;
+
+    let material: Material = Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+    let positions: Vec<Vector3> =
+        vec![Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0)];
+    let normals: Vec<Vector3> = vec![Vector3::new(0.0, 0.0, 1.0)];
+    let error: String = TriangleMesh::try_new(positions.clone(), normals, Vec::new(), vec![[0, 1, 2]], material.clone())
+        .err()
+        .unwrap();
+    assert!(error.contains("one normal per position"), "{}", error);
+    let error: String =
+        TriangleMesh::try_new(positions.clone(), Vec::new(), vec![(0.0, 0.0)], vec![[0, 1, 2]], material.clone())
+            .err()
+            .unwrap();
+    assert!(error.contains("one uv per position"), "{}", error);
+    let error: String =
+        TriangleMesh::try_new(positions, Vec::new(), Vec::new(), vec![[0, 1, 3]], material).err().unwrap();
+    assert!(error.contains("index 3 out of range"), "{}", error);
+}
+
+#[test]
+fn test_mesh_light_sampling() {
+    use lambertian::Lambertian;
+
+    let material: Material = Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+    let square = |z: f64| -> Vec<Vector3> {
+        vec![
+            Vector3::new(-1.0, -1.0, z),
+            Vector3::new(1.0, -1.0, z),
+            Vector3::new(1.0, 1.0, z),
+            Vector3::new(-1.0, 1.0, z),
+        ]
+    };
+    let mesh: TriangleMesh =
+        TriangleMesh::new(square(-3.0), Vec::new(), Vec::new(), vec![[0, 1, 2], [0, 2, 3]], material.clone());
+    let origin: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+
+    // Samples land on the mesh and 1 / pdf averages to its solid angle,
+    // 4 asin(1 / 10) for a 2 x 2 square 3 away
+    random::seed(6);
+    let samples: usize = 20000;
+    let mut solid_angle: f64 = 0.0;
+    for _ in 0..samples {
+        let v: Vector3 = mesh.random(&origin);
+        assert!((v.z() + 3.0).abs() < 1e-9 && v.x().abs() <= 1.0 && v.y().abs() <= 1.0);
+        let pdf: f64 = mesh.pdf_value(&origin, &v);
+        assert!(pdf > 0.0);
+        solid_angle += 1.0 / (pdf * samples as f64);
+    }
+    let expected: f64 = 4.0 * 0.1f64.asin();
+    assert!((solid_angle - expected).abs() < 0.01 * expected, "{} vs {}", solid_angle, expected);
+    assert_eq!(mesh.pdf_value(&origin, &Vector3::new(0.0, 0.0, 1.0)), 0.0);
+
+    // A direction crossing two faces adds both densities, each over the
+    // area of the whole mesh
+    let mut positions: Vec<Vector3> = square(-1.0);
+    positions.extend(square(-2.0));
+    let stacked: TriangleMesh = TriangleMesh::new(
+        positions,
+        Vec::new(),
+        Vec::new(),
+        vec![[0, 1, 2], [0, 2, 3], [4, 5, 6], [4, 6, 7]],
+        material,
+    );
+    let v: Vector3 = Vector3::new(0.1, 0.3, -1.0);
+    let cosine: f64 = 1.0 / v.length();
+    let expected: f64 = (v.norm() + 4.0 * v.norm()) / (cosine * 8.0);
+    assert!((stacked.pdf_value(&origin, &v) - expected).abs() < 1e-9);
+}
@@ -1,28 +1,102 @@
-use material::Scatterable;
+use material::{ScatterRecord, Scatterable};
 use hitable::HitRecord;
+use microfacet::{incident_frame, reflect_about, Ggx};
+use onb::Onb;
 use vector::Vector3;
+use random;
 use ray::Ray;
-use lambertian::point_in_unit_sphere;
+use texture::{self, SharedTexture};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct Metal {
-    albedo: Vector3,
+    albedo: SharedTexture,
     fuzz: f64,
 }
 
 impl Metal {
     pub fn new(albedo: Vector3, fuzz: f64) -> Metal {
+        Metal::textured(texture::constant(albedo), fuzz)
+    }
+
+    pub fn textured(albedo: SharedTexture, fuzz: f64) -> Metal {
         let f: f64 = if fuzz < 1.0 {fuzz} else {1.0};
-        Metal { albedo: albedo, fuzz: f }
+        Metal { albedo, fuzz: f.max(0.0) }
+    }
+
+    // A fuzzed metal reflects through a GGX lobe whose alpha is the fuzz,
+    // with the albedo in place of a Fresnel term. Without fuzz it's a mirror.
+    fn distribution(&self) -> Option<Ggx> {
+        if self.fuzz > 0.0 {
+            Some(Ggx::new(self.fuzz, self.fuzz))
+        } else {
+            None
+        }
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Vector3 {
+        self.albedo.filtered(rec.u, rec.v, &rec.p(), rec.footprint)
     }
 }
 
+// Outgoing and incoming directions in the shading frame on the ray's side
+fn local_directions(ray_in: &Ray, rec: &HitRecord, direction: &Vector3) -> (Vector3, Vector3) {
+    let (frame, _): (Onb, bool) = incident_frame(&ray_in.direction(), &rec.normal());
+    (frame.to_local(&-ray_in.direction().unit_vector()), frame.to_local(&direction.unit_vector()))
+}
+
 impl Scatterable for Metal {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, attenuation: &mut Vector3, scattered: &mut Ray) -> bool {
-        let reflected: Vector3 = reflect(&ray_in.direction().unit_vector(), &rec.normal());
-        *scattered = Ray::new(rec.p(), reflected + self.fuzz*point_in_unit_sphere());
-        *attenuation = self.albedo;
-        scattered.direction().dot(&rec.normal()) > 0.0
+    fn sample(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let distribution: Ggx = match self.distribution() {
+            Some(distribution) => distribution,
+            None => {
+                let reflected: Vector3 = reflect(&ray_in.direction().unit_vector(), &rec.normal());
+                let scattered: Ray = Ray::new(rec.p(), reflected).with_time(ray_in.time());
+                if scattered.direction().dot(&rec.normal()) <= 0.0 {
+                    return None;
+                }
+                return Some(ScatterRecord::delta(scattered, self.albedo(rec)));
+            }
+        };
+        let (frame, _): (Onb, bool) = incident_frame(&ray_in.direction(), &rec.normal());
+        let wo: Vector3 = frame.to_local(&-ray_in.direction().unit_vector());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let m: Vector3 = distribution.sample_visible(&wo, random::random_f64(), random::random_f64());
+        let wi: Vector3 = reflect_about(&wo, &m);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        let weight: f64 = distribution.g(&wo, &wi) / distribution.g1(&wo);
+        let pdf: f64 = distribution.pdf_visible(&wo, &m) / (4.0 * wo.dot(&m));
+        let scattered: Ray = Ray::new(rec.p(), frame.local_vector(&wi)).with_time(ray_in.time());
+        Some(ScatterRecord::new(scattered, self.albedo(rec) * weight, pdf))
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vector3) -> Vector3 {
+        let distribution: Ggx = match self.distribution() {
+            Some(distribution) => distribution,
+            None => return Vector3::new(0.0, 0.0, 0.0),
+        };
+        let (wo, wi): (Vector3, Vector3) = local_directions(ray_in, rec, direction);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Vector3::new(0.0, 0.0, 0.0);
+        }
+        let m: Vector3 = (wo + wi).unit_vector();
+        self.albedo(rec) * (distribution.d(&m) * distribution.g(&wo, &wi) / (4.0 * wo.z()))
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f64 {
+        let distribution: Ggx = match self.distribution() {
+            Some(distribution) => distribution,
+            None => return 0.0,
+        };
+        let (wo, wi): (Vector3, Vector3) = local_directions(ray_in, rec, direction);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let m: Vector3 = (wo + wi).unit_vector();
+        distribution.pdf_visible(&wo, &m) / (4.0 * wo.dot(&m))
     }
 }
 
@@ -36,4 +110,36 @@ fn test_reflect() {
     let n: Vector3 = Vector3::new(0.0, 0.0, 0.0);
 
     assert_eq!(reflect(&v, &n), v);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_metal_sampling() {
+    let albedo: Vector3 = Vector3::new(0.9, 0.6, 0.3);
+    let mut rec: HitRecord = HitRecord::new();
+    rec.normal = Vector3::new(0.0, 1.0, 0.0);
+    let ray_in: Ray = Ray::new(Vector3::new(-1.0, 1.0, 0.5), Vector3::new(1.0, -1.0, -0.5));
+
+    // Without fuzz the reflection is a delta lobe that can't be evaluated
+    let mirror: Metal = Metal::new(albedo, 0.0);
+    let sample: ScatterRecord = mirror.sample(&ray_in, &rec).unwrap();
+    assert!(sample.delta);
+    assert_eq!(sample.weight, albedo);
+    assert_eq!(mirror.pdf(&ray_in, &rec, &sample.ray.direction()), 0.0);
+
+    // A fuzzed one samples a lobe around the mirror direction and its weight
+    // is eval / pdf, so it takes part in light sampling
+    let brushed: Metal = Metal::new(albedo, 0.3);
+    random::seed(8);
+    for _ in 0..1000 {
+        let sample: ScatterRecord = match brushed.sample(&ray_in, &rec) {
+            Some(sample) => sample,
+            None => continue,
+        };
+        assert!(!sample.delta && sample.pdf > 0.0);
+        let direction: Vector3 = sample.ray.direction();
+        assert!((brushed.pdf(&ray_in, &rec, &direction) - sample.pdf).abs() < 1e-9 * sample.pdf);
+        let ratio: Vector3 = brushed.eval(&ray_in, &rec, &direction) / sample.pdf;
+        assert!((ratio - sample.weight).length() < 1e-9, "{:?} vs {:?}", ratio, sample.weight);
+    }
+    assert_eq!(brushed.pdf(&ray_in, &rec, &Vector3::new(0.0, -1.0, 0.0)), 0.0);
+}
@@ -0,0 +1,172 @@
+use std::f64::consts::PI;
+
+use onb::Onb;
+use vector::Vector3;
+
+// Below this alpha the distribution is too close to a delta to evaluate
+const MIN_ALPHA: f64 = 1e-4;
+
+// GGX (Trowbridge-Reitz) microfacet distribution with separable Smith
+// shadowing, in a local frame with the macro normal along z. alpha_x and
+// alpha_y are the roughness along the tangent and the bitangent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Ggx {
+        Ggx {
+            alpha_x: alpha_x.max(MIN_ALPHA),
+            alpha_y: alpha_y.max(MIN_ALPHA),
+        }
+    }
+
+    // Perceptually linear roughness in [0, 1] squared gives alpha
+    pub fn from_roughness(roughness_x: f64, roughness_y: f64) -> Ggx {
+        Ggx::new(roughness_x * roughness_x, roughness_y * roughness_y)
+    }
+
+    // Density of microfacet normals m, per unit of projected area
+    pub fn d(&self, m: &Vector3) -> f64 {
+        if m.z() <= 0.0 {
+            return 0.0;
+        }
+        let x: f64 = m.x() / self.alpha_x;
+        let y: f64 = m.y() / self.alpha_y;
+        let t: f64 = x * x + y * y + m.z() * m.z();
+        1.0 / (PI * self.alpha_x * self.alpha_y * t * t)
+    }
+
+    fn lambda(&self, w: &Vector3) -> f64 {
+        let z2: f64 = w.z() * w.z();
+        if z2 == 0.0 {
+            return f64::INFINITY;
+        }
+        let a2: f64 = self.alpha_x * self.alpha_x * w.x() * w.x() + self.alpha_y * self.alpha_y * w.y() * w.y();
+        0.5 * (-1.0 + (1.0 + a2 / z2).sqrt())
+    }
+
+    // Fraction of microfacets facing w that w sees
+    pub fn g1(&self, w: &Vector3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height correlated masking and shadowing for both directions
+    pub fn g(&self, wo: &Vector3, wi: &Vector3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Normals visible from wo (wo.z > 0), sampled proportional to their
+    // projected area as in Heitz, "Sampling the GGX Distribution of Visible Normals"
+    pub fn sample_visible(&self, wo: &Vector3, u1: f64, u2: f64) -> Vector3 {
+        let vh: Vector3 = Vector3::new(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()).unit_vector();
+        let length_squared: f64 = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1: Vector3 = if length_squared > 0.0 {
+            Vector3::new(-vh.y(), vh.x(), 0.0) / length_squared.sqrt()
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let t2: Vector3 = vh.cross(&t1);
+        let r: f64 = u1.sqrt();
+        let phi: f64 = 2.0 * PI * u2;
+        let p1: f64 = r * phi.cos();
+        let s: f64 = 0.5 * (1.0 + vh.z());
+        let p2: f64 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh: Vector3 = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+        Vector3::new(self.alpha_x * nh.x(), self.alpha_y * nh.y(), nh.z().max(0.0)).unit_vector()
+    }
+
+    // Density of sample_visible returning m
+    pub fn pdf_visible(&self, wo: &Vector3, m: &Vector3) -> f64 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(m).max(0.0) * self.d(m) / wo.z()
+    }
+}
+
+// Frame around the shading normal. Without tangents on the geometry the
+// tangent circles the world y axis, so anisotropy looks like brushing around it.
+pub fn shading_frame(n: &Vector3) -> Onb {
+    let w: Vector3 = n.unit_vector();
+    let mut u: Vector3 = Vector3::new(0.0, 1.0, 0.0).cross(&w);
+    if u.norm() < 1e-12 {
+        u = Vector3::new(1.0, 0.0, 0.0);
+    }
+    let u: Vector3 = u.unit_vector();
+    let v: Vector3 = w.cross(&u);
+    Onb { u, v, w }
+}
+
+// Shading frame on the side of the surface a ray travelling along direction
+// arrives from, and whether that is the side the normal points to
+pub fn incident_frame(direction: &Vector3, normal: &Vector3) -> (Onb, bool) {
+    let front: bool = direction.dot(normal) <= 0.0;
+    let facing: Vector3 = if front { *normal } else { -*normal };
+    (shading_frame(&facing), front)
+}
+
+// Microfacet normal on wo's side that refracts wo into wi, eta the index of
+// wi's side relative to wo's, None if no microfacet does
+pub fn refraction_half_vector(wo: &Vector3, wi: &Vector3, eta: f64) -> Option<Vector3> {
+    let m: Vector3 = -(*wo + *wi * eta);
+    if m.norm() == 0.0 {
+        return None;
+    }
+    let m: Vector3 = if m.z() < 0.0 { -m.unit_vector() } else { m.unit_vector() };
+    if wo.dot(&m) <= 0.0 || wi.dot(&m) >= 0.0 {
+        return None;
+    }
+    Some(m)
+}
+
+// Mirror of w around the microfacet normal m
+pub fn reflect_about(w: &Vector3, m: &Vector3) -> Vector3 {
+    2.0 * w.dot(m) * *m - *w
+}
+
+#[test]
+fn test_ggx() {
+    use random;
+
+    let ggx: Ggx = Ggx::from_roughness(0.5, 0.8);
+    let wo: Vector3 = Vector3::new(0.6, -0.3, 0.5).unit_vector();
+    // Numerically integrate over the hemisphere of normals: the projected
+    // microfacet area adds up to the macro surface, the visible normal density
+    // to one, and its mean m.z is what sampling should reproduce
+    let steps: usize = 400;
+    let (mut projected, mut visible, mut mean_z): (f64, f64, f64) = (0.0, 0.0, 0.0);
+    for i in 0..steps {
+        for j in 0..steps {
+            let cos_theta: f64 = (i as f64 + 0.5) / steps as f64;
+            let phi: f64 = 2.0 * PI * (j as f64 + 0.5) / steps as f64;
+            let sin_theta: f64 = (1.0 - cos_theta * cos_theta).sqrt();
+            let m: Vector3 = Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+            let solid_angle: f64 = 2.0 * PI / (steps * steps) as f64;
+            projected += ggx.d(&m) * cos_theta * solid_angle;
+            visible += ggx.pdf_visible(&wo, &m) * solid_angle;
+            mean_z += cos_theta * ggx.pdf_visible(&wo, &m) * solid_angle;
+        }
+    }
+    assert!((projected - 1.0).abs() < 1e-2, "{}", projected);
+    assert!((visible - 1.0).abs() < 1e-2, "{}", visible);
+
+    random::seed(3);
+    let samples: usize = 50000;
+    let mut sampled_z: f64 = 0.0;
+    for _ in 0..samples {
+        let m: Vector3 = ggx.sample_visible(&wo, random::random_f64(), random::random_f64());
+        assert!((m.length() - 1.0).abs() < 1e-9 && m.z() >= 0.0);
+        assert!(wo.dot(&m) >= -1e-9);
+        sampled_z += m.z() / samples as f64;
+    }
+    assert!((sampled_z - mean_z).abs() < 5e-3, "{} vs {}", sampled_z, mean_z);
+
+    assert_eq!(ggx.g1(&Vector3::new(0.0, 0.0, 1.0)), 1.0);
+    assert!(ggx.g(&wo, &wo) < ggx.g1(&wo));
+    let frame: Onb = shading_frame(&Vector3::new(0.0, 0.0, 2.0));
+    assert_eq!(frame.w, Vector3::new(0.0, 0.0, 1.0));
+    assert_eq!(frame.u, Vector3::new(1.0, 0.0, 0.0));
+}
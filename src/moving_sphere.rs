@@ -0,0 +1,98 @@
+use aabb::Aabb;
+use hitable::{HitRecord, Hittable};
+use material::Material;
+use ray::Ray;
+use sphere::hit_sphere;
+use vector::Vector3;
+
+// Sphere whose center follows a path through keyframes, moving in a straight
+// line between consecutive ones and resting at the first and last outside them
+#[derive(Clone, Debug)]
+pub struct MovingSphere {
+    // (time, center) sorted by time
+    keyframes: Vec<(f64, Vector3)>,
+    radius: f64,
+    material: Material,
+}
+
+impl MovingSphere {
+    pub fn new(center0: Vector3, center1: Vector3, time0: f64, time1: f64, radius: f64, material: Material) -> MovingSphere {
+        MovingSphere::keyframed(vec![(time0, center0), (time1, center1)], radius, material)
+    }
+
+    pub fn keyframed(mut keyframes: Vec<(f64, Vector3)>, radius: f64, material: Material) -> MovingSphere {
+        assert!(!keyframes.is_empty());
+        keyframes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        MovingSphere {
+            keyframes,
+            radius,
+            material,
+        }
+    }
+
+    pub fn center(&self, time: f64) -> Vector3 {
+        let index: usize = self.keyframes.partition_point(|&(t, _)| t <= time);
+        if index == 0 {
+            return self.keyframes[0].1;
+        }
+        if index == self.keyframes.len() {
+            return self.keyframes[index - 1].1;
+        }
+        let (t0, c0): (f64, Vector3) = self.keyframes[index - 1];
+        let (t1, c1): (f64, Vector3) = self.keyframes[index];
+        c0 + (time - t0) / (t1 - t0) * (c1 - c0)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        hit_sphere(&self.center(ray.time()), self.radius, &self.material, ray, t_min, t_max, rec)
+    }
+
+    // The path is piecewise linear, so the boxes at the keyframes enclose all of it
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        let r: Vector3 = Vector3::new(self.radius.abs(), self.radius.abs(), self.radius.abs());
+        let mut result: Aabb = Aabb::empty();
+        for &(_, center) in &self.keyframes {
+            result = result.surrounding_box(&Aabb::new(center - r, center + r));
+        }
+        *output_box = result;
+        true
+    }
+}
+
+#[test]
+fn test_moving_sphere() {
+    use lambertian::Lambertian;
+
+    let material: Material = Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+    let sphere: MovingSphere = MovingSphere::keyframed(
+        vec![
+            (1.0, Vector3::new(2.0, 0.0, -5.0)),
+            (0.0, Vector3::new(0.0, 0.0, -5.0)),
+            (2.0, Vector3::new(2.0, 2.0, -5.0)),
+        ],
+        0.5,
+        material.clone(),
+    );
+    assert_eq!(sphere.center(-1.0), Vector3::new(0.0, 0.0, -5.0));
+    assert_eq!(sphere.center(0.5), Vector3::new(1.0, 0.0, -5.0));
+    assert_eq!(sphere.center(1.5), Vector3::new(2.0, 1.0, -5.0));
+    assert_eq!(sphere.center(3.0), Vector3::new(2.0, 2.0, -5.0));
+
+    let mut rec: HitRecord = HitRecord::new();
+    let ray: Ray = Ray::new(Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(sphere.hit(&ray.with_time(0.5), 0.001, f64::MAX, &mut rec));
+    assert_eq!(rec.t, 4.5);
+    assert!(!sphere.hit(&ray.with_time(0.0), 0.001, f64::MAX, &mut rec));
+    assert!(!sphere.hit(&ray.with_time(1.5), 0.001, f64::MAX, &mut rec));
+
+    let mut bbox: Aabb = Aabb::empty();
+    assert!(sphere.bounding_box(&mut bbox));
+    assert_eq!(bbox.min(), Vector3::new(-0.5, -0.5, -5.5));
+    assert_eq!(bbox.max(), Vector3::new(2.5, 2.5, -4.5));
+
+    let linear: MovingSphere =
+        MovingSphere::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 4.0, 0.0), 0.0, 1.0, 1.0, material);
+    assert_eq!(linear.center(0.25), Vector3::new(0.0, 1.0, 0.0));
+}
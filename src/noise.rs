@@ -0,0 +1,322 @@
+use std::f64::consts::PI;
+use std::fmt;
+
+use rand::{Rng, XorShiftRng};
+
+use random;
+use texture::Texture;
+use vector::Vector3;
+
+const POINT_COUNT: usize = 256;
+
+// Gradient noise after Perlin's improved noise: random unit gradients on the
+// integer lattice, shuffled per axis by seeded permutations
+#[derive(Clone)]
+pub struct Perlin {
+    gradients: Vec<Vector3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+fn permutation(rng: &mut XorShiftRng) -> Vec<usize> {
+    let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+    for i in (1..POINT_COUNT).rev() {
+        let target: usize = rng.gen_range(0, i + 1);
+        perm.swap(i, target);
+    }
+    perm
+}
+
+impl fmt::Debug for Perlin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Perlin")
+    }
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut rng: XorShiftRng = random::generator(seed);
+        let mut gradients: Vec<Vector3> = Vec::with_capacity(POINT_COUNT);
+        while gradients.len() < POINT_COUNT {
+            let g: Vector3 = Vector3::new(
+                rng.gen_range(-1.0, 1.0),
+                rng.gen_range(-1.0, 1.0),
+                rng.gen_range(-1.0, 1.0),
+            );
+            let norm: f64 = g.norm();
+            if norm > 1e-4 && norm <= 1.0 {
+                gradients.push(g.unit_vector());
+            }
+        }
+        Perlin {
+            gradients,
+            perm_x: permutation(&mut rng),
+            perm_y: permutation(&mut rng),
+            perm_z: permutation(&mut rng),
+        }
+    }
+
+    // Roughly in [-1, 1], zero on every lattice point
+    pub fn noise(&self, p: &Vector3) -> f64 {
+        let (fx, fy, fz) = (p.x().floor(), p.y().floor(), p.z().floor());
+        let (u, v, w) = (p.x() - fx, p.y() - fy, p.z() - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+        let mask: i64 = POINT_COUNT as i64 - 1;
+        // Hermite smoothing hides the lattice
+        let (uu, vv, ww) = (u * u * (3.0 - 2.0 * u), v * v * (3.0 - 2.0 * v), w * w * (3.0 - 2.0 * w));
+        let mut sum: f64 = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index: usize = self.perm_x[((i + di) & mask) as usize]
+                        ^ self.perm_y[((j + dj) & mask) as usize]
+                        ^ self.perm_z[((k + dk) & mask) as usize];
+                    let offset: Vector3 = Vector3::new(u - di as f64, v - dj as f64, w - dk as f64);
+                    let (a, b, c) = (di as f64, dj as f64, dk as f64);
+                    sum += (a * uu + (1.0 - a) * (1.0 - uu))
+                        * (b * vv + (1.0 - b) * (1.0 - vv))
+                        * (c * ww + (1.0 - c) * (1.0 - ww))
+                        * self.gradients[index].dot(&offset);
+                }
+            }
+        }
+        sum
+    }
+
+    // Sum of octaves at doubling frequency and halving weight, non negative
+    pub fn turbulence(&self, p: &Vector3, octaves: usize) -> f64 {
+        let mut sum: f64 = 0.0;
+        let mut point: Vector3 = *p;
+        let mut weight: f64 = 1.0;
+        for _ in 0..octaves {
+            sum += weight * self.noise(&point);
+            weight *= 0.5;
+            point = 2.0 * point;
+        }
+        sum.abs()
+    }
+}
+
+// Cellular noise: one random feature point per lattice cell, the value is
+// the distance to the closest one
+#[derive(Clone, Copy, Debug)]
+pub struct Worley {
+    seed: u64,
+}
+
+impl Worley {
+    pub fn new(seed: u64) -> Worley {
+        Worley { seed }
+    }
+
+    fn feature_point(&self, i: i64, j: i64, k: i64) -> Vector3 {
+        let cell: u64 = (i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+            ^ (j as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f)
+            ^ (k as u64).wrapping_mul(0x1656_67b1_9e37_79f9);
+        let hash: u64 = random::derive_seed(self.seed, cell);
+        let unit = |shift: u32| ((hash >> shift) & 0x1f_ffff) as f64 / 0x20_0000 as f64;
+        Vector3::new(i as f64 + unit(0), j as f64 + unit(21), k as f64 + unit(42))
+    }
+
+    // Distance to the nearest feature point, in cell units
+    pub fn distance(&self, p: &Vector3) -> f64 {
+        let (i, j, k) = (p.x().floor() as i64, p.y().floor() as i64, p.z().floor() as i64);
+        let mut nearest: f64 = f64::MAX;
+        for di in -1..2 {
+            for dj in -1..2 {
+                for dk in -1..2 {
+                    let d: f64 = (self.feature_point(i + di, j + dj, k + dk) - *p).norm();
+                    nearest = nearest.min(d);
+                }
+            }
+        }
+        nearest.sqrt()
+    }
+}
+
+fn mix(a: &Vector3, b: &Vector3, t: f64) -> Vector3 {
+    (1.0 - t) * *a + t * *b
+}
+
+// Plain noise, remapped to [0, 1] and scaling color
+#[derive(Clone, Debug)]
+pub struct NoiseTexture {
+    perlin: Perlin,
+    scale: f64,
+    color: Vector3,
+}
+
+impl NoiseTexture {
+    pub fn new(seed: u64, scale: f64, color: Vector3) -> NoiseTexture {
+        NoiseTexture {
+            perlin: Perlin::new(seed),
+            scale,
+            color,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Vector3) -> Vector3 {
+        0.5 * (1.0 + self.perlin.noise(&(self.scale * *p))) * self.color
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TurbulenceTexture {
+    perlin: Perlin,
+    scale: f64,
+    octaves: usize,
+    color: Vector3,
+}
+
+impl TurbulenceTexture {
+    pub fn new(seed: u64, scale: f64, octaves: usize, color: Vector3) -> TurbulenceTexture {
+        TurbulenceTexture {
+            perlin: Perlin::new(seed),
+            scale,
+            octaves,
+            color,
+        }
+    }
+}
+
+impl Texture for TurbulenceTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Vector3) -> Vector3 {
+        self.perlin.turbulence(&(self.scale * *p), self.octaves).min(1.0) * self.color
+    }
+}
+
+// Bands along z whose phase is pushed around by turbulence
+#[derive(Clone, Debug)]
+pub struct MarbleTexture {
+    perlin: Perlin,
+    scale: f64,
+    octaves: usize,
+    // How far turbulence bends the veins
+    strength: f64,
+    light: Vector3,
+    dark: Vector3,
+}
+
+impl MarbleTexture {
+    pub fn new(seed: u64, scale: f64, octaves: usize, strength: f64, light: Vector3, dark: Vector3) -> MarbleTexture {
+        MarbleTexture {
+            perlin: Perlin::new(seed),
+            scale,
+            octaves,
+            strength,
+            light,
+            dark,
+        }
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Vector3) -> Vector3 {
+        let phase: f64 = self.scale * p.z() + self.strength * self.perlin.turbulence(p, self.octaves);
+        mix(&self.dark, &self.light, 0.5 * (1.0 + phase.sin()))
+    }
+}
+
+// Growth rings around the y axis, distorted by turbulence
+#[derive(Clone, Debug)]
+pub struct WoodTexture {
+    perlin: Perlin,
+    // Rings per unit of radius
+    scale: f64,
+    octaves: usize,
+    strength: f64,
+    light: Vector3,
+    dark: Vector3,
+}
+
+impl WoodTexture {
+    pub fn new(seed: u64, scale: f64, octaves: usize, strength: f64, light: Vector3, dark: Vector3) -> WoodTexture {
+        WoodTexture {
+            perlin: Perlin::new(seed),
+            scale,
+            octaves,
+            strength,
+            light,
+            dark,
+        }
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Vector3) -> Vector3 {
+        let radius: f64 = (p.x() * p.x() + p.z() * p.z()).sqrt();
+        let rings: f64 = self.scale * radius + self.strength * self.perlin.turbulence(p, self.octaves);
+        // Sharp late wood edge, slow fade back to early wood
+        let t: f64 = (rings - rings.floor()).powf(3.0);
+        mix(&self.light, &self.dark, 0.5 * (1.0 - (PI * t).cos()))
+    }
+}
+
+// Cells colored by the distance to their feature point: near at the point,
+// far at the borders
+#[derive(Clone, Debug)]
+pub struct WorleyTexture {
+    worley: Worley,
+    scale: f64,
+    near: Vector3,
+    far: Vector3,
+}
+
+impl WorleyTexture {
+    pub fn new(seed: u64, scale: f64, near: Vector3, far: Vector3) -> WorleyTexture {
+        WorleyTexture {
+            worley: Worley::new(seed),
+            scale,
+            near,
+            far,
+        }
+    }
+}
+
+impl Texture for WorleyTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Vector3) -> Vector3 {
+        let d: f64 = self.worley.distance(&(self.scale * *p));
+        mix(&self.near, &self.far, d.min(1.0))
+    }
+}
+
+#[test]
+fn test_perlin() {
+    let perlin: Perlin = Perlin::new(3);
+    let mut min: f64 = 0.0;
+    let mut max: f64 = 0.0;
+    for i in 0..1000 {
+        let p: Vector3 = Vector3::new(i as f64 * 0.173, i as f64 * 0.051, i as f64 * -0.29);
+        let n: f64 = perlin.noise(&p);
+        min = min.min(n);
+        max = max.max(n);
+        // Continuous: a tiny step barely moves it
+        assert!((perlin.noise(&(p + Vector3::new(1e-6, 0.0, 0.0))) - n).abs() < 1e-4);
+    }
+    assert!(min > -1.1 && max < 1.1 && min < -0.2 && max > 0.2, "{} {}", min, max);
+    assert_eq!(perlin.noise(&Vector3::new(2.0, -5.0, 7.0)), 0.0);
+
+    // Same seed, same noise; another seed, another pattern
+    let p: Vector3 = Vector3::new(0.3, 1.7, -2.2);
+    assert_eq!(Perlin::new(3).noise(&p), perlin.noise(&p));
+    assert_ne!(Perlin::new(4).noise(&p), perlin.noise(&p));
+    assert!(perlin.turbulence(&p, 7) >= 0.0);
+}
+
+#[test]
+fn test_worley() {
+    let worley: Worley = Worley::new(9);
+    for i in 0..200 {
+        let p: Vector3 = Vector3::new(i as f64 * 0.37, i as f64 * -0.11, i as f64 * 0.23);
+        let d: f64 = worley.distance(&p);
+        // A cell's own point is never further than the cell diagonal
+        assert!((0.0..=3f64.sqrt()).contains(&d));
+        assert_eq!(d, Worley::new(9).distance(&p));
+    }
+    let point: Vector3 = worley.feature_point(4, -2, 1);
+    assert!(worley.distance(&point) < 1e-12);
+    assert!(worley.distance(&point) != Worley::new(10).distance(&point));
+}
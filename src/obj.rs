@@ -0,0 +1,556 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use dielectric::Dielectric;
+use diffuse_light::DiffuseLight;
+use hitable_list::HittableList;
+use image_reader::ImageError;
+use lambertian::Lambertian;
+use material::Material;
+use mesh::TriangleMesh;
+use metal::Metal;
+use principled::Principled;
+use texture::{self, ImageTexture, SharedTexture};
+use vector::Vector3;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io { file: String, error: io::Error },
+    Parse { file: String, line: usize, message: String },
+    Image(ImageError),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ObjError::Io { ref file, ref error } => write!(f, "{}: {}", file, error),
+            ObjError::Parse {
+                ref file,
+                line,
+                ref message,
+            } => write!(f, "{}:{}: {}", file, line, message),
+            ObjError::Image(ref error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+impl From<ImageError> for ObjError {
+    fn from(error: ImageError) -> ObjError {
+        ObjError::Image(error)
+    }
+}
+
+// Raw values of a `newmtl` block, kept around so callers can map them differently
+#[derive(Clone, Debug, PartialEq)]
+pub struct MtlMaterial {
+    pub name: String,
+    pub kd: Vector3,
+    pub ks: Vector3,
+    pub ke: Vector3,
+    pub ns: f64,
+    pub ni: f64,
+    pub d: f64,
+    pub illum: u32,
+    // Diffuse color map, relative to the MTL file until load_mtl resolves it
+    pub map_kd: Option<PathBuf>,
+    // PBR extension: roughness, metallic, sheen, clearcoat thickness and
+    // roughness and anisotropy. Any of them selects the principled material.
+    pub pr: Option<f64>,
+    pub pm: Option<f64>,
+    pub ps: Option<f64>,
+    pub pc: Option<f64>,
+    pub pcr: Option<f64>,
+    pub aniso: Option<f64>,
+}
+
+impl MtlMaterial {
+    pub fn new(name: &str) -> MtlMaterial {
+        MtlMaterial {
+            name: name.to_string(),
+            kd: Vector3::new(0.8, 0.8, 0.8),
+            ks: Vector3::new(0.0, 0.0, 0.0),
+            ke: Vector3::new(0.0, 0.0, 0.0),
+            ns: 0.0,
+            ni: 1.0,
+            d: 1.0,
+            illum: 1,
+            map_kd: None,
+            pr: None,
+            pm: None,
+            ps: None,
+            pc: None,
+            pcr: None,
+            aniso: None,
+        }
+    }
+
+    // diffuse_map is the loaded map_Kd, it replaces Kd rather than tinting it
+    pub fn to_material(&self, diffuse_map: Option<SharedTexture>) -> Material {
+        if self.ke.x > 0.0 || self.ke.y > 0.0 || self.ke.z > 0.0 {
+            return Material::DiffuseLight(DiffuseLight::new(self.ke));
+        }
+        // illum 4, 6, 7 and 9 are the transparent/refractive models
+        let transparent: bool = self.d < 1.0 || [4, 6, 7, 9].contains(&self.illum);
+        let pbr: [Option<f64>; 6] = [self.pr, self.pm, self.ps, self.pc, self.pcr, self.aniso];
+        if pbr.iter().any(|value| value.is_some()) {
+            let base_color: SharedTexture = diffuse_map.unwrap_or_else(|| texture::constant(self.kd));
+            let defaults: Principled = Principled::textured(base_color);
+            let fraction = |value: Option<f64>, default: f64| -> f64 { value.unwrap_or(default).clamp(0.0, 1.0) };
+            return Material::Principled(Principled {
+                roughness: fraction(self.pr, defaults.roughness),
+                metallic: fraction(self.pm, defaults.metallic),
+                sheen: fraction(self.ps, defaults.sheen),
+                clearcoat: fraction(self.pc, defaults.clearcoat),
+                clearcoat_gloss: 1.0 - fraction(self.pcr, 1.0 - defaults.clearcoat_gloss),
+                anisotropic: fraction(self.aniso, defaults.anisotropic),
+                transmission: match (self.d < 1.0, transparent) {
+                    (true, _) => 1.0 - self.d.max(0.0),
+                    (false, true) => 1.0,
+                    _ => 0.0,
+                },
+                ior: if self.ni > 1.0 { self.ni } else { defaults.ior },
+                ..defaults
+            });
+        }
+        if transparent {
+            let ri: f64 = if self.ni > 1.0 { self.ni } else { 1.5 };
+            return Material::Dielectric(Dielectric::new(ri));
+        }
+        if self.illum == 3 || self.illum == 5 {
+            // Blinn-Phong exponent to an approximate roughness
+            let fuzz: f64 = (2.0 / (self.ns + 2.0)).sqrt();
+            return Material::Metal(Metal::new(self.ks, fuzz));
+        }
+        match diffuse_map {
+            Some(texture) => Material::Lambertian(Lambertian::textured(texture)),
+            None => Material::Lambertian(Lambertian::new(self.kd)),
+        }
+    }
+}
+
+pub fn default_material() -> Material {
+    Material::Lambertian(Lambertian::new(Vector3::new(0.8, 0.8, 0.8)))
+}
+
+// One run of faces sharing a group name and a material
+pub struct ObjGroup {
+    pub name: String,
+    pub material_name: Option<String>,
+    pub mesh: TriangleMesh,
+}
+
+pub struct ObjModel {
+    pub groups: Vec<ObjGroup>,
+    pub materials: HashMap<String, MtlMaterial>,
+}
+
+impl ObjModel {
+    pub fn into_hittable_list(self) -> HittableList {
+        let mut list: HittableList = HittableList::new();
+        for group in self.groups {
+            list.add(group.mesh);
+        }
+        list
+    }
+}
+
+// (position, texcoord, normal) indices of one face corner, all zero based
+type FaceVertex = (usize, Option<usize>, Option<usize>);
+
+struct Segment {
+    name: String,
+    material_name: Option<String>,
+    faces: Vec<[FaceVertex; 3]>,
+}
+
+struct Parser<'a> {
+    file: &'a str,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: String) -> ObjError {
+        ObjError::Parse {
+            file: self.file.to_string(),
+            line: self.line,
+            message,
+        }
+    }
+
+    fn floats(&self, args: &[&str], min: usize, max: usize, keyword: &str) -> Result<Vec<f64>, ObjError> {
+        if args.len() < min || args.len() > max {
+            return Err(self.error(if min == max {
+                format!("'{}' expects {} values, found {}", keyword, min, args.len())
+            } else {
+                format!("'{}' expects {} to {} values, found {}", keyword, min, max, args.len())
+            }));
+        }
+        args.iter()
+            .map(|a| {
+                a.parse::<f64>()
+                    .map_err(|_| self.error(format!("invalid number '{}' in '{}'", a, keyword)))
+            })
+            .collect()
+    }
+
+    fn vector(&self, args: &[&str], keyword: &str) -> Result<Vector3, ObjError> {
+        let v: Vec<f64> = self.floats(args, 3, 3, keyword)?;
+        Ok(Vector3::new(v[0], v[1], v[2]))
+    }
+
+    fn index(&self, token: &str, count: usize, kind: &str) -> Result<usize, ObjError> {
+        let i: i64 = token
+            .parse::<i64>()
+            .map_err(|_| self.error(format!("invalid {} index '{}'", kind, token)))?;
+        // Negative indices count back from the most recent element
+        let resolved: i64 = if i < 0 { count as i64 + i } else { i - 1 };
+        if i == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(self.error(format!(
+                "{} index {} out of range ({} defined)",
+                kind, i, count
+            )));
+        }
+        Ok(resolved as usize)
+    }
+
+    fn face_vertex(&self, token: &str, counts: (usize, usize, usize)) -> Result<FaceVertex, ObjError> {
+        let parts: Vec<&str> = token.split('/').collect();
+        if parts.len() > 3 || parts[0].is_empty() {
+            return Err(self.error(format!("malformed face vertex '{}'", token)));
+        }
+        let v: usize = self.index(parts[0], counts.0, "vertex")?;
+        let vt: Option<usize> = match parts.get(1) {
+            Some(s) if !s.is_empty() => Some(self.index(s, counts.1, "texture")?),
+            _ => None,
+        };
+        let vn: Option<usize> = match parts.get(2) {
+            Some(s) if !s.is_empty() => Some(self.index(s, counts.2, "normal")?),
+            _ => None,
+        };
+        Ok((v, vt, vn))
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(i) => &line[..i],
+        None => line,
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|error| ObjError::Io {
+        file: path.display().to_string(),
+        error,
+    })
+}
+
+pub fn load_mtl(path: &Path) -> Result<Vec<MtlMaterial>, ObjError> {
+    let source: String = read_file(path)?;
+    let mut materials: Vec<MtlMaterial> = parse_mtl(&source, &path.display().to_string())?;
+    let dir: &Path = path.parent().unwrap_or_else(|| Path::new("."));
+    for material in materials.iter_mut() {
+        material.map_kd = material.map_kd.take().map(|map| dir.join(map));
+    }
+    Ok(materials)
+}
+
+pub fn parse_mtl(source: &str, file: &str) -> Result<Vec<MtlMaterial>, ObjError> {
+    let mut parser: Parser = Parser { file, line: 0 };
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+    for (n, raw) in source.lines().enumerate() {
+        parser.line = n + 1;
+        let tokens: Vec<&str> = strip_comment(raw).split_whitespace().collect();
+        if tokens.is_empty() {
+            continue;
+        }
+        let (keyword, args) = (tokens[0], &tokens[1..]);
+        if keyword == "newmtl" {
+            if args.len() != 1 {
+                return Err(parser.error("'newmtl' expects a single name".to_string()));
+            }
+            materials.push(MtlMaterial::new(args[0]));
+            continue;
+        }
+        let current: &mut MtlMaterial = match materials.last_mut() {
+            Some(m) => m,
+            None if keyword.starts_with("map_") => continue,
+            None => return Err(parser.error(format!("'{}' before any 'newmtl'", keyword))),
+        };
+        match keyword {
+            "Kd" => current.kd = parser.vector(args, keyword)?,
+            "Ks" => current.ks = parser.vector(args, keyword)?,
+            "Ke" => current.ke = parser.vector(args, keyword)?,
+            "Ns" => current.ns = parser.floats(args, 1, 1, keyword)?[0],
+            "Ni" => current.ni = parser.floats(args, 1, 1, keyword)?[0],
+            "d" => current.d = parser.floats(args, 1, 1, keyword)?[0],
+            "Tr" => current.d = 1.0 - parser.floats(args, 1, 1, keyword)?[0],
+            "Pr" => current.pr = Some(parser.floats(args, 1, 1, keyword)?[0]),
+            "Pm" => current.pm = Some(parser.floats(args, 1, 1, keyword)?[0]),
+            "Ps" => current.ps = Some(parser.floats(args, 1, 1, keyword)?[0]),
+            "Pc" => current.pc = Some(parser.floats(args, 1, 1, keyword)?[0]),
+            "Pcr" => current.pcr = Some(parser.floats(args, 1, 1, keyword)?[0]),
+            "aniso" => current.aniso = Some(parser.floats(args, 1, 1, keyword)?[0]),
+            "illum" => {
+                if args.len() != 1 {
+                    return Err(parser.error("'illum' expects a single value".to_string()));
+                }
+                current.illum = args[0]
+                    .parse::<u32>()
+                    .map_err(|_| parser.error(format!("invalid illumination model '{}'", args[0])))?;
+            }
+            // Options such as -s or -o come before the file name and are ignored
+            "map_Kd" => match args.last() {
+                Some(map) => current.map_kd = Some(PathBuf::from(map)),
+                None => return Err(parser.error("'map_Kd' expects a file name".to_string())),
+            },
+            // Other texture maps and vendor extensions are not supported yet
+            _ => {}
+        }
+    }
+    Ok(materials)
+}
+
+pub fn load_obj(path: &Path) -> Result<ObjModel, ObjError> {
+    let source: String = read_file(path)?;
+    let base_dir: &Path = path.parent().unwrap_or_else(|| Path::new("."));
+    parse_obj(&source, &path.display().to_string(), base_dir)
+}
+
+// mtllib paths are resolved against base_dir
+pub fn parse_obj(source: &str, file: &str, base_dir: &Path) -> Result<ObjModel, ObjError> {
+    let mut parser: Parser = Parser { file, line: 0 };
+    let mut positions: Vec<Vector3> = Vec::new();
+    let mut texcoords: Vec<(f64, f64)> = Vec::new();
+    let mut normals: Vec<Vector3> = Vec::new();
+    let mut materials: HashMap<String, MtlMaterial> = HashMap::new();
+    let mut segments: Vec<Segment> = vec![Segment {
+        name: "default".to_string(),
+        material_name: None,
+        faces: Vec::new(),
+    }];
+
+    for (n, raw) in source.lines().enumerate() {
+        parser.line = n + 1;
+        let tokens: Vec<&str> = strip_comment(raw).split_whitespace().collect();
+        if tokens.is_empty() {
+            continue;
+        }
+        let (keyword, args) = (tokens[0], &tokens[1..]);
+        match keyword {
+            "v" => {
+                // The optional w component is ignored
+                let v: Vec<f64> = parser.floats(args, 3, 4, keyword)?;
+                positions.push(Vector3::new(v[0], v[1], v[2]));
+            }
+            "vt" => {
+                let v: Vec<f64> = parser.floats(args, 1, 3, keyword)?;
+                texcoords.push((v[0], if v.len() > 1 { v[1] } else { 0.0 }));
+            }
+            "vn" => normals.push(parser.vector(args, keyword)?),
+            "f" => {
+                if args.len() < 3 {
+                    return Err(parser.error(format!("face needs at least 3 vertices, found {}", args.len())));
+                }
+                let counts = (positions.len(), texcoords.len(), normals.len());
+                let mut polygon: Vec<FaceVertex> = Vec::with_capacity(args.len());
+                for token in args {
+                    polygon.push(parser.face_vertex(token, counts)?);
+                }
+                // Fan triangulation, fine for the convex polygons exporters write
+                let segment: &mut Segment = segments.last_mut().unwrap();
+                for i in 1..polygon.len() - 1 {
+                    segment.faces.push([polygon[0], polygon[i], polygon[i + 1]]);
+                }
+            }
+            "g" | "o" => {
+                let name: String = if args.is_empty() { "default".to_string() } else { args.join(" ") };
+                let material_name: Option<String> = segments.last().unwrap().material_name.clone();
+                segments.push(Segment {
+                    name,
+                    material_name,
+                    faces: Vec::new(),
+                });
+            }
+            "usemtl" => {
+                if args.len() != 1 {
+                    return Err(parser.error("'usemtl' expects a single name".to_string()));
+                }
+                if !materials.contains_key(args[0]) {
+                    return Err(parser.error(format!("unknown material '{}'", args[0])));
+                }
+                let name: String = segments.last().unwrap().name.clone();
+                segments.push(Segment {
+                    name,
+                    material_name: Some(args[0].to_string()),
+                    faces: Vec::new(),
+                });
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err(parser.error("'mtllib' expects a file name".to_string()));
+                }
+                for lib in args {
+                    for material in load_mtl(&base_dir.join(lib))? {
+                        materials.insert(material.name.clone(), material);
+                    }
+                }
+            }
+            // Smoothing groups, lines, points and free-form geometry are ignored
+            _ => {}
+        }
+    }
+
+    // Materials sharing a map share the loaded texture
+    let mut textures: HashMap<PathBuf, SharedTexture> = HashMap::new();
+    let mut groups: Vec<ObjGroup> = Vec::new();
+    for s in segments.into_iter().filter(|s| !s.faces.is_empty()) {
+        let material: Material = match s.material_name {
+            Some(ref name) => {
+                let mtl: &MtlMaterial = &materials[name];
+                let diffuse_map: Option<SharedTexture> = match mtl.map_kd {
+                    Some(ref path) => {
+                        if !textures.contains_key(path) {
+                            let texture: SharedTexture = Arc::new(ImageTexture::load(path)?);
+                            textures.insert(path.clone(), texture);
+                        }
+                        Some(textures[path].clone())
+                    }
+                    None => None,
+                };
+                mtl.to_material(diffuse_map)
+            }
+            None => default_material(),
+        };
+        groups.push(ObjGroup {
+            mesh: build_mesh(&s.faces, &positions, &texcoords, &normals, material),
+            name: s.name,
+            material_name: s.material_name,
+        });
+    }
+    Ok(ObjModel { groups, materials })
+}
+
+// OBJ indexes each attribute separately, the mesh wants one index per vertex
+fn build_mesh(
+    faces: &[[FaceVertex; 3]],
+    positions: &[Vector3],
+    texcoords: &[(f64, f64)],
+    normals: &[Vector3],
+    material: Material,
+) -> TriangleMesh {
+    let has_uvs: bool = faces.iter().all(|f| f.iter().all(|v| v.1.is_some()));
+    let has_normals: bool = faces.iter().all(|f| f.iter().all(|v| v.2.is_some()));
+
+    let mut remap: HashMap<FaceVertex, usize> = HashMap::new();
+    let mut mesh_positions: Vec<Vector3> = Vec::new();
+    let mut mesh_uvs: Vec<(f64, f64)> = Vec::new();
+    let mut mesh_normals: Vec<Vector3> = Vec::new();
+    let mut indices: Vec<[usize; 3]> = Vec::with_capacity(faces.len());
+    for face in faces {
+        let mut triangle: [usize; 3] = [0; 3];
+        for (corner, vertex) in face.iter().enumerate() {
+            let key: FaceVertex = (
+                vertex.0,
+                if has_uvs { vertex.1 } else { None },
+                if has_normals { vertex.2 } else { None },
+            );
+            let next: usize = mesh_positions.len();
+            let index: usize = *remap.entry(key).or_insert(next);
+            if index == next {
+                mesh_positions.push(positions[key.0]);
+                if let Some(vt) = key.1 {
+                    mesh_uvs.push(texcoords[vt]);
+                }
+                if let Some(vn) = key.2 {
+                    mesh_normals.push(normals[vn]);
+                }
+            }
+            triangle[corner] = index;
+        }
+        indices.push(triangle);
+    }
+    TriangleMesh::new(mesh_positions, mesh_normals, mesh_uvs, indices, material)
+}
+
+#[test]
+fn test_parse_obj() {
+    let source: &str = "# a unit quad split over two groups\n\
+                        v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+                        vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+                        vn 0 0 1\n\
+                        g front\n\
+                        f 1/1/1 2/2/1 3/3/1 4/4/1\n\
+                        g back\n\
+                        f -1 -2 -3\n";
+    let model: ObjModel = parse_obj(source, "quad.obj", Path::new(".")).unwrap();
+    assert_eq!(model.groups.len(), 2);
+    assert_eq!(model.groups[0].name, "front");
+    assert_eq!(model.groups[0].mesh.triangle_count(), 2);
+    assert_eq!(model.groups[0].mesh.data().positions.len(), 4);
+    assert_eq!(model.groups[0].mesh.data().normals.len(), 4);
+    assert_eq!(model.groups[1].mesh.data().indices, vec![[0, 1, 2]]);
+    assert_eq!(model.groups[1].mesh.data().positions[0], Vector3::new(0.0, 1.0, 0.0));
+}
+
+#[test]
+fn test_parse_obj_errors() {
+    let bad_index: String = parse_obj("v 0 0 0\nv 1 0 0\nf 1 2 3\n", "bad.obj", Path::new("."))
+        .err()
+        .unwrap()
+        .to_string();
+    assert_eq!(bad_index, "bad.obj:3: vertex index 3 out of range (2 defined)");
+    let bad_number: String = parse_obj("v 0 zero 0\n", "bad.obj", Path::new("."))
+        .err()
+        .unwrap()
+        .to_string();
+    assert_eq!(bad_number, "bad.obj:1: invalid number 'zero' in 'v'");
+    let unknown: String = parse_obj("\nusemtl gold\n", "bad.obj", Path::new("."))
+        .err()
+        .unwrap()
+        .to_string();
+    assert_eq!(unknown, "bad.obj:2: unknown material 'gold'");
+}
+
+#[test]
+fn test_parse_mtl() {
+    let source: &str = "newmtl matte\nKd 0.1 0.2 0.3\nmap_Kd -s 2 2 1 wood.png\n\
+                        newmtl mirror\nKs 0.9 0.9 0.9\nNs 1000\nillum 3\n\
+                        newmtl glass\nNi 1.33\nd 0.2\nillum 4\n";
+    let materials: Vec<MtlMaterial> = parse_mtl(source, "test.mtl").unwrap();
+    assert_eq!(materials.len(), 3);
+    assert_eq!(materials[0].map_kd, Some(PathBuf::from("wood.png")));
+    assert_eq!(materials[1].map_kd, None);
+    match materials[0].to_material(None) {
+        Material::Lambertian(l) => assert_eq!(l.albedo.value(0.0, 0.0, &Vector3::new(0.0, 0.0, 0.0)), Vector3::new(0.1, 0.2, 0.3)),
+        _ => panic!("expected a lambertian"),
+    }
+    match materials[1].to_material(None) {
+        Material::Metal(_) => {}
+        _ => panic!("expected a metal"),
+    }
+    match materials[2].to_material(None) {
+        Material::Dielectric(_) => {}
+        _ => panic!("expected a dielectric"),
+    }
+    assert!(parse_mtl("Kd 1 1 1\n", "test.mtl").is_err());
+
+    // The PBR extension switches to the principled material
+    let pbr: Vec<MtlMaterial> = parse_mtl("newmtl paint\nKd 0.5 0.1 0.1\nPr 0.3\nPm 1\nPcr 0.2\nPc 0.5\nd 0.75\n", "test.mtl").unwrap();
+    assert_eq!(pbr[0].pr, Some(0.3));
+    match pbr[0].to_material(None) {
+        Material::Principled(p) => {
+            assert_eq!((p.roughness, p.metallic, p.clearcoat, p.transmission), (0.3, 1.0, 0.5, 0.25));
+            assert!((p.clearcoat_gloss - 0.8).abs() < 1e-12);
+            assert_eq!(p.base_color.value(0.0, 0.0, &Vector3::new(0.0, 0.0, 0.0)), Vector3::new(0.5, 0.1, 0.1));
+        }
+        _ => panic!("expected a principled material"),
+    }
+}
@@ -0,0 +1,45 @@
+use vector::Vector3;
+
+// Orthonormal basis, w is the axis directions are sampled around
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub u: Vector3,
+    pub v: Vector3,
+    pub w: Vector3,
+}
+
+impl Onb {
+    pub fn build_from_w(n: &Vector3) -> Onb {
+        let w: Vector3 = n.unit_vector();
+        let a: Vector3 = if w.x.abs() > 0.9 {
+            Vector3::new(0.0, 1.0, 0.0)
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let v: Vector3 = w.cross(&a).unit_vector();
+        let u: Vector3 = w.cross(&v);
+        Onb { u, v, w }
+    }
+
+    pub fn local(&self, a: f64, b: f64, c: f64) -> Vector3 {
+        a * self.u + b * self.v + c * self.w
+    }
+
+    pub fn local_vector(&self, a: &Vector3) -> Vector3 {
+        self.local(a.x, a.y, a.z)
+    }
+
+    // Inverse of local: coordinates of a world space vector in this basis
+    pub fn to_local(&self, a: &Vector3) -> Vector3 {
+        Vector3::new(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+}
+
+#[test]
+fn test_onb() {
+    let onb: Onb = Onb::build_from_w(&Vector3::new(0.0, 0.0, 2.0));
+    assert_eq!(onb.w, Vector3::new(0.0, 0.0, 1.0));
+    assert!(onb.u.dot(&onb.v).abs() < 1e-12 && onb.u.dot(&onb.w).abs() < 1e-12);
+    let a: Vector3 = Vector3::new(0.3, -0.2, 0.9);
+    assert!((onb.to_local(&onb.local_vector(&a)) - a).length() < 1e-12);
+}
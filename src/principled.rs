@@ -0,0 +1,323 @@
+use std::f64::consts::PI;
+
+use dielectric::{fresnel_dielectric, refract_about};
+use hitable::HitRecord;
+use material::{ScatterRecord, Scatterable};
+use microfacet::{incident_frame, reflect_about, refraction_half_vector, Ggx};
+use onb::Onb;
+use random;
+use ray::Ray;
+use texture::{self, SharedTexture};
+use vector::Vector3;
+
+// Disney's principled BSDF ("Physically Based Shading at Disney", Burley 2012,
+// with the transmission of the 2015 notes). Every knob other than base_color
+// and ior is in [0, 1]. The lobes are sampled one at a time and weighted
+// against the density of all of them.
+#[derive(Clone, Debug)]
+pub struct Principled {
+    pub base_color: SharedTexture,
+    pub metallic: f64,
+    pub roughness: f64,
+    // Reflectance of the dielectric base, 0.5 is 4%
+    pub specular: f64,
+    pub specular_tint: f64,
+    pub sheen: f64,
+    pub sheen_tint: f64,
+    pub clearcoat: f64,
+    pub clearcoat_gloss: f64,
+    pub transmission: f64,
+    pub ior: f64,
+    // Flattens the diffuse lobe like light scattered under the surface
+    pub subsurface: f64,
+    pub anisotropic: f64,
+}
+
+impl Principled {
+    pub fn new(base_color: Vector3) -> Principled {
+        Principled::textured(texture::constant(base_color))
+    }
+
+    // Defaults of the reference implementation
+    pub fn textured(base_color: SharedTexture) -> Principled {
+        Principled {
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+            ior: 1.5,
+            subsurface: 0.0,
+            anisotropic: 0.0,
+        }
+    }
+
+    fn distribution(&self) -> Ggx {
+        let aspect: f64 = (1.0 - 0.9 * self.anisotropic).sqrt();
+        let alpha: f64 = self.roughness * self.roughness;
+        Ggx::new((alpha / aspect).max(1e-3), (alpha * aspect).max(1e-3))
+    }
+
+    fn clearcoat_alpha(&self) -> f64 {
+        0.1 + (0.001 - 0.1) * self.clearcoat_gloss
+    }
+
+    // Probabilities of sampling the diffuse, specular, clearcoat and
+    // transmission lobes. The coat is only on the outside.
+    fn lobe_weights(&self, front: bool) -> [f64; 4] {
+        let diffuse: f64 = (1.0 - self.metallic) * (1.0 - self.transmission);
+        let specular: f64 = 1.0 - 0.5 * (1.0 - self.metallic);
+        let clearcoat: f64 = if front { 0.25 * self.clearcoat } else { 0.0 };
+        let transmission: f64 = (1.0 - self.metallic) * self.transmission;
+        let total: f64 = diffuse + specular + clearcoat + transmission;
+        [diffuse / total, specular / total, clearcoat / total, transmission / total]
+    }
+
+    // Index of the far side relative to the side wo is on
+    fn eta(&self, front: bool) -> f64 {
+        if front {
+            self.ior
+        } else {
+            1.0 / self.ior
+        }
+    }
+
+    // BSDF times |cos| of wi for directions in the shading frame, wo.z > 0.
+    // front tells whether wo is outside the object.
+    pub fn eval_local(&self, base: &Vector3, front: bool, wo: &Vector3, wi: &Vector3) -> Vector3 {
+        let black: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return black;
+        }
+        let dielectric: f64 = 1.0 - self.metallic;
+        let eta: f64 = self.eta(front);
+        let distribution: Ggx = self.distribution();
+        if wi.z() < 0.0 {
+            // Refraction, Walter et al. without the 1 / eta^2 radiance scaling
+            // so that, as in Dielectric, what goes into a closed object comes out
+            let transmission: f64 = dielectric * self.transmission;
+            let m: Vector3 = match refraction_half_vector(wo, wi, eta) {
+                Some(m) if transmission > 0.0 => m,
+                _ => return black,
+            };
+            let (o_m, i_m): (f64, f64) = (wo.dot(&m), wi.dot(&m));
+            let denominator: f64 = o_m + eta * i_m;
+            let fresnel: f64 = fresnel_dielectric(o_m, eta);
+            let value: f64 = (1.0 - fresnel) * distribution.d(&m) * distribution.g(wo, wi) * eta * eta * i_m.abs() * o_m
+                / (wo.z() * denominator * denominator);
+            // Light crosses two interfaces, so each one takes the square root of the color
+            return sqrt_color(base) * (transmission * value);
+        }
+
+        let h: Vector3 = (*wo + *wi).unit_vector();
+        let (cos_o, cos_i, cos_d): (f64, f64, f64) = (wo.z(), wi.z(), wi.dot(&h));
+        let tint: Vector3 = tint(base);
+        let mut value: Vector3 = black;
+
+        let diffuse_weight: f64 = dielectric * (1.0 - self.transmission);
+        if diffuse_weight > 0.0 {
+            let (fl, fv): (f64, f64) = (schlick_weight(cos_i), schlick_weight(cos_o));
+            let fd90: f64 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let diffuse: f64 = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+            // Hanrahan-Krueger inspired flattening
+            let fss90: f64 = self.roughness * cos_d * cos_d;
+            let fss: f64 = (1.0 + (fss90 - 1.0) * fl) * (1.0 + (fss90 - 1.0) * fv);
+            let ss: f64 = 1.25 * (fss * (1.0 / (cos_i + cos_o) - 0.5) + 0.5);
+            let diffuse: f64 = diffuse + (ss - diffuse) * self.subsurface;
+            value = value + *base * (diffuse_weight * diffuse / PI);
+            if front && self.sheen > 0.0 {
+                let sheen: Vector3 = lerp(Vector3::new(1.0, 1.0, 1.0), tint, self.sheen_tint);
+                value = value + sheen * (diffuse_weight * self.sheen * schlick_weight(cos_d));
+            }
+        }
+
+        // The metal reflects its base color, the opaque dielectric a tinted
+        // specular level and the transmissive part follows the exact Fresnel
+        let specular0: Vector3 = lerp(Vector3::new(1.0, 1.0, 1.0), tint, self.specular_tint) * (0.08 * self.specular);
+        let fresnel: Vector3 = schlick(&specular0, cos_d) * diffuse_weight
+            + schlick(base, cos_d) * self.metallic
+            + Vector3::new(1.0, 1.0, 1.0) * (dielectric * self.transmission * fresnel_dielectric(cos_d, eta));
+        value = value + fresnel * (distribution.d(&h) * distribution.g(wo, wi) / (4.0 * cos_o * cos_i));
+
+        if front && self.clearcoat > 0.0 {
+            let coat: Ggx = Ggx::new(0.25, 0.25);
+            let fresnel: f64 = 0.04 + 0.96 * schlick_weight(cos_d);
+            let coat: f64 = 0.25 * self.clearcoat * gtr1(h.z(), self.clearcoat_alpha()) * fresnel * coat.g(wo, wi)
+                / (4.0 * cos_o * cos_i);
+            value = value + Vector3::new(coat, coat, coat);
+        }
+        value * cos_i
+    }
+
+    // Solid angle density of sample_local() returning wi
+    pub fn pdf_local(&self, front: bool, wo: &Vector3, wi: &Vector3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return 0.0;
+        }
+        let [diffuse, specular, clearcoat, transmission]: [f64; 4] = self.lobe_weights(front);
+        let distribution: Ggx = self.distribution();
+        if wi.z() < 0.0 {
+            let eta: f64 = self.eta(front);
+            return match refraction_half_vector(wo, wi, eta) {
+                Some(m) if transmission > 0.0 => {
+                    let denominator: f64 = wo.dot(&m) + eta * wi.dot(&m);
+                    transmission * distribution.pdf_visible(wo, &m) * eta * eta * wi.dot(&m).abs() / (denominator * denominator)
+                }
+                _ => 0.0,
+            };
+        }
+        let h: Vector3 = (*wo + *wi).unit_vector();
+        let mut pdf: f64 = diffuse * wi.z() / PI + specular * distribution.pdf_visible(wo, &h) / (4.0 * wo.dot(&h));
+        if clearcoat > 0.0 {
+            pdf += clearcoat * gtr1(h.z(), self.clearcoat_alpha()) * h.z() / (4.0 * wo.dot(&h));
+        }
+        pdf
+    }
+
+    // A direction from one of the lobes and its weight eval / pdf
+    pub fn sample_local(&self, base: &Vector3, front: bool, wo: &Vector3, u: [f64; 3]) -> Option<(Vector3, Vector3)> {
+        let [diffuse, specular, clearcoat, _]: [f64; 4] = self.lobe_weights(front);
+        let wi: Vector3 = if u[0] < diffuse {
+            let r: f64 = u[1].sqrt();
+            let phi: f64 = 2.0 * PI * u[2];
+            Vector3::new(r * phi.cos(), r * phi.sin(), (1.0 - u[1]).sqrt())
+        } else if u[0] < diffuse + specular {
+            reflect_about(wo, &self.distribution().sample_visible(wo, u[1], u[2]))
+        } else if u[0] < diffuse + specular + clearcoat {
+            let alpha2: f64 = self.clearcoat_alpha().powi(2);
+            let cos_theta: f64 = ((1.0 - alpha2.powf(1.0 - u[1])) / (1.0 - alpha2)).max(0.0).sqrt();
+            let sin_theta: f64 = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi: f64 = 2.0 * PI * u[2];
+            let h: Vector3 = Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+            reflect_about(wo, &h)
+        } else {
+            let m: Vector3 = self.distribution().sample_visible(wo, u[1], u[2]);
+            let wi: Vector3 = refract_about(wo, &m, self.eta(front))?;
+            if wi.z() >= 0.0 {
+                return None;
+            }
+            wi
+        };
+        let pdf: f64 = self.pdf_local(front, wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some((wi, self.eval_local(base, front, wo, &wi) / pdf))
+    }
+}
+
+// Generalised Trowbridge-Reitz with gamma = 1, the long tailed clearcoat distribution
+fn gtr1(cos_h: f64, alpha: f64) -> f64 {
+    let alpha2: f64 = alpha * alpha;
+    (alpha2 - 1.0) / (PI * alpha2.ln() * (1.0 + (alpha2 - 1.0) * cos_h * cos_h))
+}
+
+fn schlick_weight(cos: f64) -> f64 {
+    (1.0 - cos.clamp(0.0, 1.0)).powi(5)
+}
+
+fn schlick(f0: &Vector3, cos: f64) -> Vector3 {
+    lerp(*f0, Vector3::new(1.0, 1.0, 1.0), schlick_weight(cos))
+}
+
+fn lerp(a: Vector3, b: Vector3, t: f64) -> Vector3 {
+    a * (1.0 - t) + b * t
+}
+
+// Hue and saturation of the color at unit luminance
+fn tint(color: &Vector3) -> Vector3 {
+    let luminance: f64 = 0.3 * color.x() + 0.6 * color.y() + 0.1 * color.z();
+    if luminance > 0.0 {
+        *color / luminance
+    } else {
+        Vector3::new(1.0, 1.0, 1.0)
+    }
+}
+
+fn sqrt_color(color: &Vector3) -> Vector3 {
+    Vector3::new(color.x().max(0.0).sqrt(), color.y().max(0.0).sqrt(), color.z().max(0.0).sqrt())
+}
+
+impl Scatterable for Principled {
+    // The side the ray arrives from decides the direction of refraction
+    fn sample(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let (frame, front): (Onb, bool) = incident_frame(&ray_in.direction(), &rec.normal());
+        let wo: Vector3 = frame.to_local(&-ray_in.direction().unit_vector());
+        let base: Vector3 = self.base_color.filtered(rec.u, rec.v, &rec.p(), rec.footprint);
+        let u: [f64; 3] = [random::random_f64(), random::random_f64(), random::random_f64()];
+        let (wi, weight): (Vector3, Vector3) = self.sample_local(&base, front, &wo, u)?;
+        let scattered: Ray = Ray::new(rec.p(), frame.local_vector(&wi)).with_time(ray_in.time());
+        Some(ScatterRecord::new(scattered, weight, self.pdf_local(front, &wo, &wi)))
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vector3) -> Vector3 {
+        let (frame, front): (Onb, bool) = incident_frame(&ray_in.direction(), &rec.normal());
+        let wo: Vector3 = frame.to_local(&-ray_in.direction().unit_vector());
+        let wi: Vector3 = frame.to_local(&direction.unit_vector());
+        self.eval_local(&self.base_color.filtered(rec.u, rec.v, &rec.p(), rec.footprint), front, &wo, &wi)
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f64 {
+        let (frame, front): (Onb, bool) = incident_frame(&ray_in.direction(), &rec.normal());
+        let wo: Vector3 = frame.to_local(&-ray_in.direction().unit_vector());
+        self.pdf_local(front, &wo, &frame.to_local(&direction.unit_vector()))
+    }
+}
+
+#[test]
+fn test_principled_sampling() {
+    let white: Vector3 = Vector3::new(1.0, 1.0, 1.0);
+    let wo: Vector3 = Vector3::new(0.5, -0.2, 0.7).unit_vector();
+    let mut plastic: Principled = Principled::new(white);
+    plastic.roughness = 0.4;
+    plastic.sheen = 1.0;
+    plastic.clearcoat = 1.0;
+    plastic.subsurface = 0.5;
+    let mut metal: Principled = Principled::new(Vector3::new(0.9, 0.6, 0.3));
+    metal.anisotropic = 0.6;
+    metal.metallic = 1.0;
+    metal.roughness = 0.3;
+    let mut glass: Principled = Principled::new(white);
+    glass.transmission = 1.0;
+    glass.roughness = 0.2;
+
+    random::seed(7);
+    for (material, front) in [(&plastic, true), (&metal, true), (&glass, true), (&glass, false)].iter() {
+        // Samples stay on the surface's side unless they are transmitted, their
+        // weight is eval / pdf, and the mean weight is the (bounded) albedo
+        let samples: usize = 20000;
+        let mut albedo: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+        for _ in 0..samples {
+            let u: [f64; 3] = [random::random_f64(), random::random_f64(), random::random_f64()];
+            if let Some((wi, weight)) = material.sample_local(&white, *front, &wo, u) {
+                assert!(wi.z() > 0.0 || material.transmission > 0.0);
+                let pdf: f64 = material.pdf_local(*front, &wo, &wi);
+                let expected: Vector3 = material.eval_local(&white, *front, &wo, &wi) / pdf;
+                assert!((expected - weight).length() < 1e-9 * weight.length().max(1.0));
+                albedo = albedo + weight / samples as f64;
+            }
+        }
+        assert!(albedo.x() > 0.5 && albedo.x() < 1.3, "{:?} {}", albedo, front);
+    }
+
+    // The mixture density integrates to at most one over the sphere
+    let steps: usize = 300;
+    for material in [&plastic, &metal, &glass].iter() {
+        let mut integral: f64 = 0.0;
+        for i in 0..steps {
+            for j in 0..steps {
+                let cos_theta: f64 = -1.0 + 2.0 * (i as f64 + 0.5) / steps as f64;
+                let phi: f64 = 2.0 * PI * (j as f64 + 0.5) / steps as f64;
+                let sin_theta: f64 = (1.0 - cos_theta * cos_theta).sqrt();
+                let wi: Vector3 = Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                integral += material.pdf_local(true, &wo, &wi) * 4.0 * PI / (steps * steps) as f64;
+            }
+        }
+        assert!(integral > 0.85 && integral < 1.02, "{}", integral);
+    }
+}
@@ -0,0 +1,320 @@
+use aabb::Aabb;
+use hitable::{HitRecord, Hittable};
+use material::Material;
+use random;
+use ray::Ray;
+use vector::Vector3;
+
+const PARALLEL_EPSILON: f64 = 1e-12;
+// Flat quads get a box this thick so the slab test never degenerates
+const BOX_PADDING: f64 = 1e-4;
+
+// Parallelogram spanned by the edges u and v from the corner q. Texture
+// coordinates run from 0 to 1 along each edge and the normal is u x v,
+// unless flipped.
+#[derive(Clone, Debug)]
+pub struct Quad {
+    q: Vector3,
+    u: Vector3,
+    v: Vector3,
+    normal: Vector3,
+    // Plane offset, normal . p == d for points on the quad
+    d: f64,
+    // Maps a planar offset from q to its (u, v) coordinates
+    w: Vector3,
+    area: f64,
+    material: Material,
+}
+
+impl Quad {
+    pub fn new(q: Vector3, u: Vector3, v: Vector3, material: Material) -> Quad {
+        let n: Vector3 = u.cross(&v);
+        let normal: Vector3 = n.unit_vector();
+        Quad {
+            q,
+            u,
+            v,
+            normal,
+            d: normal.dot(&q),
+            w: n / n.norm(),
+            area: n.length(),
+            material,
+        }
+    }
+
+    // Same surface facing the other way
+    pub fn flipped(mut self) -> Quad {
+        self.normal = -self.normal;
+        self.d = -self.d;
+        self
+    }
+
+    pub fn normal(&self) -> Vector3 {
+        self.normal
+    }
+
+    pub fn area(&self) -> f64 {
+        self.area
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let denominator: f64 = self.normal.dot(&ray.direction());
+        if denominator.abs() < PARALLEL_EPSILON {
+            return false;
+        }
+        let t: f64 = (self.d - self.normal.dot(&ray.origin())) / denominator;
+        if t <= t_min || t >= t_max {
+            return false;
+        }
+        let p: Vector3 = ray.point_at_parameter(t);
+        let planar: Vector3 = p - self.q;
+        let alpha: f64 = self.w.dot(&planar.cross(&self.v));
+        let beta: f64 = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+        rec.t = t;
+        rec.p = p;
+        rec.normal = self.normal;
+        rec.u = alpha;
+        rec.v = beta;
+        rec.footprint = ray.spread() * t * ray.direction().length() / self.area.sqrt();
+        rec.material = self.material.clone();
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        let pad: Vector3 = Vector3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+        let bbox: Aabb = Aabb::new(self.q, self.q)
+            .include_point(&(self.q + self.u))
+            .include_point(&(self.q + self.v))
+            .include_point(&(self.q + self.u + self.v));
+        *output_box = Aabb::new(bbox.min() - pad, bbox.max() + pad);
+        true
+    }
+
+    // Area sampling converted to solid angle: distance^2 / (cos * area)
+    fn pdf_value(&self, o: &Vector3, v: &Vector3) -> f64 {
+        let mut rec: HitRecord = HitRecord::new();
+        if self.area <= 0.0 || !self.hit(&Ray::new(*o, *v), 0.001, f64::MAX, &mut rec) {
+            return 0.0;
+        }
+        let distance_squared: f64 = rec.t * rec.t * v.norm();
+        let cosine: f64 = (v.dot(&self.normal) / v.length()).abs();
+        if cosine <= 0.0 {
+            0.0
+        } else {
+            distance_squared / (cosine * self.area)
+        }
+    }
+
+    fn random(&self, o: &Vector3) -> Vector3 {
+        let point: Vector3 = self.q + random::random_f64() * self.u + random::random_f64() * self.v;
+        point - *o
+    }
+}
+
+// Rectangle in the plane z = k facing +z, with u along x and v along y
+#[derive(Clone, Debug)]
+pub struct XYRect {
+    quad: Quad,
+}
+
+impl XYRect {
+    pub fn new(x0: f64, x1: f64, y0: f64, y1: f64, k: f64, material: Material) -> XYRect {
+        XYRect {
+            quad: Quad::new(
+                Vector3::new(x0, y0, k),
+                Vector3::new(x1 - x0, 0.0, 0.0),
+                Vector3::new(0.0, y1 - y0, 0.0),
+                material,
+            ),
+        }
+    }
+
+    pub fn flipped(self) -> XYRect {
+        XYRect {
+            quad: self.quad.flipped(),
+        }
+    }
+}
+
+// Rectangle in the plane y = k facing +y, with u along x and v along z
+#[derive(Clone, Debug)]
+pub struct XZRect {
+    quad: Quad,
+}
+
+impl XZRect {
+    pub fn new(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, material: Material) -> XZRect {
+        // x cross z points down, flip it to face +y like the other two
+        XZRect {
+            quad: Quad::new(
+                Vector3::new(x0, k, z0),
+                Vector3::new(x1 - x0, 0.0, 0.0),
+                Vector3::new(0.0, 0.0, z1 - z0),
+                material,
+            )
+            .flipped(),
+        }
+    }
+
+    pub fn flipped(self) -> XZRect {
+        XZRect {
+            quad: self.quad.flipped(),
+        }
+    }
+}
+
+// Rectangle in the plane x = k facing +x, with u along y and v along z
+#[derive(Clone, Debug)]
+pub struct YZRect {
+    quad: Quad,
+}
+
+impl YZRect {
+    pub fn new(y0: f64, y1: f64, z0: f64, z1: f64, k: f64, material: Material) -> YZRect {
+        YZRect {
+            quad: Quad::new(
+                Vector3::new(k, y0, z0),
+                Vector3::new(0.0, y1 - y0, 0.0),
+                Vector3::new(0.0, 0.0, z1 - z0),
+                material,
+            ),
+        }
+    }
+
+    pub fn flipped(self) -> YZRect {
+        YZRect {
+            quad: self.quad.flipped(),
+        }
+    }
+}
+
+impl From<XYRect> for Quad {
+    fn from(rect: XYRect) -> Quad {
+        rect.quad
+    }
+}
+
+impl From<XZRect> for Quad {
+    fn from(rect: XZRect) -> Quad {
+        rect.quad
+    }
+}
+
+impl From<YZRect> for Quad {
+    fn from(rect: YZRect) -> Quad {
+        rect.quad
+    }
+}
+
+impl Hittable for XYRect {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.quad.hit(ray, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        self.quad.bounding_box(output_box)
+    }
+
+    fn pdf_value(&self, o: &Vector3, v: &Vector3) -> f64 {
+        self.quad.pdf_value(o, v)
+    }
+
+    fn random(&self, o: &Vector3) -> Vector3 {
+        self.quad.random(o)
+    }
+}
+
+impl Hittable for XZRect {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.quad.hit(ray, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        self.quad.bounding_box(output_box)
+    }
+
+    fn pdf_value(&self, o: &Vector3, v: &Vector3) -> f64 {
+        self.quad.pdf_value(o, v)
+    }
+
+    fn random(&self, o: &Vector3) -> Vector3 {
+        self.quad.random(o)
+    }
+}
+
+impl Hittable for YZRect {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.quad.hit(ray, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        self.quad.bounding_box(output_box)
+    }
+
+    fn pdf_value(&self, o: &Vector3, v: &Vector3) -> f64 {
+        self.quad.pdf_value(o, v)
+    }
+
+    fn random(&self, o: &Vector3) -> Vector3 {
+        self.quad.random(o)
+    }
+}
+
+#[test]
+fn test_quad_hit() {
+    use lambertian::Lambertian;
+
+    let material: Material = Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+    // Skewed: a parallelogram, not a rectangle
+    let quad: Quad = Quad::new(
+        Vector3::new(0.0, 0.0, -2.0),
+        Vector3::new(2.0, 0.0, 0.0),
+        Vector3::new(1.0, 1.0, 0.0),
+        material,
+    );
+    let mut rec: HitRecord = HitRecord::new();
+    let ray: Ray = Ray::new(Vector3::new(1.5, 0.5, 0.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(quad.hit(&ray, 0.001, f64::MAX, &mut rec));
+    assert_eq!(rec.t, 2.0);
+    assert_eq!(rec.normal(), Vector3::new(0.0, 0.0, 1.0));
+    assert_eq!(rec.uv(), (0.5, 0.5));
+    assert_eq!(quad.area(), 2.0);
+
+    let outside: Ray = Ray::new(Vector3::new(0.2, 0.5, 0.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(!quad.hit(&outside, 0.001, f64::MAX, &mut rec));
+    let parallel: Ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    assert!(!quad.hit(&parallel, 0.001, f64::MAX, &mut rec));
+    assert_eq!(quad.flipped().normal(), Vector3::new(0.0, 0.0, -1.0));
+}
+
+#[test]
+fn test_rects() {
+    use lambertian::Lambertian;
+
+    let material: Material = Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+    let xy: Quad = XYRect::new(0.0, 1.0, 0.0, 2.0, 3.0, material.clone()).into();
+    let xz: Quad = XZRect::new(0.0, 1.0, 0.0, 2.0, 3.0, material.clone()).into();
+    let yz: Quad = YZRect::new(0.0, 1.0, 0.0, 2.0, 3.0, material.clone()).into();
+    assert_eq!(xy.normal(), Vector3::new(0.0, 0.0, 1.0));
+    assert_eq!(xz.normal(), Vector3::new(0.0, 1.0, 0.0));
+    assert_eq!(yz.normal(), Vector3::new(1.0, 0.0, 0.0));
+
+    // A light facing down, sampled from below
+    let light: XZRect = XZRect::new(-1.0, 1.0, -1.0, 1.0, 2.0, material).flipped();
+    let origin: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+    let mut rec: HitRecord = HitRecord::new();
+    assert!(light.hit(&Ray::new(origin, Vector3::new(0.0, 1.0, 0.0)), 0.001, f64::MAX, &mut rec));
+    assert_eq!(rec.normal(), Vector3::new(0.0, -1.0, 0.0));
+    assert_eq!(rec.uv(), (0.5, 0.5));
+    assert_eq!(light.pdf_value(&origin, &Vector3::new(0.0, 1.0, 0.0)), 1.0);
+    for _ in 0..100 {
+        let v: Vector3 = light.random(&origin);
+        assert!((v.y() - 2.0).abs() < 1e-12 && v.x().abs() <= 1.0 && v.z().abs() <= 1.0);
+        assert!(light.pdf_value(&origin, &v) > 0.0);
+    }
+}
@@ -0,0 +1,74 @@
+use std::cell::RefCell;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+// Every thread owns a generator that the renderer reseeds per tile, so the
+// random sequence a pixel sees does not depend on which thread renders it.
+thread_local! {
+    static RNG: RefCell<XorShiftRng> = RefCell::new(generator(0));
+}
+
+// SplitMix64, turns any seed (including 0) into well mixed generator state
+fn split_mix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z: u64 = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// Standalone generator for things that need their own reproducible sequence
+pub fn generator(seed: u64) -> XorShiftRng {
+    let mut state: u64 = seed;
+    let a: u64 = split_mix(&mut state);
+    let b: u64 = split_mix(&mut state);
+    let mut words: [u32; 4] = [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32];
+    // xorshift must not start from the all zero state
+    if words == [0; 4] {
+        words[0] = 1;
+    }
+    XorShiftRng::from_seed(words)
+}
+
+// Derives an independent seed for a sub-stream, e.g. one tile of an image
+pub fn derive_seed(seed: u64, stream: u64) -> u64 {
+    let mut state: u64 = seed ^ stream.wrapping_mul(0xd6e8_feb8_6659_fd93);
+    split_mix(&mut state)
+}
+
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = generator(seed));
+}
+
+// Handle to the calling thread's generator, usable anywhere a rand::Rng is
+#[derive(Clone, Copy, Debug)]
+pub struct ThreadRng;
+
+pub fn thread_rng() -> ThreadRng {
+    ThreadRng
+}
+
+impl Rng for ThreadRng {
+    fn next_u32(&mut self) -> u32 {
+        RNG.with(|rng| rng.borrow_mut().next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        RNG.with(|rng| rng.borrow_mut().next_u64())
+    }
+}
+
+pub fn random_f64() -> f64 {
+    thread_rng().gen::<f64>()
+}
+
+#[test]
+fn test_seed_repeats() {
+    seed(42);
+    let first: Vec<f64> = (0..8).map(|_| random_f64()).collect();
+    seed(42);
+    let second: Vec<f64> = (0..8).map(|_| random_f64()).collect();
+    assert_eq!(first, second);
+    assert!(first.iter().all(|x| *x >= 0.0 && *x < 1.0));
+    assert_ne!(derive_seed(42, 0), derive_seed(42, 1));
+}
@@ -4,6 +4,11 @@ use vector::Vector3;
 pub struct Ray {
     pub a: Vector3,
     pub b: Vector3,
+    // Angle in radians the ray stands for, camera rays cover a pixel.
+    // Textures use it to pick a mipmap level, 0 is a point sample.
+    pub spread: f64,
+    // Moment within the camera shutter the ray was cast at
+    pub time: f64,
 }
 
 impl Ray {
@@ -19,8 +24,26 @@ impl Ray {
         self.origin() + t * self.direction()
     }
 
+    pub fn spread(&self) -> f64 {
+        self.spread
+    }
+
     pub fn new(a: Vector3, b: Vector3) -> Ray {
-        Ray { a, b }
+        Ray { a, b, spread: 0.0, time: 0.0 }
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn with_time(mut self, time: f64) -> Ray {
+        self.time = time;
+        self
+    }
+
+    pub fn with_spread(mut self, spread: f64) -> Ray {
+        self.spread = spread;
+        self
     }
 }
 
@@ -0,0 +1,511 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use camera::Camera;
+use environment::Environment;
+use hitable::{HitRecord, Hittable};
+use hitable_list::HittableList;
+use light::{LightList, LightSample};
+use material::{ScatterRecord, Scatterable};
+use random;
+use ray::Ray;
+use scene::RenderSettings;
+use vector::Vector3;
+
+pub const TILE_SIZE: usize = 16;
+
+// Linear radiance per pixel, row 0 is the top of the image
+#[derive(Clone, Debug, PartialEq)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vector3>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Vector3::new(0.0, 0.0, 0.0); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Vector3 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Vector3) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    pub fn pixels(&self) -> &[Vector3] {
+        &self.pixels
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Tile {
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+}
+
+fn tiles(width: usize, height: usize) -> Vec<Tile> {
+    let mut tiles: Vec<Tile> = Vec::new();
+    for y0 in (0..height).step_by(TILE_SIZE) {
+        for x0 in (0..width).step_by(TILE_SIZE) {
+            tiles.push(Tile {
+                x0,
+                y0,
+                x1: (x0 + TILE_SIZE).min(width),
+                y1: (y0 + TILE_SIZE).min(height),
+            });
+        }
+    }
+    tiles
+}
+
+pub fn color(ray: &Ray, world: &dyn Hittable, background: &dyn Environment, depth: i32, max_depth: i32) -> Vector3 {
+    let mut rec: HitRecord = HitRecord::new();
+    if world.hit(ray, 0.001, f64::MAX, &mut rec) {
+        let emitted: Vector3 = rec.material().emitted(rec.u, rec.v, &rec.p());
+        match rec.material().sample(ray, &rec) {
+            Some(sample) if depth < max_depth => {
+                emitted + sample.weight * color(&sample.ray, world, background, depth + 1, max_depth)
+            }
+            _ => emitted,
+        }
+    } else {
+        background.radiance(&ray.direction())
+    }
+}
+
+pub fn power_heuristic(pdf_a: f64, pdf_b: f64) -> f64 {
+    let a2: f64 = pdf_a * pdf_a;
+    let b2: f64 = pdf_b * pdf_b;
+    if a2 + b2 == 0.0 {
+        0.0
+    } else {
+        a2 / (a2 + b2)
+    }
+}
+
+// Direct light from every punctual light at a hit, they can only be reached
+// by shadow rays
+fn punctual_light(ray: &Ray, rec: &HitRecord, world: &dyn Hittable, punctual: &LightList) -> Vector3 {
+    let mut total: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+    for light in punctual {
+        let sample: LightSample = match light.sample(&rec.p()) {
+            Some(sample) => sample,
+            None => continue,
+        };
+        let bsdf: Vector3 = rec.material().eval(ray, rec, &sample.direction);
+        if bsdf == Vector3::new(0.0, 0.0, 0.0) {
+            continue;
+        }
+        let shadow: Ray = Ray::new(rec.p(), sample.direction).with_time(ray.time());
+        let t_max: f64 = if sample.distance < f64::MAX {
+            sample.distance * (1.0 - 1e-6)
+        } else {
+            f64::MAX
+        };
+        let mut blocker: HitRecord = HitRecord::new();
+        if !world.hit_surface(&shadow, 0.001, t_max, &mut blocker) {
+            total = total + world.transmittance(&shadow, 0.001, t_max) * bsdf * sample.irradiance;
+        }
+    }
+    total
+}
+
+// Path tracer with next event estimation: every non-specular hit also samples
+// a direction towards the lights or an importance sampled background and
+// casts a shadow ray, and both strategies are weighted with the power
+// heuristic. Punctual lights are all sampled at every such hit. bsdf_pdf is
+// the density the incoming ray was sampled with, None for camera rays and
+// delta bounces.
+#[allow(clippy::too_many_arguments)]
+pub fn color_mis(
+    ray: &Ray,
+    world: &dyn Hittable,
+    lights: &HittableList,
+    punctual: &LightList,
+    background: &dyn Environment,
+    depth: i32,
+    max_depth: i32,
+    bsdf_pdf: Option<f64>,
+) -> Vector3 {
+    // Chance of sampling the background rather than the lights
+    let env_chance: f64 = match (background.importance_sampled(), lights.is_empty()) {
+        (false, _) => 0.0,
+        (true, true) => 1.0,
+        (true, false) => 0.5,
+    };
+    let light_pdf = |origin: &Vector3, direction: &Vector3| -> f64 {
+        let mut pdf: f64 = 0.0;
+        if env_chance < 1.0 {
+            pdf += (1.0 - env_chance) * lights.pdf_value(origin, direction);
+        }
+        if env_chance > 0.0 {
+            pdf += env_chance * background.pdf_value(direction);
+        }
+        pdf
+    };
+
+    let mut rec: HitRecord = HitRecord::new();
+    if !world.hit(ray, 0.001, f64::MAX, &mut rec) {
+        let radiance: Vector3 = background.radiance(&ray.direction());
+        return match bsdf_pdf {
+            Some(pdf) if env_chance > 0.0 => {
+                power_heuristic(pdf, env_chance * background.pdf_value(&ray.direction())) * radiance
+            }
+            _ => radiance,
+        };
+    }
+    let mut emitted: Vector3 = rec.material().emitted(rec.u, rec.v, &rec.p());
+    if let Some(pdf) = bsdf_pdf {
+        // This light may also have been reached by the shadow ray of the previous hit
+        emitted = power_heuristic(pdf, light_pdf(&ray.origin(), &ray.direction())) * emitted;
+    }
+
+    if depth >= max_depth {
+        return emitted;
+    }
+    // Light sampling doesn't depend on the sampled direction being usable
+    let sample: Option<ScatterRecord> = rec.material().sample(ray, &rec);
+    if let Some(ref sample) = sample {
+        if sample.delta {
+            let bounce: Vector3 =
+                color_mis(&sample.ray, world, lights, punctual, background, depth + 1, max_depth, None);
+            return emitted + sample.weight * bounce;
+        }
+    }
+
+    let mut direct: Vector3 = punctual_light(ray, &rec, world, punctual);
+    let toward_background: bool = env_chance >= 1.0 || (env_chance > 0.0 && random::random_f64() < env_chance);
+    let direction: Vector3 = if toward_background {
+        background.random()
+    } else {
+        lights.random(&rec.p())
+    };
+    let to_light: Ray = Ray::new(rec.p(), direction).with_time(ray.time());
+    let pdf: f64 = light_pdf(&to_light.origin(), &to_light.direction());
+    if pdf > 0.0 {
+        let mut light_rec: HitRecord = HitRecord::new();
+        let bsdf: Vector3 = rec.material().eval(ray, &rec, &to_light.direction());
+        let bsdf_light_pdf: f64 = rec.material().pdf(ray, &rec, &to_light.direction());
+        if bsdf != Vector3::new(0.0, 0.0, 0.0) {
+            // Whatever the shadow ray reaches is weighted with the density of
+            // both light strategies, the background only counts when sampled
+            let (mut light, t_max): (Vector3, f64) = if world.hit_surface(&to_light, 0.001, f64::MAX, &mut light_rec) {
+                (light_rec.material().emitted(light_rec.u, light_rec.v, &light_rec.p()), light_rec.t)
+            } else if env_chance > 0.0 {
+                (background.radiance(&to_light.direction()), f64::MAX)
+            } else {
+                (Vector3::new(0.0, 0.0, 0.0), f64::MAX)
+            };
+            // Media between here and the light dim it rather than block it
+            if light != Vector3::new(0.0, 0.0, 0.0) {
+                light = world.transmittance(&to_light, 0.001, t_max) * light;
+            }
+            let weight: f64 = power_heuristic(pdf, bsdf_light_pdf) / pdf;
+            direct = direct + weight * bsdf * light;
+        }
+    }
+
+    match sample {
+        Some(sample) => {
+            let bounce: Vector3 =
+                color_mis(&sample.ray, world, lights, punctual, background, depth + 1, max_depth, Some(sample.pdf));
+            emitted + direct + sample.weight * bounce
+        }
+        None => emitted + direct,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn render_tile<H: Hittable>(
+    world: &H,
+    lights: &HittableList,
+    punctual: &LightList,
+    camera: &Camera,
+    background: &(dyn Environment + Sync),
+    settings: &RenderSettings,
+    index: usize,
+    tile: &Tile,
+) -> Vec<Vector3> {
+    // Seeding per tile makes the image independent of the thread count
+    random::seed(random::derive_seed(settings.seed, index as u64));
+    let nx: usize = settings.width;
+    let ny: usize = settings.height;
+    let ns: usize = settings.samples;
+    // Only camera rays carry a footprint, bounces sample the finest mip level
+    let spread: f64 = camera.pixel_spread(ny);
+    let mut pixels: Vec<Vector3> = Vec::with_capacity((tile.x1 - tile.x0) * (tile.y1 - tile.y0));
+    for y in tile.y0..tile.y1 {
+        let j: usize = ny - 1 - y;
+        for i in tile.x0..tile.x1 {
+            let mut col = Vector3::new(0.0, 0.0, 0.0);
+            for _s in 0..ns {
+                let u: f64 = (i as f64 + random::random_f64()) / nx as f64;
+                let v: f64 = (j as f64 + random::random_f64()) / ny as f64;
+                let r = camera.get_ray(u, v).with_spread(spread);
+                col = col + if lights.is_empty() && punctual.is_empty() && !background.importance_sampled() {
+                    color(&r, world, background, 0, settings.max_depth)
+                } else {
+                    color_mis(&r, world, lights, punctual, background, 0, settings.max_depth, None)
+                };
+            }
+            pixels.push(col / ns as f64);
+        }
+    }
+    pixels
+}
+
+// Lights are sampled explicitly when the list isn't empty, they also
+// have to be part of world. So is the background if it supports it, and
+// punctual lights always are.
+pub fn render<H: Hittable + Sync>(
+    world: &H,
+    lights: &HittableList,
+    punctual: &LightList,
+    camera: &Camera,
+    background: &(dyn Environment + Sync),
+    settings: &RenderSettings,
+) -> Framebuffer {
+    let tiles: Vec<Tile> = tiles(settings.width, settings.height);
+    let threads: usize = if settings.threads > 0 {
+        settings.threads
+    } else {
+        thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    };
+    let next_tile: AtomicUsize = AtomicUsize::new(0);
+
+    let finished: Vec<(usize, Vec<Vector3>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(tiles.len()).max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done: Vec<(usize, Vec<Vector3>)> = Vec::new();
+                    loop {
+                        let index: usize = next_tile.fetch_add(1, Ordering::Relaxed);
+                        if index >= tiles.len() {
+                            break;
+                        }
+                        let pixels: Vec<Vector3> =
+                            render_tile(world, lights, punctual, camera, background, settings, index, &tiles[index]);
+                        done.push((index, pixels));
+                    }
+                    done
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("render thread panicked"))
+            .collect()
+    });
+
+    let mut image: Framebuffer = Framebuffer::new(settings.width, settings.height);
+    for (index, pixels) in finished {
+        let tile: &Tile = &tiles[index];
+        let mut colors = pixels.into_iter();
+        for y in tile.y0..tile.y1 {
+            for x in tile.x0..tile.x1 {
+                image.set(x, y, colors.next().unwrap());
+            }
+        }
+    }
+    image
+}
+
+#[test]
+fn test_render_thread_count_independent() {
+    use bvh::BvhNode;
+    use scene::{parse_scene, Scene};
+    use std::path::Path;
+
+    let source: &str = r#"{
+        "render": { "width": 37, "height": 21, "samples": 3, "seed": 7 },
+        "camera": { "lookfrom": [13, 2, 3], "lookat": [0, 0, 0], "vfov": 20, "aperture": 0.1 },
+        "objects": [ { "type": "random_spheres", "seed": 3 } ]
+    }"#;
+    let scene: Scene = parse_scene(source, "test.json", Path::new(".")).unwrap();
+    let world: BvhNode = BvhNode::new(scene.world);
+    let mut settings: RenderSettings = scene.settings;
+    settings.threads = 1;
+    let single: Framebuffer =
+        render(&world, &scene.lights, &scene.punctual_lights, &scene.camera, &*scene.background, &settings);
+    settings.threads = 4;
+    let multi: Framebuffer =
+        render(&world, &scene.lights, &scene.punctual_lights, &scene.camera, &*scene.background, &settings);
+    assert_eq!(single, multi);
+    assert_eq!(single.width(), 37);
+    assert_eq!(single.height(), 21);
+}
+
+#[test]
+fn test_color_emission() {
+    use diffuse_light::DiffuseLight;
+    use hitable_list::HittableList;
+    use material::Material;
+    use sphere::Sphere;
+
+    use background::Background;
+
+    let black: Background = Background::Solid(Vector3::new(0.0, 0.0, 0.0));
+    let mut world: HittableList = HittableList::new();
+    world.add(Sphere::new(
+        Vector3::new(0.0, 0.0, -2.0),
+        1.0,
+        Material::DiffuseLight(DiffuseLight::new(Vector3::new(2.0, 3.0, 4.0))),
+    ));
+    let at_light: Ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+    let away: Ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+    assert_eq!(color(&at_light, &world, &black, 0, 50), Vector3::new(2.0, 3.0, 4.0));
+    assert_eq!(color(&away, &world, &black, 0, 50), Vector3::new(0.0, 0.0, 0.0));
+}
+
+#[test]
+fn test_mis_matches_path_tracing() {
+    use conductor::Conductor;
+    use diffuse_light::DiffuseLight;
+    use lambertian::Lambertian;
+    use material::Material;
+    use principled::Principled;
+    use sphere::Sphere;
+
+    use background::Background;
+
+    let black: Background = Background::Solid(Vector3::new(0.0, 0.0, 0.0));
+    let light: Sphere = Sphere::new(
+        Vector3::new(0.0, 3.0, 0.0),
+        1.0,
+        Material::DiffuseLight(DiffuseLight::new(Vector3::new(4.0, 4.0, 4.0))),
+    );
+    let mut lights: HittableList = HittableList::new();
+    lights.add(light.clone());
+    let mut plastic: Principled = Principled::new(Vector3::new(0.5, 0.5, 0.5));
+    plastic.roughness = 0.3;
+    plastic.clearcoat = 1.0;
+    // Every floor with a density takes part in light sampling
+    let floors: Vec<Material> = vec![
+        Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5))),
+        Material::Principled(plastic),
+        Material::Conductor(Conductor::copper(0.6)),
+    ];
+    for floor in floors {
+        let mut world: HittableList = HittableList::new();
+        world.add(Sphere::new(Vector3::new(0.0, -1000.0, 0.0), 1000.0, floor.clone()));
+        world.add(light.clone());
+
+        let ray: Ray = Ray::new(Vector3::new(0.0, 1.0, 3.0), Vector3::new(0.0, -1.0, -3.0));
+        let samples: usize = 40000;
+        random::seed(1);
+        let mut plain: f64 = 0.0;
+        let mut mis: f64 = 0.0;
+        for _ in 0..samples {
+            plain += color(&ray, &world, &black, 0, 4).x();
+            mis += color_mis(&ray, &world, &lights, &LightList::new(), &black, 0, 4, None).x();
+        }
+        plain /= samples as f64;
+        mis /= samples as f64;
+        assert!(mis > 0.0);
+        assert!((plain - mis).abs() < 0.05 * mis, "{:?}: plain {} mis {}", floor, plain, mis);
+    }
+}
+
+#[test]
+fn test_mis_with_environment_map() {
+    use diffuse_light::DiffuseLight;
+    use environment::EnvironmentMap;
+    use lambertian::Lambertian;
+    use material::Material;
+    use sphere::Sphere;
+
+    // A small bright patch of sky over a diffuse floor, with and without a lamp
+    let mut image: Framebuffer = Framebuffer::new(32, 16);
+    for y in 0..16 {
+        for x in 0..32 {
+            image.set(x, y, Vector3::new(0.2, 0.3, 0.5));
+        }
+    }
+    for x in 18..22 {
+        image.set(x, 4, Vector3::new(20.0, 20.0, 20.0));
+        image.set(x, 5, Vector3::new(20.0, 20.0, 20.0));
+    }
+    let sky: EnvironmentMap = EnvironmentMap::new(image).with_rotation(40.0).with_intensity(0.5);
+    let lamp: Sphere = Sphere::new(
+        Vector3::new(0.0, 3.0, 0.0),
+        1.0,
+        Material::DiffuseLight(DiffuseLight::new(Vector3::new(4.0, 4.0, 4.0))),
+    );
+    for with_lamp in [false, true] {
+        let mut world: HittableList = HittableList::new();
+        world.add(Sphere::new(
+            Vector3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5))),
+        ));
+        let mut lights: HittableList = HittableList::new();
+        if with_lamp {
+            world.add(lamp.clone());
+            lights.add(lamp.clone());
+        }
+
+        let ray: Ray = Ray::new(Vector3::new(0.0, 1.0, 3.0), Vector3::new(0.0, -1.0, -3.0));
+        let samples: usize = 40000;
+        random::seed(2);
+        let mut plain: f64 = 0.0;
+        let mut mis: f64 = 0.0;
+        for _ in 0..samples {
+            plain += color(&ray, &world, &sky, 0, 4).x();
+            mis += color_mis(&ray, &world, &lights, &LightList::new(), &sky, 0, 4, None).x();
+        }
+        plain /= samples as f64;
+        mis /= samples as f64;
+        assert!((plain - mis).abs() < 0.05 * mis, "lamp {}: plain {} mis {}", with_lamp, plain, mis);
+    }
+}
+
+#[test]
+fn test_punctual_lights() {
+    use background::Background;
+    use lambertian::Lambertian;
+    use light::{DirectionalLight, PointLight};
+    use material::Material;
+    use quad::XZRect;
+    use sphere::Sphere;
+    use std::f64::consts::PI;
+
+    // Direct light on a diffuse floor is albedo / pi * E * cos, with nothing
+    // else around to bounce off
+    let black: Background = Background::Solid(Vector3::new(0.0, 0.0, 0.0));
+    let floor: Material = Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+    let mut world: HittableList = HittableList::new();
+    world.add(XZRect::new(-10.0, 10.0, -10.0, 10.0, 0.0, floor));
+    let mut punctual: LightList = LightList::new();
+    punctual.add(PointLight::new(Vector3::new(3.0, 4.0, 0.0), Vector3::new(50.0, 50.0, 50.0)));
+    punctual.add(DirectionalLight::new(Vector3::new(0.0, -1.0, 0.0), Vector3::new(2.0, 2.0, 2.0)));
+    let ray: Ray = Ray::new(Vector3::new(0.0, 1.0, 1.0), Vector3::new(0.0, -1.0, -1.0));
+    let lit: f64 = color_mis(&ray, &world, &HittableList::new(), &punctual, &black, 0, 1, None).x();
+    let expected: f64 = 0.5 / PI * (50.0 / 25.0 * 0.8 + 2.0);
+    assert!((lit - expected).abs() < 1e-9, "{} vs {}", lit, expected);
+
+    // A ball above the floor shadows the point light but not the sun
+    world.add(Sphere::new(
+        Vector3::new(1.5, 2.0, 0.0),
+        0.5,
+        Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5))),
+    ));
+    let shadowed: f64 = color_mis(&ray, &world, &HittableList::new(), &punctual, &black, 0, 1, None).x();
+    assert!((shadowed - 0.5 / PI * 2.0).abs() < 1e-9, "{}", shadowed);
+}
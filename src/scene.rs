@@ -0,0 +1,1642 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use rand::Rng;
+
+use background::Background;
+use camera::Camera;
+use conductor::Conductor;
+use constant_medium::ConstantMedium;
+use cuboid::Cuboid;
+use dielectric::Dielectric;
+use diffuse_light::DiffuseLight;
+use environment::{EnvironmentMap, SharedEnvironment};
+use grid_medium::GridMedium;
+use henyey_greenstein::HenyeyGreenstein;
+use hitable::SharedHittable;
+use hitable_list::HittableList;
+use ies::{IesError, IesProfile};
+use image_reader::ImageError;
+use isotropic::Isotropic;
+use json::{parse_json, Json, JsonError, JsonValue};
+use lambertian::Lambertian;
+use light::{DirectionalLight, LightList, PointLight, SharedLight, SpotLight};
+use material::Material;
+use mesh::TriangleMesh;
+use metal::Metal;
+use noise::{MarbleTexture, NoiseTexture, TurbulenceTexture, WoodTexture, WorleyTexture};
+use moving_sphere::MovingSphere;
+use obj::{load_obj, ObjError};
+use principled::Principled;
+use quad::{Quad, XYRect, XZRect, YZRect};
+use random;
+use sky::Sky;
+use sphere::Sphere;
+use texture::{self, CheckerTexture, Filter, GradientTexture, ImageTexture, SharedTexture, UvCheckerTexture, WrapMode};
+use transform::Transform;
+use triangle::Triangle;
+use vector::Vector3;
+use voxel_grid::{VoxelError, VoxelGrid};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io { file: String, error: io::Error },
+    Syntax { file: String, error: JsonError },
+    // key is the full path to the offending value, e.g. objects[2].radius
+    Invalid {
+        file: String,
+        key: String,
+        line: usize,
+        message: String,
+    },
+    Obj(ObjError),
+    Image(ImageError),
+    Voxel(VoxelError),
+    Ies(IesError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SceneError::Io { ref file, ref error } => write!(f, "{}: {}", file, error),
+            SceneError::Syntax { ref file, ref error } => write!(f, "{}: {}", file, error),
+            SceneError::Invalid {
+                ref file,
+                ref key,
+                line,
+                ref message,
+            } => write!(f, "{}:{}: {}: {}", file, line, key, message),
+            SceneError::Obj(ref error) => write!(f, "{}", error),
+            SceneError::Image(ref error) => write!(f, "{}", error),
+            SceneError::Voxel(ref error) => write!(f, "{}", error),
+            SceneError::Ies(ref error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+impl From<ObjError> for SceneError {
+    fn from(error: ObjError) -> SceneError {
+        SceneError::Obj(error)
+    }
+}
+
+impl From<ImageError> for SceneError {
+    fn from(error: ImageError) -> SceneError {
+        SceneError::Image(error)
+    }
+}
+
+impl From<VoxelError> for SceneError {
+    fn from(error: VoxelError) -> SceneError {
+        SceneError::Voxel(error)
+    }
+}
+
+impl From<IesError> for SceneError {
+    fn from(error: IesError) -> SceneError {
+        SceneError::Ies(error)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub max_depth: i32,
+    pub seed: u64,
+    // 0 uses every available core
+    pub threads: usize,
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            width: 200,
+            height: 100,
+            samples: 100,
+            max_depth: 50,
+            seed: 0,
+            threads: 0,
+        }
+    }
+}
+
+pub struct Scene {
+    pub settings: RenderSettings,
+    pub camera: Camera,
+    pub background: SharedEnvironment,
+    pub world: HittableList,
+    // Emitters to sample directly, every one of them is also in world
+    pub lights: HittableList,
+    // Point, spot and directional lights, which aren't in world
+    pub punctual_lights: LightList,
+}
+
+pub fn load_scene(path: &Path) -> Result<Scene, SceneError> {
+    let source: String = fs::read_to_string(path).map_err(|error| SceneError::Io {
+        file: path.display().to_string(),
+        error,
+    })?;
+    let base_dir: &Path = path.parent().unwrap_or_else(|| Path::new("."));
+    parse_scene(&source, &path.display().to_string(), base_dir)
+}
+
+// Relative file references (OBJ meshes, images) are resolved against base_dir
+pub fn parse_scene(source: &str, file: &str, base_dir: &Path) -> Result<Scene, SceneError> {
+    let root: Json = parse_json(source).map_err(|error| SceneError::Syntax {
+        file: file.to_string(),
+        error,
+    })?;
+    let loader: Loader = Loader { file, base_dir };
+    loader.scene(&root)
+}
+
+// Materials and shapes defined once and referenced by name
+struct Named {
+    materials: HashMap<String, Material>,
+    shapes: HashMap<String, SharedHittable>,
+}
+
+struct Loader<'a> {
+    file: &'a str,
+    base_dir: &'a Path,
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+impl<'a> Loader<'a> {
+    fn error(&self, json: &Json, key: &str, message: String) -> SceneError {
+        SceneError::Invalid {
+            file: self.file.to_string(),
+            key: key.to_string(),
+            line: json.line,
+            message,
+        }
+    }
+
+    fn wrong_type(&self, json: &Json, key: &str, expected: &str) -> SceneError {
+        self.error(json, key, format!("expected {}, found {}", expected, json.type_name()))
+    }
+
+    fn members<'j>(&self, json: &'j Json, key: &str, allowed: &[&str]) -> Result<&'j [(String, Json)], SceneError> {
+        match json.value {
+            JsonValue::Object(ref members) => {
+                for (name, value) in members {
+                    if !allowed.contains(&name.as_str()) {
+                        return Err(self.error(
+                            value,
+                            &join(key, name),
+                            format!("unknown key, expected one of: {}", allowed.join(", ")),
+                        ));
+                    }
+                }
+                Ok(members)
+            }
+            _ => Err(self.wrong_type(json, key, "an object")),
+        }
+    }
+
+    fn required<'j>(&self, json: &'j Json, key: &str, name: &str) -> Result<&'j Json, SceneError> {
+        json.get(name)
+            .ok_or_else(|| self.error(json, &join(key, name), "missing required key".to_string()))
+    }
+
+    fn number(&self, json: &Json, key: &str) -> Result<f64, SceneError> {
+        match json.value {
+            JsonValue::Number(n) => Ok(n),
+            _ => Err(self.wrong_type(json, key, "a number")),
+        }
+    }
+
+    fn count(&self, json: &Json, key: &str) -> Result<usize, SceneError> {
+        let n: f64 = self.number(json, key)?;
+        if n < 0.0 || n.fract() != 0.0 {
+            return Err(self.error(json, key, format!("expected a non-negative integer, found {}", n)));
+        }
+        Ok(n as usize)
+    }
+
+    fn boolean(&self, json: &Json, key: &str) -> Result<bool, SceneError> {
+        match json.value {
+            JsonValue::Bool(b) => Ok(b),
+            _ => Err(self.wrong_type(json, key, "a boolean")),
+        }
+    }
+
+    fn string<'j>(&self, json: &'j Json, key: &str) -> Result<&'j str, SceneError> {
+        match json.value {
+            JsonValue::String(ref s) => Ok(s),
+            _ => Err(self.wrong_type(json, key, "a string")),
+        }
+    }
+
+    fn array<'j>(&self, json: &'j Json, key: &str) -> Result<&'j [Json], SceneError> {
+        match json.value {
+            JsonValue::Array(ref items) => Ok(items),
+            _ => Err(self.wrong_type(json, key, "an array")),
+        }
+    }
+
+    fn vector(&self, json: &Json, key: &str) -> Result<Vector3, SceneError> {
+        let items: &[Json] = self.array(json, key)?;
+        if items.len() != 3 {
+            return Err(self.error(json, key, format!("expected 3 components, found {}", items.len())));
+        }
+        Ok(Vector3::new(
+            self.number(&items[0], &format!("{}[0]", key))?,
+            self.number(&items[1], &format!("{}[1]", key))?,
+            self.number(&items[2], &format!("{}[2]", key))?,
+        ))
+    }
+
+    // Two numbers [from, to], used for the extent of rectangles
+    fn range(&self, json: &Json, key: &str) -> Result<(f64, f64), SceneError> {
+        let items: &[Json] = self.array(json, key)?;
+        if items.len() != 2 {
+            return Err(self.error(json, key, format!("expected 2 components, found {}", items.len())));
+        }
+        Ok((
+            self.number(&items[0], &format!("{}[0]", key))?,
+            self.number(&items[1], &format!("{}[1]", key))?,
+        ))
+    }
+
+    fn vectors(&self, json: &Json, key: &str) -> Result<Vec<Vector3>, SceneError> {
+        let items: &[Json] = self.array(json, key)?;
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| self.vector(item, &format!("{}[{}]", key, i)))
+            .collect()
+    }
+
+    fn uvs(&self, json: &Json, key: &str) -> Result<Vec<(f64, f64)>, SceneError> {
+        let items: &[Json] = self.array(json, key)?;
+        let mut uvs: Vec<(f64, f64)> = Vec::with_capacity(items.len());
+        for (i, item) in items.iter().enumerate() {
+            let item_key: String = format!("{}[{}]", key, i);
+            let pair: &[Json] = self.array(item, &item_key)?;
+            if pair.len() != 2 {
+                return Err(self.error(item, &item_key, format!("expected 2 components, found {}", pair.len())));
+            }
+            uvs.push((
+                self.number(&pair[0], &format!("{}[0]", item_key))?,
+                self.number(&pair[1], &format!("{}[1]", item_key))?,
+            ));
+        }
+        Ok(uvs)
+    }
+
+    fn optional_number(&self, json: &Json, key: &str, name: &str, default: f64) -> Result<f64, SceneError> {
+        match json.get(name) {
+            Some(value) => self.number(value, &join(key, name)),
+            None => Ok(default),
+        }
+    }
+
+    fn optional_count(&self, json: &Json, key: &str, name: &str, default: usize) -> Result<usize, SceneError> {
+        match json.get(name) {
+            Some(value) => self.count(value, &join(key, name)),
+            None => Ok(default),
+        }
+    }
+
+    fn optional_bool(&self, json: &Json, key: &str, name: &str, default: bool) -> Result<bool, SceneError> {
+        match json.get(name) {
+            Some(value) => self.boolean(value, &join(key, name)),
+            None => Ok(default),
+        }
+    }
+
+    fn optional_vector(&self, json: &Json, key: &str, name: &str, default: Vector3) -> Result<Vector3, SceneError> {
+        match json.get(name) {
+            Some(value) => self.vector(value, &join(key, name)),
+            None => Ok(default),
+        }
+    }
+
+    // An optional number in [0, 1]
+    fn optional_fraction(&self, json: &Json, key: &str, name: &str, default: f64) -> Result<f64, SceneError> {
+        let value: f64 = self.optional_number(json, key, name, default)?;
+        if !(0.0..=1.0).contains(&value) {
+            return Err(self.error(json.get(name).unwrap(), &join(key, name), "must lie between 0 and 1".to_string()));
+        }
+        Ok(value)
+    }
+
+    // A single roughness, or [along, across] the tangent, smooth when missing
+    fn roughness(&self, json: &Json, key: &str) -> Result<(f64, f64), SceneError> {
+        let roughness: &Json = match json.get("roughness") {
+            Some(roughness) => roughness,
+            None => return Ok((0.0, 0.0)),
+        };
+        let (u, v): (f64, f64) = match roughness.value {
+            JsonValue::Array(_) => self.range(roughness, &join(key, "roughness"))?,
+            _ => {
+                let r: f64 = self.number(roughness, &join(key, "roughness"))?;
+                (r, r)
+            }
+        };
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return Err(self.error(roughness, &join(key, "roughness"), "must lie between 0 and 1".to_string()));
+        }
+        Ok((u, v))
+    }
+
+    fn scene(&self, root: &Json) -> Result<Scene, SceneError> {
+        self.members(root, "", &["render", "camera", "background", "materials", "shapes", "objects", "lights"])?;
+        let settings: RenderSettings = match root.get("render") {
+            Some(render) => self.settings(render, "render")?,
+            None => RenderSettings::default(),
+        };
+        let camera: Camera = self.camera(self.required(root, "", "camera")?, "camera", &settings)?;
+        let background: SharedEnvironment = match root.get("background") {
+            Some(json) => self.background(json, "background")?,
+            None => Arc::new(Background::default()),
+        };
+
+        let mut materials: HashMap<String, Material> = HashMap::new();
+        if let Some(json) = root.get("materials") {
+            for (name, value) in self.any_members(json, "materials")? {
+                let material: Material = self.material(value, &join("materials", name), &materials)?;
+                materials.insert(name.clone(), material);
+            }
+        }
+
+        // Shapes are only drawn through transforms that reference them by name
+        let mut named: Named = Named {
+            materials,
+            shapes: HashMap::new(),
+        };
+        if let Some(json) = root.get("shapes") {
+            for (name, value) in self.any_members(json, "shapes")? {
+                let shape: SharedHittable = self.shape(value, &join("shapes", name), &named)?;
+                named.shapes.insert(name.clone(), shape);
+            }
+        }
+
+        let mut world: HittableList = match root.get("objects") {
+            Some(objects) => self.objects(objects, "objects", &named)?,
+            None => HittableList::new(),
+        };
+        let mut lights: HittableList = HittableList::new();
+        let mut punctual_lights: LightList = LightList::new();
+        if let Some(json) = root.get("lights") {
+            for (i, item) in self.array(json, "lights")?.iter().enumerate() {
+                let key: String = format!("lights[{}]", i);
+                match self.punctual_light(item, &key)? {
+                    Some(light) => punctual_lights.add_shared(light),
+                    None => self.object(item, &key, &named, &mut lights)?,
+                }
+            }
+        }
+        for light in &lights {
+            world.add_shared(light.clone());
+        }
+        Ok(Scene {
+            settings,
+            camera,
+            background,
+            world,
+            lights,
+            punctual_lights,
+        })
+    }
+
+    // Like members but for maps keyed by user chosen names
+    fn any_members<'j>(&self, json: &'j Json, key: &str) -> Result<&'j [(String, Json)], SceneError> {
+        match json.value {
+            JsonValue::Object(ref members) => Ok(members),
+            _ => Err(self.wrong_type(json, key, "an object")),
+        }
+    }
+
+    fn settings(&self, json: &Json, key: &str) -> Result<RenderSettings, SceneError> {
+        self.members(json, key, &["width", "height", "samples", "max_depth", "seed", "threads"])?;
+        let mut settings: RenderSettings = RenderSettings::default();
+        if let Some(v) = json.get("width") {
+            settings.width = self.count(v, &join(key, "width"))?;
+        }
+        if let Some(v) = json.get("height") {
+            settings.height = self.count(v, &join(key, "height"))?;
+        }
+        if let Some(v) = json.get("samples") {
+            settings.samples = self.count(v, &join(key, "samples"))?;
+        }
+        if let Some(v) = json.get("max_depth") {
+            settings.max_depth = self.count(v, &join(key, "max_depth"))? as i32;
+        }
+        if let Some(v) = json.get("seed") {
+            settings.seed = self.count(v, &join(key, "seed"))? as u64;
+        }
+        if let Some(v) = json.get("threads") {
+            settings.threads = self.count(v, &join(key, "threads"))?;
+        }
+        for &(name, value) in &[("width", settings.width), ("height", settings.height), ("samples", settings.samples)] {
+            if value == 0 {
+                return Err(self.error(json.get(name).unwrap(), &join(key, name), "must be at least 1".to_string()));
+            }
+        }
+        Ok(settings)
+    }
+
+    fn camera(&self, json: &Json, key: &str, settings: &RenderSettings) -> Result<Camera, SceneError> {
+        self.members(
+            json,
+            key,
+            &["lookfrom", "lookat", "vup", "vfov", "aspect", "aperture", "focus_dist", "shutter"],
+        )?;
+        let lookfrom: Vector3 = self.vector(self.required(json, key, "lookfrom")?, &join(key, "lookfrom"))?;
+        let lookat: Vector3 = self.vector(self.required(json, key, "lookat")?, &join(key, "lookat"))?;
+        let vup: Vector3 = match json.get("vup") {
+            Some(v) => self.vector(v, &join(key, "vup"))?,
+            None => Vector3::new(0.0, 1.0, 0.0),
+        };
+        let vfov_json: &Json = self.required(json, key, "vfov")?;
+        let vfov: f64 = self.number(vfov_json, &join(key, "vfov"))?;
+        if vfov <= 0.0 || vfov >= 180.0 {
+            return Err(self.error(vfov_json, &join(key, "vfov"), "must lie between 0 and 180".to_string()));
+        }
+        let aspect: f64 = self.optional_number(
+            json,
+            key,
+            "aspect",
+            settings.width as f64 / settings.height as f64,
+        )?;
+        let aperture: f64 = self.optional_number(json, key, "aperture", 0.0)?;
+        let focus_dist: f64 = self.optional_number(json, key, "focus_dist", (lookfrom - lookat).length())?;
+        if lookfrom == lookat {
+            return Err(self.error(json, &join(key, "lookat"), "must differ from lookfrom".to_string()));
+        }
+        let (open, close): (f64, f64) = match json.get("shutter") {
+            Some(shutter) => {
+                let (open, close): (f64, f64) = self.range(shutter, &join(key, "shutter"))?;
+                if close < open {
+                    return Err(self.error(shutter, &join(key, "shutter"), "closes before it opens".to_string()));
+                }
+                (open, close)
+            }
+            None => (0.0, 0.0),
+        };
+        Ok(Camera::new(lookfrom, lookat, vup, vfov, aspect, aperture, focus_dist).with_shutter(open, close))
+    }
+
+    // Either a plain color or a typed object
+    fn background(&self, json: &Json, key: &str) -> Result<SharedEnvironment, SceneError> {
+        if let JsonValue::Array(_) = json.value {
+            return Ok(Arc::new(Background::Solid(self.vector(json, key)?)));
+        }
+        let kind_key: String = join(key, "type");
+        let kind: &str = self.string(self.required(json, key, "type")?, &kind_key)?;
+        match kind {
+            "gradient" => {
+                self.members(json, key, &["type", "bottom", "top"])?;
+                Ok(Arc::new(Background::Gradient {
+                    bottom: self.vector(self.required(json, key, "bottom")?, &join(key, "bottom"))?,
+                    top: self.vector(self.required(json, key, "top")?, &join(key, "top"))?,
+                }))
+            }
+            "solid" => {
+                self.members(json, key, &["type", "color"])?;
+                Ok(Arc::new(Background::Solid(self.vector(self.required(json, key, "color")?, &join(key, "color"))?)))
+            }
+            // An equirectangular HDR, importance sampled by luminance
+            "image" => {
+                self.members(json, key, &["type", "file", "rotation", "intensity"])?;
+                let file: &str = self.string(self.required(json, key, "file")?, &join(key, "file"))?;
+                let rotation: f64 = self.optional_number(json, key, "rotation", 0.0)?;
+                let intensity: f64 = self.optional_number(json, key, "intensity", 1.0)?;
+                if intensity < 0.0 {
+                    return Err(self.error(
+                        json.get("intensity").unwrap(),
+                        &join(key, "intensity"),
+                        "must not be negative".to_string(),
+                    ));
+                }
+                let map: EnvironmentMap = EnvironmentMap::load(&self.base_dir.join(file))?;
+                Ok(Arc::new(map.with_rotation(rotation).with_intensity(intensity)))
+            }
+            // Daylight from the sun's position and the haziness of the air
+            "sky" => {
+                self.members(json, key, &["type", "elevation", "azimuth", "turbidity", "ground_albedo", "intensity"])?;
+                let elevation_json: &Json = self.required(json, key, "elevation")?;
+                let elevation: f64 = self.number(elevation_json, &join(key, "elevation"))?;
+                if !(-90.0..=90.0).contains(&elevation) {
+                    return Err(self.error(elevation_json, &join(key, "elevation"), "must lie between -90 and 90".to_string()));
+                }
+                let azimuth: f64 = self.optional_number(json, key, "azimuth", 0.0)?;
+                let turbidity: f64 = self.optional_number(json, key, "turbidity", 3.0)?;
+                if !(1.7..=10.0).contains(&turbidity) {
+                    return Err(self.error(
+                        json.get("turbidity").unwrap(),
+                        &join(key, "turbidity"),
+                        "must lie between 1.7 and 10".to_string(),
+                    ));
+                }
+                let ground_albedo: Vector3 = self.optional_vector(json, key, "ground_albedo", Vector3::new(0.3, 0.3, 0.3))?;
+                let intensity: f64 = self.optional_number(json, key, "intensity", 1.0)?;
+                if intensity < 0.0 {
+                    return Err(self.error(
+                        json.get("intensity").unwrap(),
+                        &join(key, "intensity"),
+                        "must not be negative".to_string(),
+                    ));
+                }
+                Ok(Arc::new(
+                    Sky::new(elevation, azimuth)
+                        .with_turbidity(turbidity)
+                        .with_ground_albedo(ground_albedo)
+                        .with_intensity(intensity),
+                ))
+            }
+            _ => Err(self.error(
+                json.get("type").unwrap(),
+                &kind_key,
+                format!("unknown background type '{}'", kind),
+            )),
+        }
+    }
+
+    // Lights without a surface, None for anything else in the lights list
+    fn punctual_light(&self, json: &Json, key: &str) -> Result<Option<SharedLight>, SceneError> {
+        let kind: &str = self.string(self.required(json, key, "type")?, &join(key, "type"))?;
+        let radius = |json: &Json| -> Result<f64, SceneError> {
+            let radius: f64 = self.optional_number(json, key, "radius", 0.0)?;
+            if radius < 0.0 {
+                return Err(self.error(json.get("radius").unwrap(), &join(key, "radius"), "must not be negative".to_string()));
+            }
+            Ok(radius)
+        };
+        // A measured distribution from an IES file
+        let profile = |json: &Json| -> Result<Option<Arc<IesProfile>>, SceneError> {
+            match json.get("ies") {
+                Some(file) => {
+                    let file: &str = self.string(file, &join(key, "ies"))?;
+                    Ok(Some(Arc::new(IesProfile::load(&self.base_dir.join(file))?)))
+                }
+                None => Ok(None),
+            }
+        };
+        let light: SharedLight = match kind {
+            "point" => {
+                self.members(json, key, &["type", "position", "intensity", "radius", "ies", "aim"])?;
+                let position: Vector3 = self.vector(self.required(json, key, "position")?, &join(key, "position"))?;
+                let intensity: Vector3 = self.vector(self.required(json, key, "intensity")?, &join(key, "intensity"))?;
+                let mut light: PointLight = PointLight::new(position, intensity).with_radius(radius(json)?);
+                // Profiles point down unless aimed elsewhere
+                let aim: Vector3 = self.optional_vector(json, key, "aim", Vector3::new(0.0, -1.0, 0.0))?;
+                if aim.norm() == 0.0 {
+                    return Err(self.error(json.get("aim").unwrap(), &join(key, "aim"), "must not be zero".to_string()));
+                }
+                match profile(json)? {
+                    Some(profile) => light = light.with_profile(profile, aim),
+                    None if json.get("aim").is_some() => {
+                        return Err(self.error(json.get("aim").unwrap(), &join(key, "aim"), "only applies with ies".to_string()));
+                    }
+                    None => {}
+                }
+                Arc::new(light)
+            }
+            "spot" => {
+                self.members(
+                    json,
+                    key,
+                    &["type", "position", "direction", "intensity", "angle", "falloff", "radius", "ies"],
+                )?;
+                let position: Vector3 = self.vector(self.required(json, key, "position")?, &join(key, "position"))?;
+                let direction: Vector3 = self.light_direction(json, key)?;
+                let intensity: Vector3 = self.vector(self.required(json, key, "intensity")?, &join(key, "intensity"))?;
+                let angle_json: &Json = self.required(json, key, "angle")?;
+                let angle: f64 = self.number(angle_json, &join(key, "angle"))?;
+                if angle <= 0.0 || angle > 180.0 {
+                    return Err(self.error(angle_json, &join(key, "angle"), "must lie between 0 and 180".to_string()));
+                }
+                let falloff: f64 = self.optional_number(json, key, "falloff", 0.0)?;
+                if falloff < 0.0 {
+                    return Err(self.error(
+                        json.get("falloff").unwrap(),
+                        &join(key, "falloff"),
+                        "must not be negative".to_string(),
+                    ));
+                }
+                let mut light: SpotLight =
+                    SpotLight::new(position, direction, intensity, angle, falloff).with_radius(radius(json)?);
+                if let Some(profile) = profile(json)? {
+                    light = light.with_profile(profile);
+                }
+                Arc::new(light)
+            }
+            "directional" => {
+                self.members(json, key, &["type", "direction", "irradiance"])?;
+                let direction: Vector3 = self.light_direction(json, key)?;
+                let irradiance: Vector3 = self.vector(self.required(json, key, "irradiance")?, &join(key, "irradiance"))?;
+                Arc::new(DirectionalLight::new(direction, irradiance))
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(light))
+    }
+
+    // Where a spot or directional light shines, which needs a nonzero vector
+    fn light_direction(&self, json: &Json, key: &str) -> Result<Vector3, SceneError> {
+        let direction_json: &Json = self.required(json, key, "direction")?;
+        let direction: Vector3 = self.vector(direction_json, &join(key, "direction"))?;
+        if direction.norm() == 0.0 {
+            return Err(self.error(direction_json, &join(key, "direction"), "must not be zero".to_string()));
+        }
+        Ok(direction)
+    }
+
+    // A bare color is a constant texture
+    fn texture(&self, json: &Json, key: &str) -> Result<SharedTexture, SceneError> {
+        if let JsonValue::Array(_) = json.value {
+            return Ok(texture::constant(self.vector(json, key)?));
+        }
+        let kind_key: String = join(key, "type");
+        let kind: &str = self.string(self.required(json, key, "type")?, &kind_key)?;
+        match kind {
+            "constant" => {
+                self.members(json, key, &["type", "color"])?;
+                Ok(texture::constant(self.vector(self.required(json, key, "color")?, &join(key, "color"))?))
+            }
+            "checker" => {
+                self.members(json, key, &["type", "odd", "even", "scale"])?;
+                Ok(Arc::new(CheckerTexture::new(
+                    self.texture(self.required(json, key, "odd")?, &join(key, "odd"))?,
+                    self.texture(self.required(json, key, "even")?, &join(key, "even"))?,
+                    self.optional_number(json, key, "scale", 1.0)?,
+                )))
+            }
+            "uv_checker" => {
+                self.members(json, key, &["type", "odd", "even", "columns", "rows"])?;
+                Ok(Arc::new(UvCheckerTexture::new(
+                    self.texture(self.required(json, key, "odd")?, &join(key, "odd"))?,
+                    self.texture(self.required(json, key, "even")?, &join(key, "even"))?,
+                    self.optional_number(json, key, "columns", 8.0)?,
+                    self.optional_number(json, key, "rows", 8.0)?,
+                )))
+            }
+            "gradient" => {
+                self.members(json, key, &["type", "start", "end"])?;
+                Ok(Arc::new(GradientTexture::new(
+                    self.vector(self.required(json, key, "start")?, &join(key, "start"))?,
+                    self.vector(self.required(json, key, "end")?, &join(key, "end"))?,
+                )))
+            }
+            "noise" => {
+                self.members(json, key, &["type", "seed", "scale", "color"])?;
+                Ok(Arc::new(NoiseTexture::new(
+                    self.optional_count(json, key, "seed", 0)? as u64,
+                    self.optional_number(json, key, "scale", 1.0)?,
+                    self.optional_vector(json, key, "color", Vector3::new(1.0, 1.0, 1.0))?,
+                )))
+            }
+            "turbulence" => {
+                self.members(json, key, &["type", "seed", "scale", "octaves", "color"])?;
+                Ok(Arc::new(TurbulenceTexture::new(
+                    self.optional_count(json, key, "seed", 0)? as u64,
+                    self.optional_number(json, key, "scale", 1.0)?,
+                    self.optional_count(json, key, "octaves", 7)?,
+                    self.optional_vector(json, key, "color", Vector3::new(1.0, 1.0, 1.0))?,
+                )))
+            }
+            "marble" | "wood" => {
+                self.members(json, key, &["type", "seed", "scale", "octaves", "strength", "light", "dark"])?;
+                let seed: u64 = self.optional_count(json, key, "seed", 0)? as u64;
+                let scale: f64 = self.optional_number(json, key, "scale", 4.0)?;
+                let octaves: usize = self.optional_count(json, key, "octaves", 7)?;
+                if kind == "marble" {
+                    Ok(Arc::new(MarbleTexture::new(
+                        seed,
+                        scale,
+                        octaves,
+                        self.optional_number(json, key, "strength", 10.0)?,
+                        self.optional_vector(json, key, "light", Vector3::new(0.9, 0.9, 0.88))?,
+                        self.optional_vector(json, key, "dark", Vector3::new(0.2, 0.2, 0.22))?,
+                    )))
+                } else {
+                    Ok(Arc::new(WoodTexture::new(
+                        seed,
+                        scale,
+                        octaves,
+                        self.optional_number(json, key, "strength", 1.0)?,
+                        self.optional_vector(json, key, "light", Vector3::new(0.72, 0.5, 0.3))?,
+                        self.optional_vector(json, key, "dark", Vector3::new(0.4, 0.22, 0.1))?,
+                    )))
+                }
+            }
+            "worley" => {
+                self.members(json, key, &["type", "seed", "scale", "near", "far"])?;
+                Ok(Arc::new(WorleyTexture::new(
+                    self.optional_count(json, key, "seed", 0)? as u64,
+                    self.optional_number(json, key, "scale", 1.0)?,
+                    self.optional_vector(json, key, "near", Vector3::new(1.0, 1.0, 1.0))?,
+                    self.optional_vector(json, key, "far", Vector3::new(0.0, 0.0, 0.0))?,
+                )))
+            }
+            "image" => {
+                self.members(json, key, &["type", "file", "wrap", "filter"])?;
+                let file: &str = self.string(self.required(json, key, "file")?, &join(key, "file"))?;
+                let wrap: WrapMode = match json.get("wrap") {
+                    Some(wrap) => {
+                        let name: &str = self.string(wrap, &join(key, "wrap"))?;
+                        WrapMode::from_name(name).ok_or_else(|| {
+                            self.error(wrap, &join(key, "wrap"), format!("unknown wrap mode '{}', expected repeat, clamp or mirror", name))
+                        })?
+                    }
+                    None => WrapMode::Repeat,
+                };
+                let filter: Filter = match json.get("filter") {
+                    Some(filter) => {
+                        let name: &str = self.string(filter, &join(key, "filter"))?;
+                        Filter::from_name(name).ok_or_else(|| {
+                            self.error(
+                                filter,
+                                &join(key, "filter"),
+                                format!("unknown filter '{}', expected nearest, bilinear or trilinear", name),
+                            )
+                        })?
+                    }
+                    None => Filter::Trilinear,
+                };
+                let image: ImageTexture = ImageTexture::load(&self.base_dir.join(file))?;
+                Ok(Arc::new(image.with_wrap(wrap).with_filter(filter)))
+            }
+            _ => Err(self.error(
+                json.get("type").unwrap(),
+                &kind_key,
+                format!("unknown texture type '{}'", kind),
+            )),
+        }
+    }
+
+    fn material(&self, json: &Json, key: &str, named: &HashMap<String, Material>) -> Result<Material, SceneError> {
+        if let JsonValue::String(ref name) = json.value {
+            return named
+                .get(name)
+                .cloned()
+                .ok_or_else(|| self.error(json, key, format!("unknown material '{}'", name)));
+        }
+        let kind_key: String = join(key, "type");
+        let kind: &str = self.string(self.required(json, key, "type")?, &kind_key)?;
+        match kind {
+            "lambertian" => {
+                self.members(json, key, &["type", "albedo"])?;
+                let albedo: SharedTexture = self.texture(self.required(json, key, "albedo")?, &join(key, "albedo"))?;
+                Ok(Material::Lambertian(Lambertian::textured(albedo)))
+            }
+            "metal" => {
+                self.members(json, key, &["type", "albedo", "fuzz"])?;
+                let albedo: SharedTexture = self.texture(self.required(json, key, "albedo")?, &join(key, "albedo"))?;
+                let fuzz: f64 = self.optional_number(json, key, "fuzz", 0.0)?;
+                Ok(Material::Metal(Metal::textured(albedo, fuzz)))
+            }
+            "conductor" => {
+                self.members(json, key, &["type", "preset", "eta", "k", "roughness"])?;
+                let (eta, k): (Vector3, Vector3) = match json.get("preset") {
+                    Some(preset) => {
+                        if json.get("eta").is_some() || json.get("k").is_some() {
+                            return Err(self.error(preset, &join(key, "preset"), "can't be combined with eta and k".to_string()));
+                        }
+                        let name: &str = self.string(preset, &join(key, "preset"))?;
+                        Conductor::preset(name).ok_or_else(|| {
+                            self.error(
+                                preset,
+                                &join(key, "preset"),
+                                format!("unknown metal '{}', expected one of gold, copper, aluminium, silver", name),
+                            )
+                        })?
+                    }
+                    None => (
+                        self.vector(self.required(json, key, "eta")?, &join(key, "eta"))?,
+                        self.vector(self.required(json, key, "k")?, &join(key, "k"))?,
+                    ),
+                };
+                let (roughness_u, roughness_v): (f64, f64) = self.roughness(json, key)?;
+                Ok(Material::Conductor(Conductor::anisotropic(eta, k, roughness_u, roughness_v)))
+            }
+            "dielectric" => {
+                self.members(json, key, &["type", "ri", "roughness", "absorption", "color", "distance"])?;
+                let ri_json: &Json = self.required(json, key, "ri")?;
+                let ri: f64 = self.number(ri_json, &join(key, "ri"))?;
+                if ri <= 0.0 {
+                    return Err(self.error(ri_json, &join(key, "ri"), "must be positive".to_string()));
+                }
+                let (roughness_u, roughness_v): (f64, f64) = self.roughness(json, key)?;
+                let glass: Dielectric = Dielectric::new(ri).with_roughness(roughness_u, roughness_v);
+                // Absorption directly, or the color left after travelling a distance inside
+                match (json.get("absorption"), json.get("color")) {
+                    (Some(absorption_json), None) => {
+                        let absorption: Vector3 = self.vector(absorption_json, &join(key, "absorption"))?;
+                        if absorption.x() < 0.0 || absorption.y() < 0.0 || absorption.z() < 0.0 {
+                            return Err(self.error(
+                                absorption_json,
+                                &join(key, "absorption"),
+                                "must not be negative".to_string(),
+                            ));
+                        }
+                        Ok(Material::Dielectric(glass.with_absorption(absorption)))
+                    }
+                    (None, Some(color)) => {
+                        let color: Vector3 = self.vector(color, &join(key, "color"))?;
+                        let distance: f64 = self.optional_number(json, key, "distance", 1.0)?;
+                        if distance <= 0.0 {
+                            return Err(self.error(
+                                json.get("distance").unwrap(),
+                                &join(key, "distance"),
+                                "must be positive".to_string(),
+                            ));
+                        }
+                        Ok(Material::Dielectric(glass.with_color(color, distance)))
+                    }
+                    (Some(absorption), Some(_)) => Err(self.error(
+                        absorption,
+                        &join(key, "absorption"),
+                        "can't be combined with color".to_string(),
+                    )),
+                    (None, None) => Ok(Material::Dielectric(glass)),
+                }
+            }
+            "principled" => {
+                self.members(
+                    json,
+                    key,
+                    &[
+                        "type", "base_color", "metallic", "roughness", "specular", "specular_tint", "sheen", "sheen_tint",
+                        "clearcoat", "clearcoat_gloss", "transmission", "ior", "subsurface", "anisotropic",
+                    ],
+                )?;
+                let base_color: SharedTexture =
+                    self.texture(self.required(json, key, "base_color")?, &join(key, "base_color"))?;
+                let defaults: Principled = Principled::textured(base_color);
+                let ior: f64 = self.optional_number(json, key, "ior", defaults.ior)?;
+                if ior <= 0.0 {
+                    return Err(self.error(json.get("ior").unwrap(), &join(key, "ior"), "must be positive".to_string()));
+                }
+                Ok(Material::Principled(Principled {
+                    metallic: self.optional_fraction(json, key, "metallic", defaults.metallic)?,
+                    roughness: self.optional_fraction(json, key, "roughness", defaults.roughness)?,
+                    specular: self.optional_fraction(json, key, "specular", defaults.specular)?,
+                    specular_tint: self.optional_fraction(json, key, "specular_tint", defaults.specular_tint)?,
+                    sheen: self.optional_fraction(json, key, "sheen", defaults.sheen)?,
+                    sheen_tint: self.optional_fraction(json, key, "sheen_tint", defaults.sheen_tint)?,
+                    clearcoat: self.optional_fraction(json, key, "clearcoat", defaults.clearcoat)?,
+                    clearcoat_gloss: self.optional_fraction(json, key, "clearcoat_gloss", defaults.clearcoat_gloss)?,
+                    transmission: self.optional_fraction(json, key, "transmission", defaults.transmission)?,
+                    ior,
+                    subsurface: self.optional_fraction(json, key, "subsurface", defaults.subsurface)?,
+                    anisotropic: self.optional_fraction(json, key, "anisotropic", defaults.anisotropic)?,
+                    ..defaults
+                }))
+            }
+            "isotropic" => {
+                self.members(json, key, &["type", "albedo"])?;
+                let albedo: SharedTexture = self.texture(self.required(json, key, "albedo")?, &join(key, "albedo"))?;
+                Ok(Material::Isotropic(Isotropic::textured(albedo)))
+            }
+            "henyey_greenstein" => {
+                self.members(json, key, &["type", "albedo", "g"])?;
+                let albedo: SharedTexture = self.texture(self.required(json, key, "albedo")?, &join(key, "albedo"))?;
+                let g_json: &Json = self.required(json, key, "g")?;
+                let g: f64 = self.number(g_json, &join(key, "g"))?;
+                if g <= -1.0 || g >= 1.0 {
+                    return Err(self.error(g_json, &join(key, "g"), "must lie strictly between -1 and 1".to_string()));
+                }
+                Ok(Material::HenyeyGreenstein(HenyeyGreenstein::textured(albedo, g)))
+            }
+            "diffuse_light" => {
+                self.members(json, key, &["type", "emit"])?;
+                let emit: Vector3 = self.vector(self.required(json, key, "emit")?, &join(key, "emit"))?;
+                Ok(Material::DiffuseLight(DiffuseLight::new(emit)))
+            }
+            _ => Err(self.error(
+                json.get("type").unwrap(),
+                &kind_key,
+                format!("unknown material type '{}'", kind),
+            )),
+        }
+    }
+
+    // A single object, or the name of one from the shapes map
+    fn shape(&self, json: &Json, key: &str, named: &Named) -> Result<SharedHittable, SceneError> {
+        if let JsonValue::String(ref name) = json.value {
+            return named
+                .shapes
+                .get(name)
+                .cloned()
+                .ok_or_else(|| self.error(json, key, format!("unknown shape '{}'", name)));
+        }
+        let mut list: HittableList = HittableList::new();
+        self.object(json, key, named, &mut list)?;
+        // random_spheres adds many objects, keep them together
+        if list.size() == 1 {
+            Ok(list.remove(0))
+        } else {
+            Ok(Arc::new(list))
+        }
+    }
+
+    fn objects(&self, json: &Json, key: &str, named: &Named) -> Result<HittableList, SceneError> {
+        let mut list: HittableList = HittableList::new();
+        for (i, item) in self.array(json, key)?.iter().enumerate() {
+            self.object(item, &format!("{}[{}]", key, i), named, &mut list)?;
+        }
+        Ok(list)
+    }
+
+    fn object(
+        &self,
+        json: &Json,
+        key: &str,
+        named: &Named,
+        list: &mut HittableList,
+    ) -> Result<(), SceneError> {
+        let kind_key: String = join(key, "type");
+        let kind: &str = self.string(self.required(json, key, "type")?, &kind_key)?;
+        match kind {
+            "sphere" => {
+                self.members(json, key, &["type", "center", "radius", "material"])?;
+                let center: Vector3 = self.vector(self.required(json, key, "center")?, &join(key, "center"))?;
+                let radius: f64 = self.number(self.required(json, key, "radius")?, &join(key, "radius"))?;
+                let material: Material =
+                    self.material(self.required(json, key, "material")?, &join(key, "material"), &named.materials)?;
+                list.add(Sphere::new(center, radius, material));
+            }
+            "moving_sphere" => {
+                self.members(
+                    json,
+                    key,
+                    &["type", "center0", "center1", "time0", "time1", "keyframes", "radius", "material"],
+                )?;
+                let radius: f64 = self.number(self.required(json, key, "radius")?, &join(key, "radius"))?;
+                let material: Material =
+                    self.material(self.required(json, key, "material")?, &join(key, "material"), &named.materials)?;
+                // Either a list of { time, center } keyframes or a straight move from center0 to center1
+                let keyframes: Vec<(f64, Vector3)> = match json.get("keyframes") {
+                    Some(frames_json) => {
+                        let frames_key: String = join(key, "keyframes");
+                        let frames: &[Json] = self.array(frames_json, &frames_key)?;
+                        if frames.is_empty() {
+                            return Err(self.error(frames_json, &frames_key, "expected at least one keyframe".to_string()));
+                        }
+                        let mut keyframes: Vec<(f64, Vector3)> = Vec::with_capacity(frames.len());
+                        for (i, frame) in frames.iter().enumerate() {
+                            let frame_key: String = format!("{}[{}]", frames_key, i);
+                            self.members(frame, &frame_key, &["time", "center"])?;
+                            keyframes.push((
+                                self.number(self.required(frame, &frame_key, "time")?, &join(&frame_key, "time"))?,
+                                self.vector(self.required(frame, &frame_key, "center")?, &join(&frame_key, "center"))?,
+                            ));
+                        }
+                        keyframes
+                    }
+                    None => vec![
+                        (
+                            self.optional_number(json, key, "time0", 0.0)?,
+                            self.vector(self.required(json, key, "center0")?, &join(key, "center0"))?,
+                        ),
+                        (
+                            self.optional_number(json, key, "time1", 1.0)?,
+                            self.vector(self.required(json, key, "center1")?, &join(key, "center1"))?,
+                        ),
+                    ],
+                };
+                list.add(MovingSphere::keyframed(keyframes, radius, material));
+            }
+            "triangle" => {
+                self.members(json, key, &["type", "vertices", "normals", "uvs", "material"])?;
+                let vertices_json: &Json = self.required(json, key, "vertices")?;
+                let v: Vec<Vector3> = self.vectors(vertices_json, &join(key, "vertices"))?;
+                if v.len() != 3 {
+                    return Err(self.error(vertices_json, &join(key, "vertices"), format!("expected 3 vertices, found {}", v.len())));
+                }
+                let material: Material =
+                    self.material(self.required(json, key, "material")?, &join(key, "material"), &named.materials)?;
+                let mut triangle: Triangle = Triangle::new(v[0], v[1], v[2], material);
+                if let Some(normals_json) = json.get("normals") {
+                    let n: Vec<Vector3> = self.vectors(normals_json, &join(key, "normals"))?;
+                    if n.len() != 3 {
+                        return Err(self.error(normals_json, &join(key, "normals"), format!("expected 3 normals, found {}", n.len())));
+                    }
+                    triangle = triangle.with_normals(n[0], n[1], n[2]);
+                }
+                if let Some(uvs_json) = json.get("uvs") {
+                    let uv: Vec<(f64, f64)> = self.uvs(uvs_json, &join(key, "uvs"))?;
+                    if uv.len() != 3 {
+                        return Err(self.error(uvs_json, &join(key, "uvs"), format!("expected 3 uvs, found {}", uv.len())));
+                    }
+                    triangle = triangle.with_uvs(uv[0], uv[1], uv[2]);
+                }
+                list.add(triangle);
+            }
+            "quad" => {
+                self.members(json, key, &["type", "origin", "u", "v", "material", "flip"])?;
+                let origin: Vector3 = self.vector(self.required(json, key, "origin")?, &join(key, "origin"))?;
+                let u: Vector3 = self.vector(self.required(json, key, "u")?, &join(key, "u"))?;
+                let v: Vector3 = self.vector(self.required(json, key, "v")?, &join(key, "v"))?;
+                let material: Material =
+                    self.material(self.required(json, key, "material")?, &join(key, "material"), &named.materials)?;
+                let quad: Quad = Quad::new(origin, u, v, material);
+                if self.optional_bool(json, key, "flip", false)? {
+                    list.add(quad.flipped());
+                } else {
+                    list.add(quad);
+                }
+            }
+            "xy_rect" | "xz_rect" | "yz_rect" => {
+                // The two axes spanned by the rectangle, in name order
+                let (a, b): (&str, &str) = (&kind[0..1], &kind[1..2]);
+                self.members(json, key, &["type", a, b, "k", "material", "flip"])?;
+                let (a0, a1): (f64, f64) = self.range(self.required(json, key, a)?, &join(key, a))?;
+                let (b0, b1): (f64, f64) = self.range(self.required(json, key, b)?, &join(key, b))?;
+                let k: f64 = self.number(self.required(json, key, "k")?, &join(key, "k"))?;
+                let material: Material =
+                    self.material(self.required(json, key, "material")?, &join(key, "material"), &named.materials)?;
+                let flip: bool = self.optional_bool(json, key, "flip", false)?;
+                let quad: Quad = match kind {
+                    "xy_rect" => XYRect::new(a0, a1, b0, b1, k, material).into(),
+                    "xz_rect" => XZRect::new(a0, a1, b0, b1, k, material).into(),
+                    _ => YZRect::new(a0, a1, b0, b1, k, material).into(),
+                };
+                if flip {
+                    list.add(quad.flipped());
+                } else {
+                    list.add(quad);
+                }
+            }
+            "box" => {
+                self.members(json, key, &["type", "min", "max", "material"])?;
+                let min: Vector3 = self.vector(self.required(json, key, "min")?, &join(key, "min"))?;
+                let max: Vector3 = self.vector(self.required(json, key, "max")?, &join(key, "max"))?;
+                let material: Material =
+                    self.material(self.required(json, key, "material")?, &join(key, "material"), &named.materials)?;
+                list.add(Cuboid::new(min, max, material));
+            }
+            "mesh" => {
+                self.members(json, key, &["type", "positions", "normals", "uvs", "indices", "material"])?;
+                let positions: Vec<Vector3> =
+                    self.vectors(self.required(json, key, "positions")?, &join(key, "positions"))?;
+                let normals: Vec<Vector3> = match json.get("normals") {
+                    Some(n) => self.vectors(n, &join(key, "normals"))?,
+                    None => Vec::new(),
+                };
+                let uvs: Vec<(f64, f64)> = match json.get("uvs") {
+                    Some(uv) => self.uvs(uv, &join(key, "uvs"))?,
+                    None => Vec::new(),
+                };
+                for &(name, len) in &[("normals", normals.len()), ("uvs", uvs.len())] {
+                    if len != 0 && len != positions.len() {
+                        return Err(self.error(
+                            json.get(name).unwrap(),
+                            &join(key, name),
+                            format!("expected {} entries to match positions, found {}", positions.len(), len),
+                        ));
+                    }
+                }
+                let indices_key: String = join(key, "indices");
+                let indices_json: &Json = self.required(json, key, "indices")?;
+                let flat: &[Json] = self.array(indices_json, &indices_key)?;
+                if !flat.len().is_multiple_of(3) {
+                    return Err(self.error(indices_json, &indices_key, "length must be a multiple of 3".to_string()));
+                }
+                let mut indices: Vec<[usize; 3]> = Vec::with_capacity(flat.len() / 3);
+                for (f, face) in flat.chunks(3).enumerate() {
+                    let mut triangle: [usize; 3] = [0; 3];
+                    for (c, index) in face.iter().enumerate() {
+                        let index_key: String = format!("{}[{}]", indices_key, 3 * f + c);
+                        triangle[c] = self.count(index, &index_key)?;
+                        if triangle[c] >= positions.len() {
+                            return Err(self.error(index, &index_key, format!("index out of range ({} positions)", positions.len())));
+                        }
+                    }
+                    indices.push(triangle);
+                }
+                let material: Material =
+                    self.material(self.required(json, key, "material")?, &join(key, "material"), &named.materials)?;
+                list.add(TriangleMesh::new(positions, normals, uvs, indices, material));
+            }
+            "obj" => {
+                self.members(json, key, &["type", "file"])?;
+                let file: &str = self.string(self.required(json, key, "file")?, &join(key, "file"))?;
+                let model = load_obj(&self.base_dir.join(file))?;
+                list.add(model.into_hittable_list());
+            }
+            "list" => {
+                self.members(json, key, &["type", "objects"])?;
+                let objects: HittableList =
+                    self.objects(self.required(json, key, "objects")?, &join(key, "objects"), named)?;
+                list.add(objects);
+            }
+            "transform" => {
+                self.members(json, key, &["type", "object", "scale", "rotate", "axis", "angle", "translate"])?;
+                let object: SharedHittable = self.shape(self.required(json, key, "object")?, &join(key, "object"), named)?;
+                // Applied in a fixed order: scale, Euler rotation, axis rotation, translation
+                let mut transform: Transform = Transform::new(object);
+                if let Some(scale) = json.get("scale") {
+                    let factors: Vector3 = match scale.value {
+                        JsonValue::Number(n) => Vector3::new(n, n, n),
+                        _ => self.vector(scale, &join(key, "scale"))?,
+                    };
+                    if factors.x() == 0.0 || factors.y() == 0.0 || factors.z() == 0.0 {
+                        return Err(self.error(scale, &join(key, "scale"), "scale factors must not be zero".to_string()));
+                    }
+                    transform = transform.scale(factors);
+                }
+                if let Some(rotate) = json.get("rotate") {
+                    transform = transform.rotate_euler(self.vector(rotate, &join(key, "rotate"))?);
+                }
+                if let Some(angle) = json.get("angle") {
+                    let degrees: f64 = self.number(angle, &join(key, "angle"))?;
+                    let axis: Vector3 = self.vector(self.required(json, key, "axis")?, &join(key, "axis"))?;
+                    if axis.length() == 0.0 {
+                        return Err(self.error(json.get("axis").unwrap(), &join(key, "axis"), "axis must not be zero".to_string()));
+                    }
+                    transform = transform.rotate(axis, degrees);
+                } else if json.get("axis").is_some() {
+                    self.required(json, key, "angle")?;
+                }
+                let translate: Vector3 = self.optional_vector(json, key, "translate", Vector3::new(0.0, 0.0, 0.0))?;
+                list.add(transform.translate(translate));
+            }
+            "constant_medium" => {
+                self.members(json, key, &["type", "boundary", "density", "material"])?;
+                let boundary: SharedHittable =
+                    self.shape(self.required(json, key, "boundary")?, &join(key, "boundary"), named)?;
+                let density_json: &Json = self.required(json, key, "density")?;
+                let density: f64 = self.number(density_json, &join(key, "density"))?;
+                if density <= 0.0 {
+                    return Err(self.error(density_json, &join(key, "density"), "must be positive".to_string()));
+                }
+                let material: Material =
+                    self.material(self.required(json, key, "material")?, &join(key, "material"), &named.materials)?;
+                list.add(ConstantMedium::new(boundary, density, material));
+            }
+            "grid_medium" => {
+                self.members(json, key, &["type", "file", "min", "max", "density", "material"])?;
+                let min: Vector3 = self.vector(self.required(json, key, "min")?, &join(key, "min"))?;
+                let max_json: &Json = self.required(json, key, "max")?;
+                let max: Vector3 = self.vector(max_json, &join(key, "max"))?;
+                if max.x() <= min.x() || max.y() <= min.y() || max.z() <= min.z() {
+                    return Err(self.error(max_json, &join(key, "max"), "must be above min on every axis".to_string()));
+                }
+                let density: f64 = self.optional_number(json, key, "density", 1.0)?;
+                if density < 0.0 {
+                    return Err(self.error(json.get("density").unwrap(), &join(key, "density"), "must not be negative".to_string()));
+                }
+                let material: Material =
+                    self.material(self.required(json, key, "material")?, &join(key, "material"), &named.materials)?;
+                let file: &str = self.string(self.required(json, key, "file")?, &join(key, "file"))?;
+                let grid: VoxelGrid = VoxelGrid::load(&self.base_dir.join(file))?;
+                list.add(GridMedium::new(Arc::new(grid), min, max, density, material));
+            }
+            "random_spheres" => {
+                self.members(json, key, &["type", "seed", "ground"])?;
+                let seed: u64 = self.optional_count(json, key, "seed", 0)? as u64;
+                let ground: Material = match json.get("ground") {
+                    Some(ground) => self.material(ground, &join(key, "ground"), &named.materials)?,
+                    None => Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5))),
+                };
+                random_spheres(list, seed, ground);
+            }
+            _ => {
+                return Err(self.error(
+                    json.get("type").unwrap(),
+                    &kind_key,
+                    format!("unknown object type '{}'", kind),
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+// The final scene of "Ray Tracing in One Weekend": a grid of small random spheres
+fn random_spheres(list: &mut HittableList, seed: u64, ground: Material) {
+    let mut rng = random::generator(seed);
+
+    list.add(Sphere::new(Vector3::new(0.0, -1000.0, 0.0), 1000.0, ground));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat: f64 = rng.gen::<f64>();
+            let center: Vector3 = Vector3::new(
+                a as f64 + 0.9 * rng.gen::<f64>(),
+                0.2,
+                b as f64 + 0.9 * rng.gen::<f64>(),
+            );
+            if (center - Vector3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                if choose_mat < 0.8 {
+                    // diffuse
+                    list.add(Sphere::new(
+                        center,
+                        0.2,
+                        Material::Lambertian(Lambertian::new(Vector3::new(
+                            rng.gen::<f64>() * rng.gen::<f64>(),
+                            rng.gen::<f64>() * rng.gen::<f64>(),
+                            rng.gen::<f64>() * rng.gen::<f64>(),
+                        ))),
+                    ));
+                } else if choose_mat < 0.95 {
+                    //metal
+                    list.add(Sphere::new(
+                        center,
+                        0.2,
+                        Material::Metal(Metal::new(
+                            Vector3::new(
+                                0.5 * (1.0 + rng.gen::<f64>()),
+                                0.5 * (1.0 + rng.gen::<f64>()),
+                                0.5 * (1.0 + rng.gen::<f64>()),
+                            ),
+                            0.5 * rng.gen::<f64>(),
+                        )),
+                    ));
+                } else {
+                    // dielectric
+                    list.add(Sphere::new(
+                        center,
+                        0.2,
+                        Material::Dielectric(Dielectric::new(1.5)),
+                    ));
+                }
+            }
+        }
+    }
+
+    list.add(Sphere::new(
+        Vector3::new(0.0, 1.0, 0.0),
+        1.0,
+        Material::Dielectric(Dielectric::new(1.5)),
+    ));
+    list.add(Sphere::new(
+        Vector3::new(-4.0, 1.0, 0.0),
+        1.0,
+        Material::Lambertian(Lambertian::new(Vector3::new(0.4, 0.4, 0.1))),
+    ));
+    list.add(Sphere::new(
+        Vector3::new(4.0, 1.0, 0.0),
+        1.0,
+        Material::Metal(Metal::new(Vector3::new(0.7, 0.6, 0.5), 0.0)),
+    ));
+}
+
+#[cfg(test)]
+const TEST_CAMERA: &str = r#""camera": { "lookfrom": [0, 0, 1], "lookat": [0, 0, -1], "vfov": 90 }"#;
+
+#[cfg(test)]
+fn scene_error(source: &str) -> String {
+    match parse_scene(source, "test.json", Path::new(".")) {
+        Ok(_) => panic!("expected an error"),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn test_parse_scene() {
+    let source: &str = r#"{
+        "render": { "width": 40, "height": 20, "samples": 4 },
+        "camera": { "lookfrom": [0, 0, 1], "lookat": [0, 0, -1], "vfov": 90 },
+        "materials": {
+            "red": { "type": "lambertian", "albedo": [0.8, 0.1, 0.1] },
+            "glass": { "type": "dielectric", "ri": 1.5 }
+        },
+        "objects": [
+            { "type": "sphere", "center": [0, 0, -1], "radius": 0.5, "material": "red" },
+            { "type": "list", "objects": [
+                { "type": "sphere", "center": [1, 0, -1], "radius": 0.5, "material": "glass" },
+                { "type": "mesh", "positions": [[0, 0, 0], [1, 0, 0], [0, 1, 0]], "indices": [0, 1, 2],
+                  "material": { "type": "metal", "albedo": [0.9, 0.9, 0.9] } }
+            ] }
+        ]
+    }"#;
+    let scene: Scene = parse_scene(source, "test.json", Path::new(".")).unwrap();
+    assert_eq!(scene.settings.width, 40);
+    assert_eq!(scene.settings.max_depth, 50);
+    assert_eq!(scene.world.size(), 2);
+    assert_eq!(scene.camera.shutter(), (0.0, 0.0));
+}
+
+#[test]
+fn test_parse_scene_errors() {
+    let camera: &str = TEST_CAMERA;
+    assert_eq!(
+        scene_error(&format!("{{ {},\n \"objects\": [\n {{ \"type\": \"sphere\", \"center\": [0, 0], \"radius\": 1, \"material\": \"x\" }} ] }}", camera)),
+        "test.json:3: objects[0].center: expected 3 components, found 2"
+    );
+    assert_eq!(
+        scene_error(&format!("{{ {},\n \"objects\": [\n {{ \"type\": \"sphere\", \"center\": [0, 0, 0], \"radius\": 1, \"material\": \"x\" }} ] }}", camera)),
+        "test.json:3: objects[0].material: unknown material 'x'"
+    );
+    assert_eq!(
+        scene_error("{ \"camera\": { \"lookfrom\": [0, 0, 1], \"lookat\": [0, 0, -1], \"vfov\": \"wide\" } }"),
+        "test.json:1: camera.vfov: expected a number, found a string"
+    );
+    for vfov in &["0", "180", "-30"] {
+        assert_eq!(
+            scene_error(&format!("{{ \"camera\": {{ \"lookfrom\": [0, 0, 1], \"lookat\": [0, 0, -1], \"vfov\": {} }} }}", vfov)),
+            "test.json:1: camera.vfov: must lie between 0 and 180"
+        );
+    }
+    assert_eq!(
+        scene_error("{ \"render\": { \"widht\": 10 } }"),
+        "test.json:1: render.widht: unknown key, expected one of: width, height, samples, max_depth, seed, threads"
+    );
+}
+
+#[test]
+fn test_parse_textures() {
+    let source: &str = r#"{
+        "camera": { "lookfrom": [0, 0, 1], "lookat": [0, 0, -1], "vfov": 90 },
+        "materials": {
+            "floor": { "type": "lambertian", "albedo": { "type": "checker", "scale": 2,
+                "odd": [0.2, 0.3, 0.1], "even": { "type": "constant", "color": [0.9, 0.9, 0.9] } } },
+            "stone": { "type": "metal", "albedo": { "type": "marble", "seed": 2, "octaves": 5 } },
+            "cells": { "type": "lambertian", "albedo": { "type": "worley", "scale": 3, "near": [1, 0, 0] } }
+        },
+        "objects": [
+            { "type": "sphere", "center": [0, -100.5, -1], "radius": 100, "material": "floor" },
+            { "type": "sphere", "center": [0, 0, -1], "radius": 0.5, "material": "stone" },
+            { "type": "sphere", "center": [1, 0, -1], "radius": 0.5, "material": "cells" }
+        ]
+    }"#;
+    let scene: Scene = parse_scene(source, "test.json", Path::new(".")).unwrap();
+    assert_eq!(scene.world.size(), 3);
+}
+
+#[test]
+fn test_parse_textures_errors() {
+    let textured = |texture: &str| -> String {
+        scene_error(&format!(
+            "{{ {},\n \"materials\": {{ \"m\": {{ \"type\": \"lambertian\",\n \"albedo\": {} }} }} }}",
+            TEST_CAMERA, texture
+        ))
+    };
+    assert_eq!(
+        textured(r#"{ "type": "image", "file": "wood.png", "wrap": "tile" }"#),
+        "test.json:3: materials.m.albedo.wrap: unknown wrap mode 'tile', expected repeat, clamp or mirror"
+    );
+    assert!(textured(r#"{ "type": "image", "file": "missing.png" }"#).starts_with("./missing.png: "));
+    assert_eq!(
+        textured(r#"{ "type": "wood", "rings": 12 }"#),
+        "test.json:3: materials.m.albedo.rings: unknown key, expected one of: type, seed, scale, octaves, strength, light, dark"
+    );
+}
+
+#[test]
+fn test_parse_shapes() {
+    let source: &str = r#"{
+        "camera": { "lookfrom": [0, 0, 1], "lookat": [0, 0, -1], "vfov": 90, "shutter": [0, 0.5] },
+        "materials": {
+            "red": { "type": "lambertian", "albedo": [0.8, 0.1, 0.1] }
+        },
+        "shapes": {
+            "pebble": { "type": "sphere", "center": [0, 0, 0], "radius": 0.1, "material": "red" }
+        },
+        "objects": [
+            { "type": "quad", "origin": [0, 0, -2], "u": [1, 0, 0], "v": [0, 1, 0], "material": "red", "flip": true },
+            { "type": "xz_rect", "x": [-1, 1], "z": [-3, -2], "k": 1, "material": "red" },
+            { "type": "box", "min": [-1, -0.5, -3], "max": [-0.5, 0, -2.5], "material": "red" },
+            { "type": "transform", "object": "pebble", "scale": [2, 1, 1], "translate": [0.5, -0.4, -0.5] },
+            { "type": "transform", "object": "pebble", "rotate": [0, 45, 0], "axis": [0, 1, 0], "angle": 10,
+              "translate": [-0.5, -0.4, -0.5] },
+            { "type": "moving_sphere", "center0": [0, 1, -1], "center1": [0, 1.2, -1], "radius": 0.2, "material": "red" },
+            { "type": "moving_sphere", "keyframes": [{ "time": 0, "center": [1, 1, -1] }, { "time": 0.5, "center": [1, 1, -2] }],
+              "radius": 0.2, "material": "red" }
+        ]
+    }"#;
+    let scene: Scene = parse_scene(source, "test.json", Path::new(".")).unwrap();
+    assert_eq!(scene.world.size(), 7);
+    assert_eq!(scene.camera.shutter(), (0.0, 0.5));
+}
+
+#[test]
+fn test_parse_shapes_errors() {
+    let camera: &str = TEST_CAMERA;
+    assert_eq!(
+        scene_error(&format!("{{ {},\n \"objects\": [\n {{ \"type\": \"yz_rect\", \"x\": [0, 1], \"z\": [0, 1], \"k\": 0, \"material\": \"x\" }} ] }}", camera)),
+        "test.json:3: objects[0].x: unknown key, expected one of: type, y, z, k, material, flip"
+    );
+    assert_eq!(
+        scene_error(&format!("{{ {},\n \"objects\": [\n {{ \"type\": \"quad\", \"origin\": [0, 0, 0], \"u\": [1, 0, 0], \"v\": [0, 1, 0],\n \"material\": {{ \"type\": \"dielectric\", \"ri\": 1.5 }}, \"flip\": 1 }} ] }}", camera)),
+        "test.json:4: objects[0].flip: expected a boolean, found a number"
+    );
+    assert_eq!(
+        scene_error(&format!("{{ {},\n \"objects\": [\n {{ \"type\": \"transform\", \"object\": \"rock\" }} ] }}", camera)),
+        "test.json:3: objects[0].object: unknown shape 'rock'"
+    );
+    assert_eq!(
+        scene_error(&format!("{{ {},\n \"shapes\": {{ \"s\": {{ \"type\": \"sphere\", \"center\": [0, 0, 0], \"radius\": 1, \"material\": {{ \"type\": \"dielectric\", \"ri\": 1.5 }} }} }},\n \"objects\": [ {{ \"type\": \"transform\", \"object\": \"s\", \"scale\": [1, 0, 1] }} ] }}", camera)),
+        "test.json:3: objects[0].scale: scale factors must not be zero"
+    );
+    assert_eq!(
+        scene_error("{ \"camera\": { \"lookfrom\": [0, 0, 1], \"lookat\": [0, 0, -1], \"vfov\": 90, \"shutter\": [1, 0] } }"),
+        "test.json:1: camera.shutter: closes before it opens"
+    );
+}
+
+#[test]
+fn test_parse_media() {
+    let source: &str = r#"{
+        "camera": { "lookfrom": [0, 0, 1], "lookat": [0, 0, -1], "vfov": 90 },
+        "shapes": {
+            "pebble": { "type": "sphere", "center": [0, 0, 0], "radius": 0.1,
+                "material": { "type": "dielectric", "ri": 1.5 } }
+        },
+        "objects": [
+            { "type": "constant_medium", "boundary": "pebble", "density": 2,
+              "material": { "type": "isotropic", "albedo": [0.9, 0.9, 0.9] } },
+            { "type": "grid_medium", "file": "scenes/cloud.grid", "min": [-1, -1, -3], "max": [1, 1, -2],
+              "material": { "type": "henyey_greenstein", "albedo": [0.9, 0.9, 0.9], "g": -0.3 } }
+        ]
+    }"#;
+    let scene: Scene = parse_scene(source, "test.json", Path::new(".")).unwrap();
+    assert_eq!(scene.world.size(), 2);
+}
+
+#[test]
+fn test_parse_media_errors() {
+    let camera: &str = TEST_CAMERA;
+    assert_eq!(
+        scene_error(&format!("{{ {},\n \"objects\": [ {{ \"type\": \"constant_medium\", \"boundary\": \"fog\", \"density\": 1, \"material\": \"m\" }} ] }}", camera)),
+        "test.json:2: objects[0].boundary: unknown shape 'fog'"
+    );
+    assert_eq!(
+        scene_error(&format!("{{ {},\n \"materials\": {{ \"m\": {{ \"type\": \"henyey_greenstein\", \"albedo\": [1, 1, 1], \"g\": 1 }} }} }}", camera)),
+        "test.json:2: materials.m.g: must lie strictly between -1 and 1"
+    );
+}
+
+#[test]
+fn test_parse_materials() {
+    let source: &str = r#"{
+        "camera": { "lookfrom": [0, 0, 1], "lookat": [0, 0, -1], "vfov": 90 },
+        "materials": {
+            "frosted": { "type": "dielectric", "ri": 1.5, "roughness": 0.2, "color": [0.6, 0.9, 0.7], "distance": 2 },
+            "car_paint": { "type": "principled", "base_color": [0.6, 0.05, 0.05], "metallic": 0.3, "roughness": 0.4,
+                "clearcoat": 1, "clearcoat_gloss": 0.9 },
+            "brushed": { "type": "conductor", "preset": "aluminium", "roughness": [0.2, 0.5] },
+            "custom": { "type": "conductor", "eta": [0.2, 0.9, 1.1], "k": [3.9, 2.4, 2.1], "roughness": 0.3 }
+        },
+        "objects": [
+            { "type": "sphere", "center": [-1, 1, -2], "radius": 0.3, "material": "brushed" },
+            { "type": "sphere", "center": [1, 1, -2], "radius": 0.3, "material": "custom" },
+            { "type": "sphere", "center": [0, 1, -2], "radius": 0.3, "material": "frosted" },
+            { "type": "sphere", "center": [0, 2, -2], "radius": 0.3, "material": "car_paint" }
+        ]
+    }"#;
+    let scene: Scene = parse_scene(source, "test.json", Path::new(".")).unwrap();
+    assert_eq!(scene.world.size(), 4);
+}
+
+#[test]
+fn test_parse_materials_errors() {
+    let camera: &str = TEST_CAMERA;
+    assert_eq!(
+        scene_error(&format!("{{ {},\n \"materials\": {{ \"m\": {{ \"type\": \"conductor\", \"preset\": \"tin\" }} }} }}", camera)),
+        "test.json:2: materials.m.preset: unknown metal 'tin', expected one of gold, copper, aluminium, silver"
+    );
+    assert_eq!(
+        scene_error(&format!("{{ {},\n \"materials\": {{ \"m\": {{ \"type\": \"dielectric\", \"ri\": 1.5,\n \"absorption\": [1, 0, 0], \"color\": [1, 1, 1] }} }} }}", camera)),
+        "test.json:3: materials.m.absorption: can't be combined with color"
+    );
+    assert_eq!(
+        scene_error(&format!("{{ {},\n \"materials\": {{ \"m\": {{ \"type\": \"dielectric\", \"ri\": 1.5, \"absorption\": [1, -0.5, 0] }} }} }}", camera)),
+        "test.json:2: materials.m.absorption: must not be negative"
+    );
+    for ri in &["0", "-1.5"] {
+        assert_eq!(
+            scene_error(&format!("{{ {},\n \"materials\": {{ \"m\": {{ \"type\": \"dielectric\", \"ri\": {} }} }} }}", camera, ri)),
+            "test.json:2: materials.m.ri: must be positive"
+        );
+    }
+    for material in &[
+        r#"{ "type": "dielectric", "ri": 1.5, "roughness": 1.5 }"#,
+        r#"{ "type": "conductor", "preset": "gold", "roughness": [0.2, -0.1] }"#,
+    ] {
+        assert_eq!(
+            scene_error(&format!("{{ {},\n \"materials\": {{ \"m\": {} }} }}", camera, material)),
+            "test.json:2: materials.m.roughness: must lie between 0 and 1"
+        );
+    }
+    for distance in &["0", "-2"] {
+        assert_eq!(
+            scene_error(&format!(
+                "{{ {},\n \"materials\": {{ \"m\": {{ \"type\": \"dielectric\", \"ri\": 1.5, \"color\": [1, 1, 1], \"distance\": {} }} }} }}",
+                camera, distance
+            )),
+            "test.json:2: materials.m.distance: must be positive"
+        );
+    }
+    assert_eq!(
+        scene_error(&format!("{{ {},\n \"materials\": {{ \"m\": {{ \"type\": \"principled\", \"base_color\": [1, 1, 1], \"sheen\": 2 }} }} }}", camera)),
+        "test.json:2: materials.m.sheen: must lie between 0 and 1"
+    );
+}
+
+#[test]
+fn test_parse_backgrounds() {
+    let background = |json: &str| -> SharedEnvironment {
+        let source: String = format!("{{ {}, \"background\": {} }}", TEST_CAMERA, json);
+        parse_scene(&source, "test.json", Path::new(".")).unwrap().background
+    };
+    assert!(!background("[0, 0, 0]").importance_sampled());
+    assert!(!background(r#"{ "type": "gradient", "bottom": [1, 1, 1], "top": [0.5, 0.7, 1] }"#).importance_sampled());
+    assert!(background(r#"{ "type": "image", "file": "scenes/sky.hdr", "rotation": 20 }"#).importance_sampled());
+    assert!(background(r#"{ "type": "sky", "elevation": 30, "turbidity": 3 }"#).importance_sampled());
+}
+
+#[test]
+fn test_parse_backgrounds_errors() {
+    let camera: &str = TEST_CAMERA;
+    assert_eq!(
+        scene_error(&format!("{{ {},\n \"background\": {{ \"type\": \"image\", \"file\": \"sky.hdr\", \"intensity\": -1 }} }}", camera)),
+        "test.json:2: background.intensity: must not be negative"
+    );
+    assert_eq!(
+        scene_error(&format!("{{ {},\n \"background\": {{ \"type\": \"sky\", \"elevation\": 30, \"turbidity\": 12 }} }}", camera)),
+        "test.json:2: background.turbidity: must lie between 1.7 and 10"
+    );
+    assert!(scene_error(&format!("{{ {}, \"background\": {{ \"type\": \"image\", \"file\": \"missing.hdr\" }} }}", camera))
+        .starts_with("./missing.hdr: "));
+}
+
+#[test]
+fn test_parse_lights() {
+    let source: &str = r#"{
+        "camera": { "lookfrom": [0, 0, 1], "lookat": [0, 0, -1], "vfov": 90 },
+        "objects": [
+            { "type": "sphere", "center": [0, 0, -1], "radius": 0.5,
+              "material": { "type": "lambertian", "albedo": [0.5, 0.5, 0.5] } }
+        ],
+        "lights": [
+            { "type": "xz_rect", "x": [-1, 1], "z": [-2, -1], "k": 3, "flip": true,
+              "material": { "type": "diffuse_light", "emit": [4, 4, 4] } },
+            { "type": "point", "position": [0, 2, 0], "intensity": [10, 10, 10], "radius": 0.1 },
+            { "type": "point", "position": [1, 2, 0], "intensity": [10, 10, 10], "ies": "scenes/ies/quadrant.ies",
+              "aim": [0, -1, 0.2] },
+            { "type": "spot", "position": [0, 2, -1], "direction": [0, -1, 0], "intensity": [20, 20, 20],
+              "angle": 30, "falloff": 5, "ies": "scenes/ies/downlight.ies" },
+            { "type": "directional", "direction": [1, -1, 0], "irradiance": [2, 2, 2] }
+        ]
+    }"#;
+    let scene: Scene = parse_scene(source, "test.json", Path::new(".")).unwrap();
+    // Area lights join the world, punctual ones stay out of it
+    assert_eq!(scene.world.size(), 2);
+    assert_eq!(scene.lights.size(), 1);
+    assert_eq!(scene.punctual_lights.size(), 4);
+}
+
+#[test]
+fn test_parse_lights_errors() {
+    let camera: &str = TEST_CAMERA;
+    assert_eq!(
+        scene_error(&format!(
+            "{{ {},\n \"lights\": [ {{ \"type\": \"spot\", \"position\": [0, 1, 0], \"direction\": [0, -1, 0],\n \"intensity\": [1, 1, 1], \"angle\": 0 }} ] }}",
+            camera
+        )),
+        "test.json:3: lights[0].angle: must lie between 0 and 180"
+    );
+    assert_eq!(
+        scene_error(&format!(
+            "{{ {},\n \"lights\": [ {{ \"type\": \"point\", \"position\": [0, 1, 0], \"intensity\": [1, 1, 1],\n \"aim\": [0, 0, 1] }} ] }}",
+            camera
+        )),
+        "test.json:3: lights[0].aim: only applies with ies"
+    );
+    assert_eq!(
+        scene_error(&format!(
+            "{{ {},\n \"lights\": [ {{ \"type\": \"point\", \"position\": [0, 1, 0], \"intensity\": [1, 1, 1],\n \"ies\": \"scenes/ies/downlight.ies\", \"aim\": [0, 0, 0] }} ] }}",
+            camera
+        )),
+        "test.json:3: lights[0].aim: must not be zero"
+    );
+    assert_eq!(
+        scene_error(&format!(
+            "{{ {},\n \"lights\": [ {{ \"type\": \"spot\", \"position\": [0, 1, 0], \"direction\": [0, 0, 0],\n \"intensity\": [1, 1, 1], \"angle\": 30 }} ] }}",
+            camera
+        )),
+        "test.json:2: lights[0].direction: must not be zero"
+    );
+    assert_eq!(
+        scene_error(&format!("{{ {},\n \"lights\": [ {{ \"type\": \"directional\", \"direction\": [0, 0, 0], \"irradiance\": [1, 1, 1] }} ] }}", camera)),
+        "test.json:2: lights[0].direction: must not be zero"
+    );
+    assert!(scene_error(&format!(
+        "{{ {}, \"lights\": [ {{ \"type\": \"point\", \"position\": [0, 1, 0], \"intensity\": [1, 1, 1], \"ies\": \"missing.ies\" }} ] }}",
+        camera
+    ))
+    .starts_with("./missing.ies: "));
+}
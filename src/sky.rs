@@ -0,0 +1,313 @@
+use std::f64::consts::PI;
+
+use environment::{luminance, uv_to_direction, Environment, SphericalDistribution};
+use onb::Onb;
+use random;
+use vector::Vector3;
+
+// Angular radius of the sun seen from the ground
+pub const SUN_RADIUS: f64 = 0.004_65;
+
+// Luminance of the sun outside the atmosphere in kcd/m^2, the unit of the sky model
+const SUN_LUMINANCE: f64 = 1.96e6;
+
+// kcd/m^2 to render units: a white surface under a high sun comes out near 1
+const SCALE: f64 = 1.0 / 40.0;
+
+// Resolution of the table the sky is importance sampled with
+const TABLE_WIDTH: usize = 64;
+const TABLE_HEIGHT: usize = 32;
+
+// Coefficients A to E of the Perez distribution, linear in turbidity
+#[derive(Clone, Copy, Debug)]
+struct Perez([f64; 5]);
+
+impl Perez {
+    fn new(turbidity: f64, slope: [f64; 5], offset: [f64; 5]) -> Perez {
+        let mut c: [f64; 5] = [0.0; 5];
+        for (c, (slope, offset)) in c.iter_mut().zip(slope.iter().zip(&offset)) {
+            *c = slope * turbidity + offset;
+        }
+        Perez(c)
+    }
+
+    // Relative distribution for a view at theta from the zenith and gamma from the sun
+    fn value(&self, theta: f64, gamma: f64) -> f64 {
+        let [a, b, c, d, e]: [f64; 5] = self.0;
+        let cos_theta: f64 = theta.cos().max(1e-3);
+        (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+    }
+}
+
+// Clear sky daylight after Preetham, Shirley and Smits, "A Practical Analytic
+// Model for Daylight" (1999), with the sun as a disk of the right angular size.
+// Below the horizon is a diffuse ground lit by both.
+#[derive(Clone, Debug)]
+pub struct Sky {
+    sun_direction: Vector3,
+    turbidity: f64,
+    ground_albedo: Vector3,
+    intensity: f64,
+    // Perez distributions of luminance Y and chromaticity x and y
+    perez: [Perez; 3],
+    // Zenith values of Y, x and y
+    zenith: [f64; 3],
+    sun_radiance: Vector3,
+    ground_radiance: Vector3,
+    // Chance of sampling the sun disk rather than the table
+    sun_chance: f64,
+    distribution: SphericalDistribution,
+}
+
+impl Sky {
+    // Sun elevation above the horizon and azimuth about +y in degrees, an
+    // azimuth of 0 puts the sun towards +x and 90 towards +z
+    pub fn new(elevation: f64, azimuth: f64) -> Sky {
+        let (elevation, azimuth): (f64, f64) = (elevation.to_radians(), azimuth.to_radians());
+        let sun_direction: Vector3 = Vector3::new(
+            elevation.cos() * azimuth.cos(),
+            elevation.sin(),
+            elevation.cos() * azimuth.sin(),
+        );
+        Sky::from_direction(sun_direction, 3.0, Vector3::new(0.3, 0.3, 0.3))
+    }
+
+    // Turbidity is the haziness of the air, 2 for a very clear sky up to about 10
+    pub fn with_turbidity(self, turbidity: f64) -> Sky {
+        Sky::from_direction(self.sun_direction, turbidity, self.ground_albedo).with_intensity(self.intensity)
+    }
+
+    pub fn with_ground_albedo(self, albedo: Vector3) -> Sky {
+        Sky::from_direction(self.sun_direction, self.turbidity, albedo).with_intensity(self.intensity)
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Sky {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn sun_direction(&self) -> Vector3 {
+        self.sun_direction
+    }
+
+    fn from_direction(sun_direction: Vector3, turbidity: f64, ground_albedo: Vector3) -> Sky {
+        let t: f64 = turbidity;
+        let perez: [Perez; 3] = [
+            Perez::new(t, [0.1787, -0.3554, -0.0227, 0.1206, -0.0670], [-1.4630, 0.4275, 5.3251, -2.5771, 0.3703]),
+            Perez::new(t, [-0.0193, -0.0665, -0.0004, -0.0641, -0.0033], [-0.2592, 0.0008, 0.2125, -0.8989, 0.0452]),
+            Perez::new(t, [-0.0167, -0.0950, -0.0079, -0.0441, -0.0109], [-0.2608, 0.0092, 0.2102, -1.6537, 0.0529]),
+        ];
+        // The model only covers the sun above the horizon, after sunset the
+        // sky keeps its twilight colors
+        let theta_sun: f64 = sun_direction.y().clamp(0.0, 1.0).acos();
+        let chi: f64 = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_y: f64 = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let polynomial = |c: [f64; 4]| c[0] * theta_sun.powi(3) + c[1] * theta_sun.powi(2) + c[2] * theta_sun + c[3];
+        let zenith_x: f64 = t * t * polynomial([0.00166, -0.00375, 0.00209, 0.0])
+            + t * polynomial([-0.02903, 0.06377, -0.03202, 0.00394])
+            + polynomial([0.11693, -0.21196, 0.06052, 0.25886]);
+        let zenith_yc: f64 = t * t * polynomial([0.00275, -0.00610, 0.00317, 0.0])
+            + t * polynomial([-0.04214, 0.08970, -0.04153, 0.00516])
+            + polynomial([0.15346, -0.26756, 0.06670, 0.26688]);
+
+        let mut sky: Sky = Sky {
+            sun_direction,
+            turbidity,
+            ground_albedo,
+            intensity: 1.0,
+            perez,
+            zenith: [zenith_y, zenith_x, zenith_yc],
+            sun_radiance: sun_radiance(&sun_direction, turbidity),
+            ground_radiance: Vector3::new(0.0, 0.0, 0.0),
+            sun_chance: 0.0,
+            distribution: SphericalDistribution::new(&[1.0], 1, 1),
+        };
+
+        // Tabulate the sky at the center of every cell, it sets both the
+        // sampling density and the light reaching the ground
+        let cell_solid_angle =
+            |y: usize| 2.0 * PI * PI * (PI * (y as f64 + 0.5) / TABLE_HEIGHT as f64).sin() / (TABLE_WIDTH * TABLE_HEIGHT) as f64;
+        let mut table: Vec<Vector3> = Vec::with_capacity(TABLE_WIDTH * TABLE_HEIGHT);
+        let mut irradiance: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+        for y in 0..TABLE_HEIGHT / 2 {
+            for x in 0..TABLE_WIDTH {
+                let u: f64 = (x as f64 + 0.5) / TABLE_WIDTH as f64;
+                let direction: Vector3 = uv_to_direction(u, (y as f64 + 0.5) / TABLE_HEIGHT as f64);
+                let radiance: Vector3 = sky.sky_radiance(&direction);
+                irradiance = irradiance + radiance * (direction.y() * cell_solid_angle(y));
+                table.push(radiance);
+            }
+        }
+        let solid_angle: f64 = sun_solid_angle();
+        irradiance = irradiance + sky.sun_radiance * (solid_angle * sun_direction.y().max(0.0));
+        sky.ground_radiance = ground_albedo * irradiance / PI;
+        table.resize(TABLE_WIDTH * TABLE_HEIGHT, sky.ground_radiance);
+        let sky_power: f64 = table
+            .iter()
+            .enumerate()
+            .map(|(i, radiance)| luminance(radiance) * cell_solid_angle(i / TABLE_WIDTH))
+            .sum();
+        let weights: Vec<f64> = table.iter().map(luminance).collect();
+        sky.distribution = SphericalDistribution::new(&weights, TABLE_WIDTH, TABLE_HEIGHT);
+
+        // Keep sampling the sky for surfaces that face away from the sun
+        let sun_power: f64 = luminance(&sky.sun_radiance) * solid_angle;
+        if sun_power > 0.0 {
+            sky.sun_chance = (sun_power / (sun_power + sky_power)).min(0.75);
+        }
+        sky
+    }
+
+    // Perez model without the sun disk, valid at and above the horizon
+    fn sky_radiance(&self, direction: &Vector3) -> Vector3 {
+        let d: Vector3 = direction.unit_vector();
+        let theta: f64 = d.y().clamp(0.0, 1.0).acos();
+        let gamma: f64 = d.dot(&self.sun_direction).clamp(-1.0, 1.0).acos();
+        let theta_sun: f64 = self.sun_direction.y().clamp(0.0, 1.0).acos();
+        let value = |i: usize| self.zenith[i] * self.perez[i].value(theta, gamma) / self.perez[i].value(0.0, theta_sun);
+        xyy_to_rgb(value(1), value(2), value(0)) * SCALE
+    }
+
+    fn in_sun(&self, direction: &Vector3) -> bool {
+        direction.unit_vector().dot(&self.sun_direction) >= SUN_RADIUS.cos()
+    }
+}
+
+impl Environment for Sky {
+    fn radiance(&self, direction: &Vector3) -> Vector3 {
+        let radiance: Vector3 = if direction.y() < 0.0 {
+            self.ground_radiance
+        } else if self.in_sun(direction) {
+            self.sky_radiance(direction) + self.sun_radiance
+        } else {
+            self.sky_radiance(direction)
+        };
+        self.intensity * radiance
+    }
+
+    fn importance_sampled(&self) -> bool {
+        true
+    }
+
+    fn pdf_value(&self, direction: &Vector3) -> f64 {
+        let sun: f64 = if self.in_sun(direction) {
+            self.sun_chance / sun_solid_angle()
+        } else {
+            0.0
+        };
+        sun + (1.0 - self.sun_chance) * self.distribution.pdf_value(direction)
+    }
+
+    // Uniform over the cone the sun subtends
+    fn random(&self) -> Vector3 {
+        if random::random_f64() >= self.sun_chance {
+            return self.distribution.random();
+        }
+        let cos_theta: f64 = 1.0 - random::random_f64() * (1.0 - SUN_RADIUS.cos());
+        let sin_theta: f64 = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi: f64 = 2.0 * PI * random::random_f64();
+        Onb::build_from_w(&self.sun_direction).local(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+}
+
+fn sun_solid_angle() -> f64 {
+    2.0 * PI * (1.0 - SUN_RADIUS.cos())
+}
+
+// Sunlight after Rayleigh and aerosol scattering along the way through the
+// atmosphere, at wavelengths standing in for red, green and blue
+fn sun_radiance(sun_direction: &Vector3, turbidity: f64) -> Vector3 {
+    // Fully set at the horizon, partly visible disks aren't worth the trouble
+    if sun_direction.y() < 0.0 {
+        return Vector3::new(0.0, 0.0, 0.0);
+    }
+    let theta: f64 = sun_direction.y().clamp(0.0, 1.0).acos();
+    // Air mass relative to looking straight up, Kasten's formula
+    let mass: f64 = 1.0 / (theta.cos() + 0.15 * (93.885 - theta.to_degrees()).powf(-1.253));
+    let beta: f64 = 0.04608 * turbidity - 0.04586;
+    let transmittance = |lambda: f64| -> f64 {
+        let rayleigh: f64 = 0.008735 * lambda.powf(-4.08);
+        let aerosol: f64 = beta * lambda.powf(-1.3);
+        (-(rayleigh + aerosol) * mass).exp()
+    };
+    Vector3::new(transmittance(0.68), transmittance(0.55), transmittance(0.44)) * (SUN_LUMINANCE * SCALE)
+}
+
+// CIE xyY to linear sRGB, negative components from out of gamut colors are dropped
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Vector3 {
+    if y <= 0.0 {
+        return Vector3::new(0.0, 0.0, 0.0);
+    }
+    let cx: f64 = x / y * luminance;
+    let cz: f64 = (1.0 - x - y) / y * luminance;
+    Vector3::new(
+        (3.2406 * cx - 1.5372 * luminance - 0.4986 * cz).max(0.0),
+        (-0.9689 * cx + 1.8758 * luminance + 0.0415 * cz).max(0.0),
+        (0.0557 * cx - 0.2040 * luminance + 1.0570 * cz).max(0.0),
+    )
+}
+
+#[test]
+fn test_sky_radiance() {
+    let noon: Sky = Sky::new(70.0, 30.0);
+    let up: Vector3 = Vector3::new(0.0, 1.0, 0.0);
+    // The zenith reproduces the model's zenith luminance, and is blue
+    let zenith: Vector3 = noon.radiance(&up);
+    assert!((luminance(&zenith) / SCALE - noon.zenith[0]).abs() < 0.02 * noon.zenith[0]);
+    assert!(zenith.z() > zenith.x());
+    // The sun outshines the sky by orders of magnitude and reddens as it sets
+    let sun: Vector3 = noon.radiance(&noon.sun_direction());
+    assert!(luminance(&sun) > 1e4 * luminance(&zenith));
+    let evening: Sky = Sky::new(3.0, 30.0);
+    let low: Vector3 = evening.radiance(&evening.sun_direction());
+    assert!(low.x() / low.z() > 2.0 * sun.x() / sun.z());
+    // Hazier air makes a brighter, whiter sky
+    let hazy: Sky = Sky::new(70.0, 30.0).with_turbidity(8.0);
+    assert!(luminance(&hazy.radiance(&up)) > luminance(&zenith));
+    // Everything scales with intensity, the ground with its albedo
+    let down: Vector3 = Vector3::new(0.3, -1.0, 0.0);
+    let bright: Sky = Sky::new(70.0, 30.0).with_ground_albedo(Vector3::new(0.6, 0.6, 0.6)).with_intensity(2.0);
+    assert!((bright.radiance(&down) - 4.0 * noon.radiance(&down)).length() < 1e-9);
+    assert!((bright.radiance(&up) - 2.0 * zenith).length() < 1e-9);
+    // At night only the twilight sky is left and the sun isn't sampled
+    let night: Sky = Sky::new(-10.0, 0.0);
+    assert_eq!(night.sun_radiance, Vector3::new(0.0, 0.0, 0.0));
+    assert_eq!(night.sun_chance, 0.0);
+}
+
+#[test]
+fn test_sky_sampling() {
+    // Irradiance of a tilted surface, by quadrature over the sky plus the sun
+    // disk against an importance sampled estimate
+    let sky: Sky = Sky::new(35.0, 120.0).with_turbidity(4.0);
+    let normal: Vector3 = Vector3::new(0.3, 0.8, 0.5).unit_vector();
+    let steps: usize = 400;
+    let mut expected: f64 = 0.0;
+    for i in 0..steps {
+        for j in 0..steps {
+            let cos_theta: f64 = 1.0 - 2.0 * (i as f64 + 0.5) / steps as f64;
+            let phi: f64 = 2.0 * PI * (j as f64 + 0.5) / steps as f64;
+            let sin_theta: f64 = (1.0 - cos_theta * cos_theta).sqrt();
+            let d: Vector3 = Vector3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+            let radiance: Vector3 = if d.y() < 0.0 { sky.ground_radiance } else { sky.sky_radiance(&d) };
+            expected += radiance.y() * d.dot(&normal).max(0.0) * 4.0 * PI / (steps * steps) as f64;
+        }
+    }
+    let sun_cos: f64 = sky.sun_direction().dot(&normal);
+    expected += sky.sun_radiance.y() * sun_solid_angle() * sun_cos;
+
+    random::seed(11);
+    let samples: usize = 40000;
+    let mut estimate: f64 = 0.0;
+    let mut sun_samples: usize = 0;
+    for _ in 0..samples {
+        let d: Vector3 = sky.random();
+        if sky.in_sun(&d) {
+            sun_samples += 1;
+        }
+        estimate += sky.radiance(&d).y() * d.dot(&normal).max(0.0) / sky.pdf_value(&d) / samples as f64;
+    }
+    let chance: f64 = sun_samples as f64 / samples as f64;
+    assert!((chance - sky.sun_chance).abs() < 0.01, "{} vs {}", chance, sky.sun_chance);
+    assert!((estimate - expected).abs() < 0.02 * expected, "{} vs {}", estimate, expected);
+}
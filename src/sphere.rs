@@ -1,9 +1,15 @@
+use std::f64::consts::PI;
+
+use aabb::Aabb;
+use onb::Onb;
+use random;
+use lambertian::random_unit_vector;
 use vector::Vector3;
 use ray::Ray;
 use hitable::{HitRecord, Hittable};
 use material::Material;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Sphere {
     pub center: Vector3,
     radius: f64,
@@ -15,38 +21,146 @@ impl Sphere {
         Sphere {
             center: cen,
             radius: r,
-            material: material,
+            material,
         }
     }
 }
 
+// Texture coordinates of a point on the unit sphere: u goes around the y axis
+// starting at -x, v from the south pole (0) to the north pole (1)
+pub fn sphere_uv(p: &Vector3) -> (f64, f64) {
+    let theta: f64 = (-p.y()).clamp(-1.0, 1.0).acos();
+    let phi: f64 = (-p.z()).atan2(p.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+// Shared by Sphere and MovingSphere, which passes its center at the ray's time
+pub fn hit_sphere(
+    center: &Vector3,
+    radius: f64,
+    material: &Material,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+    rec: &mut HitRecord,
+) -> bool {
+    let oc: Vector3 = ray.origin() - *center;
+    let a: f64 = ray.direction().dot(&ray.direction());
+    let b: f64 = 2.0 * oc.dot(&ray.direction());
+    let c: f64 = oc.dot(&oc) - radius * radius;
+    let discriminant: f64 = b * b - 4.0 * a * c;
+    if discriminant > 0.0 {
+        let t: f64 = (0.0 - b - discriminant.sqrt()) / (2.0 * a);
+        if t < t_max && t > t_min {
+            rec.t = t;
+            rec.p = ray.point_at_parameter(t);
+            // The length p - c would be the radius
+            rec.normal = (rec.p - *center)/radius;
+            let (u, v) = sphere_uv(&((rec.p - *center) / radius.abs()));
+            rec.u = u;
+            rec.v = v;
+            // v spans half the circumference
+            rec.footprint = ray.spread() * t * ray.direction().length() / (PI * radius.abs());
+            rec.material = material.clone();
+            return true;
+        }
+        let t: f64 = (0.0 - b + discriminant.sqrt()) / (2.0 * a);
+        if t < t_max && t > t_min {
+            rec.t = t;
+            rec.p = ray.point_at_parameter(t);
+            // The length p - c would be the radius
+            rec.normal = (rec.p - *center)/radius;
+            let (u, v) = sphere_uv(&((rec.p - *center) / radius.abs()));
+            rec.u = u;
+            rec.v = v;
+            // v spans half the circumference
+            rec.footprint = ray.spread() * t * ray.direction().length() / (PI * radius.abs());
+            rec.material = material.clone();
+            return true;
+        }
+    }
+    false
+}
+
 impl Hittable for Sphere {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
-        let oc: Vector3 = ray.origin() - self.center;
-        let a: f64 = ray.direction().dot(&ray.direction());
-        let b: f64 = 2.0 * oc.dot(&ray.direction());
-        let c: f64 = oc.dot(&oc) - self.radius * self.radius;
-        let discriminant: f64 = b * b - 4.0 * a * c;
-        if discriminant > 0.0 {
-            let t: f64 = (0.0 - b - discriminant.sqrt()) / (2.0 * a);
-            if t < t_max && t > t_min {
-                rec.t = t;
-                rec.p = ray.point_at_parameter(t);
-                // The length p - c would be the radius
-                rec.normal = (rec.p - self.center)/self.radius;
-                rec.material = self.material;
-                return true;
-            }
-            let t: f64 = (0.0 - b + discriminant.sqrt()) / (2.0 * a);
-            if t < t_max && t > t_min {
-                rec.t = t;
-                rec.p = ray.point_at_parameter(t);
-                // The length p - c would be the radius
-                rec.normal = (rec.p - self.center)/self.radius;
-                rec.material = self.material;
-                return true;
-            }
+        hit_sphere(&self.center, self.radius, &self.material, ray, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        // Hollow glass uses a negative radius, the extent is the same
+        let r: Vector3 = Vector3::new(self.radius.abs(), self.radius.abs(), self.radius.abs());
+        *output_box = Aabb::new(self.center - r, self.center + r);
+        true
+    }
+
+    fn pdf_value(&self, o: &Vector3, v: &Vector3) -> f64 {
+        let mut rec: HitRecord = HitRecord::new();
+        if !self.hit(&Ray::new(*o, *v), 0.001, f64::MAX, &mut rec) {
+            return 0.0;
         }
-        false
+        let distance_squared: f64 = (self.center - *o).norm();
+        let radius_squared: f64 = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            // From inside every direction hits, random() samples them uniformly
+            return 1.0 / (4.0 * PI);
+        }
+        let cos_theta_max: f64 = (1.0 - radius_squared / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    // Uniform over the cone of directions the sphere covers as seen from o
+    fn random(&self, o: &Vector3) -> Vector3 {
+        let direction: Vector3 = self.center - *o;
+        let distance_squared: f64 = direction.norm();
+        let radius_squared: f64 = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return random_unit_vector();
+        }
+        let r1: f64 = random::random_f64();
+        let r2: f64 = random::random_f64();
+        let cos_theta_max: f64 = (1.0 - radius_squared / distance_squared).sqrt();
+        let z: f64 = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi: f64 = 2.0 * PI * r1;
+        let sin_theta: f64 = (1.0 - z * z).sqrt();
+        Onb::build_from_w(&direction).local(phi.cos() * sin_theta, phi.sin() * sin_theta, z)
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_sphere_light_sampling() {
+    use lambertian::Lambertian;
+
+    let sphere: Sphere = Sphere::new(
+        Vector3::new(0.0, 0.0, -4.0),
+        1.0,
+        Material::Lambertian(Lambertian::new(Vector3::new(1.0, 1.0, 1.0))),
+    );
+    let origin: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+    let expected: f64 = 1.0 / (2.0 * PI * (1.0 - (15.0f64 / 16.0).sqrt()));
+    for _ in 0..1000 {
+        let v: Vector3 = sphere.random(&origin);
+        assert!((sphere.pdf_value(&origin, &v) - expected).abs() < 1e-9);
+    }
+    assert_eq!(sphere.pdf_value(&origin, &Vector3::new(0.0, 0.0, 1.0)), 0.0);
+}
+
+#[test]
+fn test_sphere_uv() {
+    use lambertian::Lambertian;
+
+    assert_eq!(sphere_uv(&Vector3::new(-1.0, 0.0, 0.0)), (0.0, 0.5));
+    assert_eq!(sphere_uv(&Vector3::new(1.0, 0.0, 0.0)), (0.5, 0.5));
+    assert_eq!(sphere_uv(&Vector3::new(0.0, 1.0, 0.0)).1, 1.0);
+    assert_eq!(sphere_uv(&Vector3::new(0.0, -1.0, 0.0)).1, 0.0);
+
+    let sphere: Sphere = Sphere::new(
+        Vector3::new(0.0, 0.0, -5.0),
+        2.0,
+        Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5))),
+    );
+    let mut rec: HitRecord = HitRecord::new();
+    let ray: Ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(sphere.hit(&ray, 0.001, f64::MAX, &mut rec));
+    assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+}
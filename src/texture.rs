@@ -0,0 +1,401 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use image_reader::{load_image, ImageError};
+use render::Framebuffer;
+use vector::Vector3;
+
+// A color that varies over a surface, looked up with the hit's texture
+// coordinates and its position in world space
+pub trait Texture: fmt::Debug {
+    fn value(&self, u: f64, v: f64, p: &Vector3) -> Vector3;
+
+    // Average over a footprint about width wide in texture space, textures
+    // without prefiltering just take a point sample
+    fn filtered(&self, u: f64, v: f64, p: &Vector3, _width: f64) -> Vector3 {
+        self.value(u, v, p)
+    }
+}
+
+pub type SharedTexture = Arc<dyn Texture + Send + Sync>;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ConstantTexture {
+    color: Vector3,
+}
+
+impl ConstantTexture {
+    pub fn new(color: Vector3) -> ConstantTexture {
+        ConstantTexture { color }
+    }
+}
+
+impl Texture for ConstantTexture {
+    fn value(&self, _u: f64, _v: f64, _p: &Vector3) -> Vector3 {
+        self.color
+    }
+}
+
+// Solid checker pattern over space, scale is the number of cells per unit
+#[derive(Clone, Debug)]
+pub struct CheckerTexture {
+    odd: SharedTexture,
+    even: SharedTexture,
+    scale: f64,
+}
+
+impl CheckerTexture {
+    pub fn new(odd: SharedTexture, even: SharedTexture, scale: f64) -> CheckerTexture {
+        CheckerTexture { odd, even, scale }
+    }
+}
+
+impl CheckerTexture {
+    fn cell(&self, p: &Vector3) -> &SharedTexture {
+        let cell: i64 = (self.scale * p.x()).floor() as i64
+            + (self.scale * p.y()).floor() as i64
+            + (self.scale * p.z()).floor() as i64;
+        if cell % 2 == 0 {
+            &self.even
+        } else {
+            &self.odd
+        }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Vector3) -> Vector3 {
+        self.cell(p).value(u, v, p)
+    }
+
+    fn filtered(&self, u: f64, v: f64, p: &Vector3, width: f64) -> Vector3 {
+        self.cell(p).filtered(u, v, p, width)
+    }
+}
+
+// Checker pattern in texture space with the given number of cells along u and v
+#[derive(Clone, Debug)]
+pub struct UvCheckerTexture {
+    odd: SharedTexture,
+    even: SharedTexture,
+    columns: f64,
+    rows: f64,
+}
+
+impl UvCheckerTexture {
+    pub fn new(odd: SharedTexture, even: SharedTexture, columns: f64, rows: f64) -> UvCheckerTexture {
+        UvCheckerTexture {
+            odd,
+            even,
+            columns,
+            rows,
+        }
+    }
+}
+
+impl UvCheckerTexture {
+    fn cell(&self, u: f64, v: f64) -> &SharedTexture {
+        let cell: i64 = (u * self.columns).floor() as i64 + (v * self.rows).floor() as i64;
+        if cell % 2 == 0 {
+            &self.even
+        } else {
+            &self.odd
+        }
+    }
+}
+
+impl Texture for UvCheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Vector3) -> Vector3 {
+        self.cell(u, v).value(u, v, p)
+    }
+
+    fn filtered(&self, u: f64, v: f64, p: &Vector3, width: f64) -> Vector3 {
+        self.cell(u, v).filtered(u, v, p, width)
+    }
+}
+
+// Linear blend from start at v = 0 to end at v = 1
+#[derive(Clone, Copy, Debug)]
+pub struct GradientTexture {
+    start: Vector3,
+    end: Vector3,
+}
+
+impl GradientTexture {
+    pub fn new(start: Vector3, end: Vector3) -> GradientTexture {
+        GradientTexture { start, end }
+    }
+}
+
+impl Texture for GradientTexture {
+    fn value(&self, _u: f64, v: f64, _p: &Vector3) -> Vector3 {
+        let t: f64 = v.clamp(0.0, 1.0);
+        (1.0 - t) * self.start + t * self.end
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    pub fn from_name(name: &str) -> Option<WrapMode> {
+        match name {
+            "repeat" => Some(WrapMode::Repeat),
+            "clamp" => Some(WrapMode::Clamp),
+            "mirror" => Some(WrapMode::Mirror),
+            _ => None,
+        }
+    }
+
+    // Maps any texel index into 0..size
+    fn apply(&self, i: i64, size: usize) -> usize {
+        let n: i64 = size as i64;
+        match *self {
+            WrapMode::Repeat => i.rem_euclid(n) as usize,
+            WrapMode::Clamp => i.clamp(0, n - 1) as usize,
+            WrapMode::Mirror => {
+                let period: i64 = i.rem_euclid(2 * n);
+                (if period < n { period } else { 2 * n - 1 - period }) as usize
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    // Bilinear on the two mipmap levels closest to the footprint, blended
+    Trilinear,
+}
+
+impl Filter {
+    pub fn from_name(name: &str) -> Option<Filter> {
+        match name {
+            "nearest" => Some(Filter::Nearest),
+            "bilinear" => Some(Filter::Bilinear),
+            "trilinear" => Some(Filter::Trilinear),
+            _ => None,
+        }
+    }
+}
+
+// Linear colors in rows from the top of the image down
+#[derive(Clone)]
+struct MipLevel {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vector3>,
+}
+
+impl MipLevel {
+    // Box filters 2x2 blocks, odd sizes round up and reuse the last row or column
+    fn downsample(&self) -> MipLevel {
+        let width: usize = self.width.div_ceil(2);
+        let height: usize = self.height.div_ceil(2);
+        let mut pixels: Vec<Vector3> = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let (y0, y1) = (2 * y, (2 * y + 1).min(self.height - 1));
+            for x in 0..width {
+                let (x0, x1) = (2 * x, (2 * x + 1).min(self.width - 1));
+                let sum: Vector3 = self.pixels[y0 * self.width + x0]
+                    + self.pixels[y0 * self.width + x1]
+                    + self.pixels[y1 * self.width + x0]
+                    + self.pixels[y1 * self.width + x1];
+                pixels.push(0.25 * sum);
+            }
+        }
+        MipLevel { width, height, pixels }
+    }
+
+    fn texel(&self, x: i64, y: i64, wrap: WrapMode) -> Vector3 {
+        self.pixels[wrap.apply(y, self.height) * self.width + wrap.apply(x, self.width)]
+    }
+
+    fn nearest(&self, u: f64, v: f64, wrap: WrapMode) -> Vector3 {
+        let x: i64 = (u * self.width as f64).floor() as i64;
+        let y: i64 = ((1.0 - v) * self.height as f64).floor() as i64;
+        self.texel(x, y, wrap)
+    }
+
+    // Texel centers sit at half integers
+    fn bilinear(&self, u: f64, v: f64, wrap: WrapMode) -> Vector3 {
+        let s: f64 = u * self.width as f64 - 0.5;
+        let t: f64 = (1.0 - v) * self.height as f64 - 0.5;
+        let (x, y) = (s.floor(), t.floor());
+        let (fx, fy) = (s - x, t - y);
+        let (x, y) = (x as i64, y as i64);
+        let top: Vector3 = (1.0 - fx) * self.texel(x, y, wrap) + fx * self.texel(x + 1, y, wrap);
+        let bottom: Vector3 = (1.0 - fx) * self.texel(x, y + 1, wrap) + fx * self.texel(x + 1, y + 1, wrap);
+        (1.0 - fy) * top + fy * bottom
+    }
+}
+
+// (0, 0) is the bottom left corner of the image in texture space. The
+// mipmap pyramid is built up front, down to a single texel.
+#[derive(Clone)]
+pub struct ImageTexture {
+    levels: Vec<MipLevel>,
+    wrap: WrapMode,
+    filter: Filter,
+}
+
+impl ImageTexture {
+    // pixels are linear colors in rows from the top of the image down
+    pub fn new(width: usize, height: usize, pixels: Vec<Vector3>) -> ImageTexture {
+        assert_eq!(pixels.len(), width * height, "image size doesn't match its pixels");
+        assert!(width > 0 && height > 0, "image textures can't be empty");
+        let mut levels: Vec<MipLevel> = vec![MipLevel { width, height, pixels }];
+        while levels.last().is_some_and(|l| l.width > 1 || l.height > 1) {
+            let next: MipLevel = levels.last().unwrap().downsample();
+            levels.push(next);
+        }
+        ImageTexture {
+            levels,
+            wrap: WrapMode::Repeat,
+            filter: Filter::Trilinear,
+        }
+    }
+
+    pub fn from_image(image: &Framebuffer) -> ImageTexture {
+        ImageTexture::new(image.width(), image.height(), image.pixels().to_vec())
+    }
+
+    pub fn load(path: &Path) -> Result<ImageTexture, ImageError> {
+        Ok(ImageTexture::from_image(&load_image(path)?))
+    }
+
+    pub fn with_wrap(mut self, wrap: WrapMode) -> ImageTexture {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> ImageTexture {
+        self.filter = filter;
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> usize {
+        self.levels[0].height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Vector3 {
+        self.levels[0].pixels[y * self.width() + x]
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+}
+
+impl fmt::Debug for ImageTexture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ImageTexture({}x{}, {:?}, {:?})", self.width(), self.height(), self.wrap, self.filter)
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, p: &Vector3) -> Vector3 {
+        self.filtered(u, v, p, 0.0)
+    }
+
+    fn filtered(&self, u: f64, v: f64, _p: &Vector3, width: f64) -> Vector3 {
+        match self.filter {
+            Filter::Nearest => self.levels[0].nearest(u, v, self.wrap),
+            Filter::Bilinear => self.levels[0].bilinear(u, v, self.wrap),
+            Filter::Trilinear => {
+                // Level whose texels are about as wide as the footprint
+                let texels: f64 = width * self.width().max(self.height()) as f64;
+                let lod: f64 = if texels > 1.0 { texels.log2() } else { 0.0 };
+                let last: usize = self.levels.len() - 1;
+                let level: usize = (lod.floor() as usize).min(last);
+                let fine: Vector3 = self.levels[level].bilinear(u, v, self.wrap);
+                if level == last {
+                    return fine;
+                }
+                let t: f64 = lod - level as f64;
+                let coarse: Vector3 = self.levels[level + 1].bilinear(u, v, self.wrap);
+                (1.0 - t) * fine + t * coarse
+            }
+        }
+    }
+}
+
+pub fn constant(color: Vector3) -> SharedTexture {
+    Arc::new(ConstantTexture::new(color))
+}
+
+#[test]
+fn test_checker() {
+    let black: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+    let white: Vector3 = Vector3::new(1.0, 1.0, 1.0);
+    let solid: CheckerTexture = CheckerTexture::new(constant(black), constant(white), 1.0);
+    assert_eq!(solid.value(0.0, 0.0, &Vector3::new(0.5, 0.5, 0.5)), white);
+    assert_eq!(solid.value(0.0, 0.0, &Vector3::new(1.5, 0.5, 0.5)), black);
+    assert_eq!(solid.value(0.0, 0.0, &Vector3::new(-0.5, 0.5, 0.5)), black);
+
+    let uv: UvCheckerTexture = UvCheckerTexture::new(constant(black), constant(white), 4.0, 2.0);
+    assert_eq!(uv.value(0.1, 0.1, &white), white);
+    assert_eq!(uv.value(0.3, 0.1, &white), black);
+    assert_eq!(uv.value(0.3, 0.6, &white), white);
+}
+
+#[test]
+fn test_image_texture() {
+    let red: Vector3 = Vector3::new(1.0, 0.0, 0.0);
+    let green: Vector3 = Vector3::new(0.0, 1.0, 0.0);
+    let blue: Vector3 = Vector3::new(0.0, 0.0, 1.0);
+    let white: Vector3 = Vector3::new(1.0, 1.0, 1.0);
+    let image: ImageTexture = ImageTexture::new(2, 2, vec![red, green, blue, white]);
+    let p: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+    assert_eq!(image.value(0.25, 0.75, &p), red);
+    assert_eq!(image.value(0.75, 0.75, &p), green);
+    assert_eq!(image.value(0.25, 0.25, &p), blue);
+    assert_eq!(image.clone().with_wrap(WrapMode::Clamp).value(1.0, 0.0, &p), white);
+
+    let gradient: GradientTexture = GradientTexture::new(red, blue);
+    assert_eq!(gradient.value(0.0, 0.5, &p), Vector3::new(0.5, 0.0, 0.5));
+}
+
+#[test]
+fn test_image_filtering() {
+    let black: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+    let white: Vector3 = Vector3::new(1.0, 1.0, 1.0);
+    let p: Vector3 = Vector3::new(0.0, 0.0, 0.0);
+
+    assert_eq!(WrapMode::Repeat.apply(-1, 4), 3);
+    assert_eq!(WrapMode::Clamp.apply(-1, 4), 0);
+    assert_eq!(WrapMode::Clamp.apply(9, 4), 3);
+    assert_eq!(WrapMode::Mirror.apply(-1, 4), 0);
+    assert_eq!(WrapMode::Mirror.apply(5, 4), 2);
+
+    // Between two texel centers bilinear filtering blends them evenly
+    let ramp: ImageTexture = ImageTexture::new(2, 1, vec![black, white]).with_filter(Filter::Bilinear);
+    assert_eq!(ramp.value(0.5, 0.5, &p), Vector3::new(0.5, 0.5, 0.5));
+    assert_eq!(ramp.clone().with_filter(Filter::Nearest).value(0.49, 0.5, &p), black);
+    assert_eq!(ramp.clone().with_wrap(WrapMode::Clamp).value(0.0, 0.5, &p), black);
+    assert_eq!(ramp.value(0.0, 0.5, &p), Vector3::new(0.5, 0.5, 0.5));
+
+    // A 5x3 pixel checkerboard: levels of 3x2, 2x1 and 1x1
+    let pixels: Vec<Vector3> = (0..15).map(|i| if i % 2 == 0 { white } else { black }).collect();
+    let checker: ImageTexture = ImageTexture::new(5, 3, pixels);
+    assert_eq!(checker.level_count(), 4);
+    assert_eq!(checker.value(0.1, 0.9, &p), white);
+    // A footprint covering the whole image reads the coarsest level
+    let average: Vector3 = checker.filtered(0.1, 0.9, &p, 1.0);
+    assert!(average.x > 0.3 && average.x < 0.7, "{:?}", average);
+    // Footprints in between blend neighbouring levels
+    let blend: Vector3 = checker.filtered(0.1, 0.9, &p, 1.5 / 5.0);
+    let fine: Vector3 = checker.filtered(0.1, 0.9, &p, 1.0 / 5.0);
+    let coarse: Vector3 = checker.filtered(0.1, 0.9, &p, 2.0 / 5.0);
+    assert!(((blend - 0.415 * fine - 0.585 * coarse).length()) < 0.01, "{:?}", blend);
+}
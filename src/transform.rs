@@ -0,0 +1,220 @@
+use aabb::Aabb;
+use hitable::{HitRecord, Hittable, SharedHittable};
+use matrix::Matrix4;
+use ray::Ray;
+use vector::Vector3;
+
+// Instance of shared geometry placed by an affine transform. Rays are moved
+// into object space, hits are moved back out.
+#[derive(Clone)]
+pub struct Transform {
+    object: SharedHittable,
+    // Object to world and world to object
+    matrix: Matrix4,
+    inverse: Matrix4,
+}
+
+impl Transform {
+    pub fn new(object: SharedHittable) -> Transform {
+        Transform {
+            object,
+            matrix: Matrix4::identity(),
+            inverse: Matrix4::identity(),
+        }
+    }
+
+    // None when the matrix can not be inverted
+    pub fn from_matrix(object: SharedHittable, matrix: Matrix4) -> Option<Transform> {
+        let inverse: Matrix4 = matrix.inverse()?;
+        Some(Transform {
+            object,
+            matrix,
+            inverse,
+        })
+    }
+
+    // Each builder applies after the transforms already set
+    pub fn translate(self, offset: Vector3) -> Transform {
+        self.then(Matrix4::translation(&offset), Matrix4::translation(&-offset))
+    }
+
+    pub fn rotate(self, axis: Vector3, degrees: f64) -> Transform {
+        let rotation: Matrix4 = Matrix4::rotation(&axis, degrees);
+        self.then(rotation, rotation.transpose())
+    }
+
+    pub fn rotate_euler(self, degrees: Vector3) -> Transform {
+        let rotation: Matrix4 = Matrix4::euler(&degrees);
+        self.then(rotation, rotation.transpose())
+    }
+
+    // Zero factors would collapse the object, they are not allowed
+    pub fn scale(self, factors: Vector3) -> Transform {
+        assert!(factors.x() != 0.0 && factors.y() != 0.0 && factors.z() != 0.0);
+        let inverse: Vector3 = Vector3::new(1.0 / factors.x(), 1.0 / factors.y(), 1.0 / factors.z());
+        self.then(Matrix4::scaling(&factors), Matrix4::scaling(&inverse))
+    }
+
+    fn then(mut self, matrix: Matrix4, inverse: Matrix4) -> Transform {
+        self.matrix = matrix * self.matrix;
+        self.inverse = self.inverse * inverse;
+        self
+    }
+
+    // The direction keeps its scale so t means the same in both spaces
+    fn local_ray(&self, ray: &Ray) -> Ray {
+        let mut local: Ray = *ray;
+        local.a = self.inverse.transform_point(&ray.origin());
+        local.b = self.inverse.transform_vector(&ray.direction());
+        local
+    }
+
+    fn to_world(&self, rec: &mut HitRecord) {
+        rec.p = self.matrix.transform_point(&rec.p);
+        // Normals follow the inverse transpose
+        rec.normal = self.inverse.transpose().transform_vector(&rec.normal).unit_vector();
+    }
+
+    pub fn matrix(&self) -> &Matrix4 {
+        &self.matrix
+    }
+
+    pub fn inverse(&self) -> &Matrix4 {
+        &self.inverse
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if !self.object.hit(&self.local_ray(ray), t_min, t_max, rec) {
+            return false;
+        }
+        self.to_world(rec);
+        true
+    }
+
+    fn hit_surface(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if !self.object.hit_surface(&self.local_ray(ray), t_min, t_max, rec) {
+            return false;
+        }
+        self.to_world(rec);
+        true
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.object.transmittance(&self.local_ray(ray), t_min, t_max)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        let mut local: Aabb = Aabb::empty();
+        if !self.object.bounding_box(&mut local) {
+            return false;
+        }
+        let mut result: Aabb = Aabb::empty();
+        for corner in 0..8 {
+            let p: Vector3 = Vector3::new(
+                if corner & 1 == 0 { local.min().x() } else { local.max().x() },
+                if corner & 2 == 0 { local.min().y() } else { local.max().y() },
+                if corner & 4 == 0 { local.min().z() } else { local.max().z() },
+            );
+            result = result.include_point(&self.matrix.transform_point(&p));
+        }
+        *output_box = result;
+        true
+    }
+
+    // The solid angle changes by |det M^-1| / |M^-1 v|^3 for a unit v
+    fn pdf_value(&self, o: &Vector3, v: &Vector3) -> f64 {
+        let local: Vector3 = self.inverse.transform_vector(&v.unit_vector());
+        let length: f64 = local.length();
+        let pdf: f64 = self.object.pdf_value(&self.inverse.transform_point(o), &local);
+        pdf * self.inverse.linear_determinant().abs() / (length * length * length)
+    }
+
+    fn random(&self, o: &Vector3) -> Vector3 {
+        let local: Vector3 = self.object.random(&self.inverse.transform_point(o));
+        self.matrix.transform_vector(&local)
+    }
+}
+
+#[test]
+fn test_transform() {
+    use std::sync::Arc;
+
+    use lambertian::Lambertian;
+    use material::Material;
+    use sphere::Sphere;
+
+    let material: Material = Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+    let sphere: SharedHittable = Arc::new(Sphere::new(Vector3::new(0.0, 0.0, 0.0), 1.0, material));
+    // An ellipsoid stretched along x, moved to x = 5
+    let ellipsoid: Transform = Transform::new(sphere.clone())
+        .scale(Vector3::new(2.0, 1.0, 1.0))
+        .rotate(Vector3::new(0.0, 1.0, 0.0), 90.0)
+        .translate(Vector3::new(5.0, 0.0, 0.0));
+    assert_eq!(Arc::strong_count(&sphere), 2);
+
+    // After the rotation the long axis lies along z
+    let mut rec: HitRecord = HitRecord::new();
+    let ray: Ray = Ray::new(Vector3::new(5.0, 0.0, 10.0), Vector3::new(0.0, 0.0, -2.0));
+    assert!(ellipsoid.hit(&ray, 0.001, f64::MAX, &mut rec));
+    assert!((rec.t - 4.0).abs() < 1e-9);
+    assert!((rec.p - Vector3::new(5.0, 0.0, 2.0)).length() < 1e-9);
+    assert!((rec.normal - Vector3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+
+    // Off-axis the normal comes from the inverse transpose, not the scaled local normal
+    let side: Ray = Ray::new(Vector3::new(5.5, 0.0, 10.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(ellipsoid.hit(&side, 0.001, f64::MAX, &mut rec));
+    let expected: Vector3 = Vector3::new(rec.p.x() - 5.0, rec.p.y(), rec.p.z() / 4.0).unit_vector();
+    assert!((rec.normal - expected).length() < 1e-9);
+
+    let mut bbox: Aabb = Aabb::empty();
+    assert!(ellipsoid.bounding_box(&mut bbox));
+    assert!((bbox.min() - Vector3::new(4.0, -1.0, -2.0)).length() < 1e-9);
+    assert!((bbox.max() - Vector3::new(6.0, 1.0, 2.0)).length() < 1e-9);
+
+    let matrix: Matrix4 = *ellipsoid.matrix();
+    assert!(Transform::from_matrix(sphere, matrix).is_some());
+}
+
+#[test]
+fn test_transform_light_sampling() {
+    use std::sync::Arc;
+
+    use lambertian::{random_unit_vector, Lambertian};
+    use material::Material;
+    use quad::Quad;
+    use random;
+
+    // Monte Carlo estimate of the integral of the pdf over its samples' solid angle:
+    // E[1/pdf] over samples equals the solid angle, compare against uniform sphere sampling
+    let material: Material = Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+    let quad: SharedHittable = Arc::new(Quad::new(
+        Vector3::new(-0.5, -0.5, 0.0),
+        Vector3::new(1.0, 0.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        material,
+    ));
+    let light: Transform = Transform::new(quad)
+        .scale(Vector3::new(3.0, 0.5, 1.0))
+        .rotate_euler(Vector3::new(20.0, 0.0, 35.0))
+        .translate(Vector3::new(0.0, 0.0, -2.0));
+    let origin: Vector3 = Vector3::new(0.1, 0.2, 0.0);
+
+    random::seed(7);
+    let samples: usize = 20000;
+    let mut from_light: f64 = 0.0;
+    for _ in 0..samples {
+        let v: Vector3 = light.random(&origin);
+        from_light += 1.0 / light.pdf_value(&origin, &v);
+    }
+    let mut uniform: f64 = 0.0;
+    for _ in 0..samples * 10 {
+        let v: Vector3 = random_unit_vector();
+        if light.pdf_value(&origin, &v) > 0.0 {
+            uniform += 4.0 * std::f64::consts::PI;
+        }
+    }
+    let (a, b): (f64, f64) = (from_light / samples as f64, uniform / (samples * 10) as f64);
+    assert!((a - b).abs() < 0.05 * b, "{} vs {}", a, b);
+}
@@ -0,0 +1,230 @@
+use aabb::Aabb;
+use hitable::{HitRecord, Hittable};
+use material::Material;
+use random;
+use ray::Ray;
+use vector::Vector3;
+
+const PARALLEL_EPSILON: f64 = 1e-12;
+// Flat triangles get a box this thick so the slab test never degenerates
+const BOX_PADDING: f64 = 1e-4;
+
+#[derive(Clone, Debug)]
+pub struct Triangle {
+    vertices: [Vector3; 3],
+    normals: Option<[Vector3; 3]>,
+    uvs: Option<[(f64, f64); 3]>,
+    material: Material,
+}
+
+impl Triangle {
+    pub fn new(v0: Vector3, v1: Vector3, v2: Vector3, material: Material) -> Triangle {
+        Triangle {
+            vertices: [v0, v1, v2],
+            normals: None,
+            uvs: None,
+            material,
+        }
+    }
+
+    pub fn with_normals(mut self, n0: Vector3, n1: Vector3, n2: Vector3) -> Triangle {
+        self.normals = Some([n0, n1, n2]);
+        self
+    }
+
+    pub fn with_uvs(mut self, uv0: (f64, f64), uv1: (f64, f64), uv2: (f64, f64)) -> Triangle {
+        self.uvs = Some([uv0, uv1, uv2]);
+        self
+    }
+}
+
+// Möller–Trumbore intersection, returns t and the barycentrics of v1 and v2
+pub fn intersect_triangle(
+    ray: &Ray,
+    v0: &Vector3,
+    v1: &Vector3,
+    v2: &Vector3,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, f64, f64)> {
+    let edge1: Vector3 = *v1 - *v0;
+    let edge2: Vector3 = *v2 - *v0;
+    let pvec: Vector3 = ray.direction().cross(&edge2);
+    let det: f64 = edge1.dot(&pvec);
+    if det.abs() < PARALLEL_EPSILON {
+        return None;
+    }
+    let inv_det: f64 = 1.0 / det;
+
+    let tvec: Vector3 = ray.origin() - *v0;
+    let b1: f64 = tvec.dot(&pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec: Vector3 = tvec.cross(&edge1);
+    let b2: f64 = ray.direction().dot(&qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t: f64 = edge2.dot(&qvec) * inv_det;
+    if t < t_max && t > t_min {
+        Some((t, b1, b2))
+    } else {
+        None
+    }
+}
+
+// Fills rec from a hit at barycentrics (b1, b2), interpolating the optional
+// per-vertex attributes; without normals the face normal follows the winding
+#[allow(clippy::too_many_arguments)]
+pub fn fill_triangle_record(
+    ray: &Ray,
+    t: f64,
+    b1: f64,
+    b2: f64,
+    vertices: &[Vector3; 3],
+    normals: Option<&[Vector3; 3]>,
+    uvs: Option<&[(f64, f64); 3]>,
+    material: &Material,
+    rec: &mut HitRecord,
+) {
+    let b0: f64 = 1.0 - b1 - b2;
+    rec.t = t;
+    rec.p = ray.point_at_parameter(t);
+    rec.normal = match normals {
+        Some(n) => (b0 * n[0] + b1 * n[1] + b2 * n[2]).unit_vector(),
+        None => (vertices[1] - vertices[0])
+            .cross(&(vertices[2] - vertices[0]))
+            .unit_vector(),
+    };
+    // Texture space area over world space area sets the footprint scale
+    let mut uv_area: f64 = 0.5;
+    match uvs {
+        Some(uv) => {
+            rec.u = b0 * uv[0].0 + b1 * uv[1].0 + b2 * uv[2].0;
+            rec.v = b0 * uv[0].1 + b1 * uv[1].1 + b2 * uv[2].1;
+            uv_area = ((uv[1].0 - uv[0].0) * (uv[2].1 - uv[0].1) - (uv[2].0 - uv[0].0) * (uv[1].1 - uv[0].1)).abs() / 2.0;
+        }
+        None => {
+            rec.u = b1;
+            rec.v = b2;
+        }
+    }
+    rec.footprint = 0.0;
+    if ray.spread() > 0.0 {
+        let area: f64 = (vertices[1] - vertices[0]).cross(&(vertices[2] - vertices[0])).length() / 2.0;
+        if area > 0.0 {
+            rec.footprint = ray.spread() * t * ray.direction().length() * (uv_area / area).sqrt();
+        }
+    }
+    rec.material = material.clone();
+}
+
+pub fn triangle_box(v0: &Vector3, v1: &Vector3, v2: &Vector3) -> Aabb {
+    let pad: Vector3 = Vector3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+    let bbox: Aabb = Aabb::new(*v0, *v0).include_point(v1).include_point(v2);
+    Aabb::new(bbox.min() - pad, bbox.max() + pad)
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let v: &[Vector3; 3] = &self.vertices;
+        match intersect_triangle(ray, &v[0], &v[1], &v[2], t_min, t_max) {
+            Some((t, b1, b2)) => {
+                fill_triangle_record(
+                    ray,
+                    t,
+                    b1,
+                    b2,
+                    v,
+                    self.normals.as_ref(),
+                    self.uvs.as_ref(),
+                    &self.material,
+                    rec,
+                );
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = triangle_box(&self.vertices[0], &self.vertices[1], &self.vertices[2]);
+        true
+    }
+
+    // Area sampling converted to solid angle: distance^2 / (cos * area)
+    fn pdf_value(&self, o: &Vector3, v: &Vector3) -> f64 {
+        let v0: &Vector3 = &self.vertices[0];
+        let cross: Vector3 = (self.vertices[1] - *v0).cross(&(self.vertices[2] - *v0));
+        let area: f64 = 0.5 * cross.length();
+        match intersect_triangle(&Ray::new(*o, *v), v0, &self.vertices[1], &self.vertices[2], 0.001, f64::MAX) {
+            Some((t, _, _)) if area > 0.0 => {
+                let distance_squared: f64 = t * t * v.norm();
+                let cosine: f64 = (v.dot(&cross) / (v.length() * cross.length())).abs();
+                if cosine <= 0.0 {
+                    0.0
+                } else {
+                    distance_squared / (cosine * area)
+                }
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn random(&self, o: &Vector3) -> Vector3 {
+        let mut b1: f64 = random::random_f64();
+        let mut b2: f64 = random::random_f64();
+        // Fold the upper half of the unit square back onto the triangle
+        if b1 + b2 > 1.0 {
+            b1 = 1.0 - b1;
+            b2 = 1.0 - b2;
+        }
+        let v: &[Vector3; 3] = &self.vertices;
+        let point: Vector3 = v[0] + b1 * (v[1] - v[0]) + b2 * (v[2] - v[0]);
+        point - *o
+    }
+}
+
+#[test]
+fn test_triangle_hit() {
+    use lambertian::Lambertian;
+
+    let material: Material = Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+    let triangle: Triangle = Triangle::new(
+        Vector3::new(0.0, 0.0, -2.0),
+        Vector3::new(1.0, 0.0, -2.0),
+        Vector3::new(0.0, 1.0, -2.0),
+        material,
+    )
+    .with_uvs((0.0, 0.0), (1.0, 0.0), (0.0, 1.0));
+    let mut rec: HitRecord = HitRecord::new();
+
+    let ray: Ray = Ray::new(Vector3::new(0.25, 0.5, 0.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(triangle.hit(&ray, 0.001, f64::MAX, &mut rec));
+    assert_eq!(rec.t, 2.0);
+    assert_eq!(rec.normal(), Vector3::new(0.0, 0.0, 1.0));
+    assert_eq!(rec.uv(), (0.25, 0.5));
+
+    let miss: Ray = Ray::new(Vector3::new(0.75, 0.5, 0.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(!triangle.hit(&miss, 0.001, f64::MAX, &mut rec));
+}
+
+#[test]
+fn test_triangle_normals() {
+    use lambertian::Lambertian;
+
+    let material: Material = Material::Lambertian(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+    let up: Vector3 = Vector3::new(0.0, 1.0, 0.0);
+    let triangle: Triangle = Triangle::new(
+        Vector3::new(-1.0, 0.0, -1.0),
+        Vector3::new(1.0, 0.0, -1.0),
+        Vector3::new(0.0, 0.0, 1.0),
+        material,
+    )
+    .with_normals(up, up, up);
+    let ray: Ray = Ray::new(Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+    let mut rec: HitRecord = HitRecord::new();
+    assert!(triangle.hit(&ray, 0.001, f64::MAX, &mut rec));
+    assert_eq!(rec.normal(), up);
+}
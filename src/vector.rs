@@ -31,8 +31,16 @@ impl Vector3 {
         self.z
     }
 
+    pub fn axis(&self, i: usize) -> f64 {
+        match i {
+            0 => self.x,
+            1 => self.y,
+            _ => self.z,
+        }
+    }
+
     pub fn norm(&self) -> f64 {
-        (self.x * self.x + self.y * self.y + self.z * self.z)
+        self.x * self.x + self.y * self.y + self.z * self.z
     }
 
     pub fn length(&self) -> f64 {
@@ -59,7 +67,7 @@ impl Vector3 {
         Vector3 { x, y, z }
     }
 
-    pub fn print(&self) -> () {
+    pub fn print(&self) {
         println!("{:?}", self);
     }
 }
@@ -144,8 +152,8 @@ impl Div<f64> for Vector3 {
     fn div(self, other: f64) -> Vector3 {
         if other == 0.0 {
             return Vector3 {
-                x: f64::MAX, 
-                y: f64::MAX, 
+                x: f64::MAX,
+                y: f64::MAX,
                 z: f64::MAX,
             };
         }
@@ -0,0 +1,203 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Dense voxel grid files hold a one line ASCII header
+//
+//     VOXELS <nx> <ny> <nz>\n
+//
+// followed by nx * ny * nz little-endian 32-bit floats, x varying fastest,
+// then y, then z. The grid fills the unit cube and voxel (i, j, k) is the
+// value at the center of its cell, ((i + 0.5) / nx, (j + 0.5) / ny, (k + 0.5) / nz).
+const MAGIC: &str = "VOXELS";
+
+#[derive(Debug)]
+pub enum VoxelError {
+    Io { file: String, error: io::Error },
+    Format { file: String, message: String },
+}
+
+impl fmt::Display for VoxelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VoxelError::Io { ref file, ref error } => write!(f, "{}: {}", file, error),
+            VoxelError::Format { ref file, ref message } => write!(f, "{}: {}", file, message),
+        }
+    }
+}
+
+impl Error for VoxelError {}
+
+#[derive(Clone, Debug)]
+pub struct VoxelGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    values: Vec<f32>,
+    max: f64,
+}
+
+impl VoxelGrid {
+    // Values must be finite and not negative
+    pub fn new(nx: usize, ny: usize, nz: usize, values: Vec<f32>) -> VoxelGrid {
+        assert!(nx > 0 && ny > 0 && nz > 0 && values.len() == nx * ny * nz);
+        assert!(values.iter().all(|v| v.is_finite() && *v >= 0.0));
+        let max: f64 = values.iter().fold(0.0f32, |a, &b| a.max(b)) as f64;
+        VoxelGrid { nx, ny, nz, values, max }
+    }
+
+    pub fn load(path: &Path) -> Result<VoxelGrid, VoxelError> {
+        let file: String = path.display().to_string();
+        let data: Vec<u8> = fs::read(path).map_err(|error| VoxelError::Io {
+            file: file.clone(),
+            error,
+        })?;
+        VoxelGrid::decode(&data, &file)
+    }
+
+    pub fn decode(data: &[u8], file: &str) -> Result<VoxelGrid, VoxelError> {
+        decode_grid(data).map_err(|message| VoxelError::Format {
+            file: file.to_string(),
+            message,
+        })
+    }
+
+    pub fn dimensions(&self) -> (usize, usize, usize) {
+        (self.nx, self.ny, self.nz)
+    }
+
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    pub fn voxel(&self, i: usize, j: usize, k: usize) -> f64 {
+        self.values[(k * self.ny + j) * self.nx + i] as f64
+    }
+
+    // Trilinear interpolation at a point of the unit cube, zero outside it
+    pub fn sample(&self, x: f64, y: f64, z: f64) -> f64 {
+        if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) || !(0.0..=1.0).contains(&z) {
+            return 0.0;
+        }
+        let (i0, i1, fx): (usize, usize, f64) = cell(x, self.nx);
+        let (j0, j1, fy): (usize, usize, f64) = cell(y, self.ny);
+        let (k0, k1, fz): (usize, usize, f64) = cell(z, self.nz);
+        let lerp = |a: f64, b: f64, t: f64| -> f64 { a + t * (b - a) };
+        let plane = |k: usize| -> f64 {
+            lerp(
+                lerp(self.voxel(i0, j0, k), self.voxel(i1, j0, k), fx),
+                lerp(self.voxel(i0, j1, k), self.voxel(i1, j1, k), fx),
+                fy,
+            )
+        };
+        lerp(plane(k0), plane(k1), fz)
+    }
+}
+
+// The two voxel centers around x along an axis of n voxels and the weight of
+// the second, clamped to the edge voxels
+fn cell(x: f64, n: usize) -> (usize, usize, f64) {
+    let position: f64 = (x * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+    let i0: usize = position as usize;
+    let i1: usize = (i0 + 1).min(n - 1);
+    (i0, i1, position - i0 as f64)
+}
+
+fn decode_grid(data: &[u8]) -> Result<VoxelGrid, String> {
+    let end: usize = data
+        .iter()
+        .take(256)
+        .position(|&b| b == b'\n')
+        .ok_or_else(|| format!("missing '{} <nx> <ny> <nz>' header line", MAGIC))?;
+    let header: &str = std::str::from_utf8(&data[..end]).map_err(|_| "header is not text".to_string())?;
+    let fields: Vec<&str> = header.split_whitespace().collect();
+    if fields.first() != Some(&MAGIC) {
+        return Err(format!("not a voxel grid, expected the header to start with '{}'", MAGIC));
+    }
+    if fields.len() != 4 {
+        return Err(format!("expected 3 dimensions in the header, found {}", fields.len() - 1));
+    }
+    let mut dimensions: [usize; 3] = [0; 3];
+    for (d, field) in dimensions.iter_mut().zip(&fields[1..]) {
+        *d = match field.parse::<usize>() {
+            Ok(n) if n > 0 => n,
+            _ => return Err(format!("invalid dimension '{}'", field)),
+        };
+    }
+    let count: usize = dimensions
+        .iter()
+        .try_fold(1usize, |acc, &d| acc.checked_mul(d))
+        .ok_or_else(|| "grid is too large".to_string())?;
+    let body: &[u8] = &data[end + 1..];
+    if count.checked_mul(4) != Some(body.len()) {
+        return Err(format!(
+            "expected {} bytes of voxel data for {}x{}x{}, found {}",
+            count.saturating_mul(4),
+            dimensions[0],
+            dimensions[1],
+            dimensions[2],
+            body.len()
+        ));
+    }
+    let mut values: Vec<f32> = Vec::with_capacity(count);
+    for (index, chunk) in body.chunks(4).enumerate() {
+        let value: f32 = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        if !value.is_finite() || value < 0.0 {
+            return Err(format!("voxel {} has invalid density {}", index, value));
+        }
+        values.push(value);
+    }
+    Ok(VoxelGrid::new(dimensions[0], dimensions[1], dimensions[2], values))
+}
+
+// Inverse of VoxelGrid::decode, used to write grids for tests and scenes
+pub fn encode_grid(grid: &VoxelGrid) -> Vec<u8> {
+    let mut data: Vec<u8> = format!("{} {} {} {}\n", MAGIC, grid.nx, grid.ny, grid.nz).into_bytes();
+    for value in &grid.values {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data
+}
+
+#[test]
+fn test_voxel_grid() {
+    // 2x1x2 grid, x fastest
+    let grid: VoxelGrid = VoxelGrid::new(2, 1, 2, vec![0.0, 1.0, 2.0, 4.0]);
+    let decoded: VoxelGrid = VoxelGrid::decode(&encode_grid(&grid), "grid").unwrap();
+    assert_eq!(decoded.dimensions(), (2, 1, 2));
+    assert_eq!(decoded.voxel(1, 0, 1), 4.0);
+    assert_eq!(decoded.max(), 4.0);
+
+    // Voxel centers are exact, in between is linear, the border clamps
+    assert_eq!(grid.sample(0.25, 0.5, 0.25), 0.0);
+    assert_eq!(grid.sample(0.75, 0.5, 0.75), 4.0);
+    assert_eq!(grid.sample(0.5, 0.5, 0.25), 0.5);
+    assert_eq!(grid.sample(0.5, 0.5, 0.5), 1.75);
+    assert_eq!(grid.sample(1.0, 0.0, 0.0), 1.0);
+    assert_eq!(grid.sample(1.1, 0.5, 0.5), 0.0);
+}
+
+#[test]
+fn test_voxel_grid_errors() {
+    let message = |data: &[u8]| -> String {
+        match VoxelGrid::decode(data, "smoke.grid") {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e.to_string(),
+        }
+    };
+    assert_eq!(
+        message(b"P6 2 2 2\n"),
+        "smoke.grid: not a voxel grid, expected the header to start with 'VOXELS'"
+    );
+    assert_eq!(message(b"VOXELS 2 2\n"), "smoke.grid: expected 3 dimensions in the header, found 2");
+    assert_eq!(message(b"VOXELS 2 0 2\n"), "smoke.grid: invalid dimension '0'");
+    assert_eq!(
+        message(b"VOXELS 1 1 2\n\0\0\0\0"),
+        "smoke.grid: expected 8 bytes of voxel data for 1x1x2, found 4"
+    );
+    let mut negative: Vec<u8> = b"VOXELS 1 1 1\n".to_vec();
+    negative.extend_from_slice(&(-1.0f32).to_le_bytes());
+    assert_eq!(message(&negative), "smoke.grid: voxel 0 has invalid density -1");
+}